@@ -1,4 +1,6 @@
+use crate::core::config::{Provider, SamplingPreset};
 use clap::{Parser, Subcommand};
+use std::path::PathBuf;
 
 #[derive(Parser)]
 #[command(
@@ -7,10 +9,41 @@ use clap::{Parser, Subcommand};
     long_about = "None"
 )]
 pub struct Args {
+    /// Disable colored output, also honors the NO_COLOR environment variable
+    #[arg(long, global = true)]
+    pub no_color: bool,
+
+    /// Output format of `run`, json prints a single object with the answer once the run is done,
+    /// jsonl prints every event as it happens, one object per line
+    #[arg(long, global = true, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+
+    /// Check whether the default local server is reachable when showing the splash screen
+    #[arg(long)]
+    pub check: bool,
+
     #[command(subcommand)]
     pub command: Option<Commands>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    Json,
+    Jsonl,
+}
+
+/// How piped stdin combines with `--context-file`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum PipedContext {
+    /// Piped input first, then the file
+    Prepend,
+    /// The file first, then the piped input
+    Append,
+    /// Only the piped input, the file is not read
+    Replace,
+}
+
 #[derive(Subcommand)]
 pub enum Commands {
     /// Initialize dotfiles and pre-configs
@@ -21,24 +54,265 @@ pub enum Commands {
     },
 
     /// Run the AI agent on a task
-    Run {
-        /// The task prompt (primary input).
-        task: Option<String>,
+    Run(RunArgs),
 
-        /// The image URL to provide as context/input for Vision-capable models
-        #[arg(short, long)]
-        image: Option<String>,
+    /// Chat with the agent interactively, takes the same flags as `run`
+    Chat(RunArgs),
 
-        /// The agent's high-level plan/goal
-        #[arg(short, long)]
-        plan: Option<String>,
+    /// Re-send the user turns of a saved session through the current config, into a new session
+    Replay {
+        /// Session to replay, it is left untouched
+        name: String,
+
+        /// Same flags as `run`, --session names the new session (defaults to `<name>-replay`)
+        #[command(flatten)]
+        run: RunArgs,
+    },
+
+    /// List the available configs and sessions
+    List {
+        /// Only list sessions
+        #[arg(long)]
+        sessions: bool,
+
+        /// Only list configs
+        #[arg(long)]
+        configs: bool,
+    },
 
-        ///Agent Config to use for the agent
+    /// List the models offered by the server of a config
+    Models {
+        /// Agent Config whose server to query
         #[arg(short, long)]
         config: String,
+    },
+
+    /// Manage saved sessions
+    Session {
+        #[command(subcommand)]
+        command: SessionCommands,
+    },
+
+    /// Manage agent configs
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ConfigCommands {
+    /// List config names
+    List,
+
+    /// Print a config, API keys are masked
+    Show {
+        /// Config name
+        name: String,
+    },
+
+    /// Delete a config
+    Delete {
+        /// Config name
+        name: String,
+
+        /// Don't ask for confirmation
+        #[arg(short, long)]
+        yes: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SessionCommands {
+    /// Delete a saved session
+    Delete {
+        /// Session name
+        name: String,
+    },
+
+    /// Rename a saved session
+    Rename {
+        /// Current session name
+        old: String,
+
+        /// New session name
+        new: String,
+    },
+
+    /// Show a session's message, tool call and token counts
+    Info {
+        /// Session name
+        name: String,
+    },
+
+    /// Export a session transcript as Markdown
+    Export {
+        /// Session name
+        name: String,
 
-        /// Session name to use for persistent memory/session
+        /// Markdown file to write, prints to stdout when omitted
         #[arg(short, long)]
-        session: Option<String>,
+        output: Option<PathBuf>,
     },
 }
+
+#[derive(clap::Args, Clone)]
+pub struct RunArgs {
+    /// The task prompt (primary input).
+    pub task: Option<String>,
+
+    /// The image to provide as context/input for Vision-capable models, repeat the flag for several images.
+    /// A file path, an http(s) URL or a data URL
+    #[arg(short, long)]
+    pub image: Vec<String>,
+
+    /// Read the context from a file instead of piped stdin
+    #[arg(long)]
+    pub context_file: Option<PathBuf>,
+
+    /// How piped input combines with --context-file, without it giving both is an error
+    #[arg(long, value_enum)]
+    pub piped_context: Option<PipedContext>,
+
+    /// The agent's high-level plan/goal
+    #[arg(short, long)]
+    pub plan: Option<String>,
+
+    ///Agent Config to use for the agent
+    #[arg(short, long)]
+    pub config: String,
+
+    /// Session name to use for persistent memory/session
+    #[arg(short, long)]
+    pub session: Option<String>,
+
+    /// Override the config's model for this run
+    #[arg(long)]
+    pub model: Option<String>,
+
+    /// Use a known provider's url and API key (from its environment variable) for this run
+    #[arg(long, value_enum)]
+    pub provider: Option<Provider>,
+
+    /// Override the config's server url for this run, wins over --provider
+    #[arg(long)]
+    pub url: Option<String>,
+
+    /// Override the config's sampling temperature for this run
+    #[arg(long)]
+    pub temperature: Option<f32>,
+
+    /// Override the config's top_p for this run
+    #[arg(long)]
+    pub top_p: Option<f32>,
+
+    /// Sampling preset applied over the config, --temperature and --top-p still win
+    #[arg(long, value_enum)]
+    pub preset: Option<SamplingPreset>,
+
+    /// Override the config's max_tokens for this run
+    #[arg(long)]
+    pub max_tokens: Option<u32>,
+
+    /// Replace the config's system prompt for this run
+    #[arg(long, conflicts_with = "system_file")]
+    pub system: Option<String>,
+
+    /// Replace the config's system prompt with the contents of a file for this run
+    #[arg(long)]
+    pub system_file: Option<PathBuf>,
+
+    /// Seconds to wait for the server to start answering (includes tool iterations)
+    #[arg(long, default_value_t = 300)]
+    pub timeout: u64,
+
+    /// Seconds to wait between two streamed chunks before giving up
+    #[arg(long, default_value_t = 60)]
+    pub idle_timeout: u64,
+
+    /// How many times to retry transient server failures (connection errors, 429, 5xx)
+    #[arg(long, default_value_t = 2)]
+    pub retries: u32,
+
+    /// Milliseconds between two printed characters of the answer, 0 disables the typewriter effect
+    #[arg(long, default_value_t = 10)]
+    pub typewriter_delay: u64,
+
+    /// Wait for the complete answer before printing it instead of streaming it live
+    #[arg(long)]
+    pub buffered: bool,
+
+    /// Wrap the answer at this many columns, defaults to the terminal width (80 when not a terminal)
+    #[arg(long)]
+    pub width: Option<usize>,
+
+    /// Wrap fenced code blocks like the rest of the answer instead of printing them verbatim
+    #[arg(long)]
+    pub wrap_code: bool,
+
+    /// Also write the answer to this file, `-` prints it to stdout in one piece without the typewriter effect
+    #[arg(long)]
+    pub save_output: Option<PathBuf>,
+
+    /// Append every request and response as JSON lines to this file (or set R_AGENT_LOG_FILE)
+    #[arg(long)]
+    pub log_file: Option<PathBuf>,
+
+    /// Only give the agent tools that inspect, nothing that runs code or changes files
+    #[arg(long)]
+    pub read_only: bool,
+
+    /// Run tools that need approval (cargo check runs build scripts) without asking
+    #[arg(short, long)]
+    pub yes: bool,
+
+    /// How many tools may run at the same time when the model asks for several at once
+    #[arg(long, default_value_t = 4)]
+    pub max_concurrent_tools: usize,
+
+    /// Stop the turn once the model repeats an identical tool call this many times, 0 never stops it
+    #[arg(long, default_value_t = 3)]
+    pub max_repeated_calls: usize,
+
+    /// Cut every tool result to this many chars before the model sees it
+    #[arg(long)]
+    pub max_tool_output: Option<usize>,
+
+    /// Print the request that would be sent (system prompt, history, tools) as JSON instead of sending it
+    #[arg(long)]
+    pub dry_run: bool,
+
+    /// Print the agent config the run would use, after overrides, presets and the API key lookup, then exit.
+    /// The API key is masked
+    #[arg(long)]
+    pub show_config: bool,
+
+    /// Request the whole answer without streaming and print it at once, no typewriter effect
+    #[arg(long)]
+    pub no_stream: bool,
+
+    /// Summarize the oldest session turns once the session holds more than this many messages
+    #[arg(long)]
+    pub compact_after: Option<usize>,
+
+    /// With `--format json`, add every tool call of the run (name, args and output) to the output
+    #[arg(long)]
+    pub include_tools: bool,
+
+    /// Don't print tool calls and results, only the answer
+    #[arg(short, long)]
+    pub quiet: bool,
+
+    /// Don't give the agent any tools, it answers from the prompt and context alone
+    #[arg(long)]
+    pub no_tools: bool,
+
+    /// Context size of the model in tokens, overrides the config's `context_window`.
+    /// Oversized requests are refused once a size is set, otherwise checked against 8192 with a warning
+    #[arg(long)]
+    pub context_size: Option<usize>,
+
+    /// Skip the `GET /models` check of the server, api key and model before sending the task
+    #[arg(long)]
+    pub no_preflight: bool,
+}
@@ -3,26 +3,45 @@ use clap::Parser;
 use colored::Colorize;
 use r_agent::args::{Args, Commands};
 use r_agent::cmd::ascii::run_ascii_art;
+use r_agent::cmd::chat::run_chat;
+use r_agent::cmd::config::run_config_command;
 use r_agent::cmd::init::run_init;
+use r_agent::cmd::list::run_list;
+use r_agent::cmd::models::run_models;
+use r_agent::cmd::replay::run_replay;
 use r_agent::cmd::run::{read_stdin, run_agent};
+use r_agent::cmd::session::run_session_command;
+use r_agent::core::tools::BackgroundProcessGuard;
+use tracing_subscriber::EnvFilter;
 
 #[tokio::main]
 pub async fn main() -> Result<()> {
-    let piped_input = read_stdin().await;
-
     let cli_args = Args::parse();
 
+    let color = color_enabled(cli_args.no_color);
+    if !color {
+        colored::control::set_override(false);
+    }
+
+    // Diagnostics go to stderr and are opt-in through RUST_LOG, e.g. RUST_LOG=r_agent=debug
+    tracing_subscriber::fmt()
+        .with_env_filter(EnvFilter::from_default_env())
+        .with_writer(std::io::stderr)
+        .with_ansi(color)
+        .init();
+
+    // Processes the agent started with background_run_tool end with ragent, errors included
+    let _background = BackgroundProcessGuard;
+
     match cli_args.command {
         Some(Commands::Init { fix }) => {
             run_init(fix).await?;
         }
-        Some(Commands::Run {
-            task,
-            image,
-            plan,
-            config,
-            session,
-        }) => {
+        Some(Commands::Run(run_args)) => {
+            let piped_input = read_stdin().await;
+
+            // --show-config stops before the task is used
+            let task = run_args.task.clone().or_else(|| run_args.show_config.then(String::new));
             let task_str = task.unwrap_or_else(|| {
                 eprintln!("{}", " Error: Task is required".to_string().red());
                 eprintln!(" Usage: ragent run {} --config {} --image {}",  "<TASK>".to_string().yellow() ,"<CONFIG>".to_string().yellow(), "<IMAGE_URL> OR <PATH>".to_string().yellow());
@@ -30,17 +49,41 @@ pub async fn main() -> Result<()> {
                 eprintln!("             {}", "↑ ↑ ↑ ↑ ↑ ↑ -> Sends as context from piped input".to_string().green());
                 std::process::exit(1);
             });
-            run_agent(&task_str, &plan, &image, &config, &session, &piped_input).await?;
+            run_agent(&task_str, &run_args, &piped_input, cli_args.format).await?;
+        }
+        Some(Commands::Chat(run_args)) => {
+            run_chat(&run_args).await?;
+        }
+        Some(Commands::Replay { name, run }) => {
+            run_replay(&name, &run).await?;
+        }
+        Some(Commands::List { sessions, configs }) => {
+            run_list(sessions, configs).await?;
+        }
+        Some(Commands::Models { config }) => {
+            run_models(&config).await?;
+        }
+        Some(Commands::Session { command }) => {
+            run_session_command(&command).await?;
+        }
+        Some(Commands::Config { command }) => {
+            run_config_command(&command).await?;
         }
 
         _ => {
-            run_ascii_art().await;
+            run_ascii_art(cli_args.check).await;
         }
     }
 
     Ok(())
 }
 
+/// Colors are off with `--no-color`, a non-empty `NO_COLOR` (https://no-color.org) or when stdout isn't a terminal
+fn color_enabled(no_color: bool) -> bool {
+    let no_color_env = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+    !no_color && !no_color_env && atty::is(atty::Stream::Stdout)
+}
+
 // fn borrow_checker() {
 //     let mut s = String::from("hello");
 //     let r1 = &s;
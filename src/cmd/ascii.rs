@@ -1,8 +1,10 @@
-use crate::core::config::get_default_config_path;
+use crate::core::config::{default_agents, get_default_config_path};
+use crate::core::models::{REACHABILITY_TIMEOUT, Reachability, check_reachability};
 use anyhow::Result;
 use colored::Colorize;
+use std::path::Path;
 
-pub async fn run_ascii_art() {
+pub async fn run_ascii_art(check: bool) {
     let ascii_art = r#"          
                                      ██   
     ████▄    ▀▀█▄ ▄████ ▄█▀█▄ ████▄ ▀██▀▀ 
@@ -13,6 +15,7 @@ pub async fn run_ascii_art() {
     "#;
 
     println!("{}\n", ascii_art.to_string().magenta());
+    println!(" Version: {}\n", version_string().cyan());
     let total_configs = get_total_configs().unwrap_or(0);
     println!(" Total Configs: {}\n", total_configs.to_string().cyan());
     let total_sessions = get_total_sessions().unwrap_or(0);
@@ -21,18 +24,62 @@ pub async fn run_ascii_art() {
         " Github: {}\n",
         "https://github.com/ronakgh97/r-agent".to_string().cyan()
     );
+
+    if check {
+        let (url, status) = default_server_status().await;
+        let status_str = match status {
+            Reachability::Up => status.to_string().green(),
+            Reachability::Down => status.to_string().red(),
+            Reachability::Unknown => status.to_string().yellow(),
+        };
+        println!(" Server: {} ({})\n", status_str, url);
+    }
+}
+
+fn version_string() -> String {
+    format!("v{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Pings the first default agent, which points at the local LM Studio server
+async fn default_server_status() -> (String, Reachability) {
+    let Some(agent) = default_agents().into_iter().next() else {
+        return ("-".to_string(), Reachability::Unknown);
+    };
+    let status = check_reachability(&agent.url, &agent.api_key, REACHABILITY_TIMEOUT).await;
+    (agent.url, status)
 }
 
 fn get_total_configs() -> Result<usize> {
-    let configs_dir = get_default_config_path()?;
-    let entries = std::fs::read_dir(configs_dir)?;
-    let count = entries.count();
-    Ok(count)
+    count_files(&get_default_config_path()?, "toml")
 }
 
 fn get_total_sessions() -> Result<usize> {
-    let sessions_dir = crate::core::session::get_default_session_path()?;
-    let entries = std::fs::read_dir(sessions_dir)?;
-    let count = entries.count();
+    count_files(&crate::core::session::get_default_session_path()?, "json")
+}
+
+/// Files with the given extension, backups and leftovers don't count
+fn count_files(dir: &Path, extension: &str) -> Result<usize> {
+    let count = std::fs::read_dir(dir)?
+        .flatten()
+        .filter(|entry| entry.path().extension().and_then(|e| e.to_str()) == Some(extension))
+        .count();
     Ok(count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_is_the_crate_version() {
+        let version = version_string();
+        assert_eq!(version, format!("v{}", env!("CARGO_PKG_VERSION")));
+        let parts: Vec<&str> = version[1..].split('.').collect();
+        assert_eq!(parts.len(), 3, "{}", version);
+        assert!(
+            parts.iter().all(|p| p.parse::<u32>().is_ok()),
+            "{}",
+            version
+        );
+    }
+}
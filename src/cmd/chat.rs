@@ -0,0 +1,214 @@
+use crate::args::RunArgs;
+use crate::cmd::run::{prepare_runner, resolve_context};
+use crate::core::runner::RunnerContext;
+use crate::core::session::Session;
+use crate::core::tools::tool_summary;
+use anyhow::Result;
+use chrono::Utc;
+use colored::Colorize;
+use forge::api::dtos::Message;
+use std::io::Write;
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, BufReader};
+use tokio_util::sync::CancellationToken;
+
+const HELP: &str = "/exit   Save and quit
+/reset  Forget the conversation (the saved session is kept until /save)
+/save   Save the session now
+/tools  List the tools the agent can use
+/help   Show this help";
+
+pub async fn run_chat(args: &RunArgs) -> Result<()> {
+    if args.session.is_none() && args.compact_after.is_some() {
+        anyhow::bail!(
+            "--compact-after compacts the saved session, start the chat with --session <NAME>"
+        );
+    }
+    let context = resolve_context(args, &None).await?;
+    let (runner, session) = prepare_runner(args, &context).await?;
+
+    println!(
+        "Chatting with {}, type {} for commands, {} to quit\n",
+        runner.agent_config.model.to_string().yellow(),
+        "/help".to_string().cyan(),
+        "/exit".to_string().cyan()
+    );
+
+    let stdin = BufReader::new(tokio::io::stdin());
+    chat_loop(runner, session, args.task.as_deref(), stdin).await
+}
+
+/// Read messages and commands from `input` until `/exit` or EOF. With a session, every turn goes through
+/// [`RunnerContext::run_session`], so compaction and autosave work like in `run`; without one the history
+/// only lives in memory.
+async fn chat_loop<R>(
+    mut runner: RunnerContext,
+    mut session: Option<Session>,
+    first_task: Option<&str>,
+    input: R,
+) -> Result<()>
+where
+    R: AsyncBufRead + Unpin,
+{
+    let mut history: Vec<Message> = Vec::new();
+
+    // A task on the command line is the first message
+    if let Some(task) = first_task {
+        println!("{} {}\n", ">".to_string().green().bold(), task);
+        chat_turn(&mut runner, task, &mut history, &mut session).await?;
+    }
+
+    let mut lines = input.lines();
+    loop {
+        print!("{} ", ">".to_string().green().bold());
+        std::io::stdout().flush()?;
+
+        let line = tokio::select! {
+            line = lines.next_line() => line?,
+            _ = tokio::signal::ctrl_c() => None,
+        };
+        // EOF or Ctrl-C
+        let Some(line) = line else {
+            println!();
+            break;
+        };
+
+        match line.trim() {
+            "" => continue,
+            "/exit" | "/quit" => break,
+            "/reset" => {
+                history.clear();
+                if let Some(ref mut session) = session {
+                    session.update_messages(Vec::new(), Utc::now());
+                }
+                println!("Conversation cleared\n");
+            }
+            "/save" => match session {
+                Some(ref mut session) => {
+                    save(session, &runner).await?;
+                    println!("Session saved\n");
+                }
+                None => println!("No session to save, start the chat with --session <NAME>\n"),
+            },
+            "/tools" if !runner.options.tools => println!("Tools are disabled (--no-tools)\n"),
+            "/tools" => {
+                for tool in runner.options.tool_options.tools() {
+                    println!(" {} - {}", tool.name().cyan(), tool_summary(tool.as_ref()));
+                }
+                println!();
+            }
+            "/help" => println!("{}\n", HELP),
+            command if command.starts_with('/') => {
+                println!("Unknown command {}, see /help\n", command.yellow());
+            }
+            task => {
+                // A fresh token per turn, Ctrl-C stops the answer but the partial turn is still saved
+                runner.cancel = CancellationToken::new();
+                let cancel = runner.cancel.clone();
+                let turn = chat_turn(&mut runner, task, &mut history, &mut session);
+                tokio::pin!(turn);
+                tokio::select! {
+                    result = &mut turn => result?,
+                    _ = tokio::signal::ctrl_c() => {
+                        cancel.cancel();
+                        turn.await?;
+                    }
+                }
+                if cancel.is_cancelled() {
+                    println!("\nInterrupted");
+                    break;
+                }
+            }
+        }
+    }
+
+    if let Some(ref mut session) = session {
+        save(session, &runner).await?;
+        println!("Session {} saved", session.name.to_string().green().bold());
+    }
+
+    Ok(())
+}
+
+/// One exchange, saved right away with a session. Errors are reported without leaving the chat
+async fn chat_turn(
+    runner: &mut RunnerContext,
+    task: &str,
+    history: &mut Vec<Message>,
+    session: &mut Option<Session>,
+) -> Result<()> {
+    let result = match session {
+        Some(session) => runner.run_session(task.to_string(), session).await,
+        None => runner.turn(task, history).await,
+    };
+    match result {
+        Ok(_) => {
+            println!();
+            // Piped context and images only belong to the first message
+            runner.context = None;
+            runner.image_urls.clear();
+        }
+        Err(e) => eprintln!("{}\n", format!("Error: {:#}", e).red()),
+    }
+    Ok(())
+}
+
+async fn save(session: &mut Session, runner: &RunnerContext) -> Result<()> {
+    session.last_model_used = runner.agent_config.model.clone();
+    session.settings = Some(runner.settings());
+    session.save_to_disk().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::tests::test_home;
+    use crate::core::runner::tests::{answer, quiet_options, test_runner};
+    use crate::core::session::load_session;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[tokio::test]
+    async fn scripted_chat_handles_reset_save_and_exit() {
+        let home = test_home();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut runner = test_runner(quiet_options()).await;
+        runner.script = Some(answer(&["Hi", " there"], calls.clone()));
+        let session = Session::new("chat-scripted", "qwen3-8b", home.join("sessions"));
+
+        let input = "hello\n/save\n/reset\n/help\nagain\n/exit\nnever sent\n";
+        chat_loop(runner, Some(session), None, input.as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+
+        // /reset dropped the first exchange, /exit saved the one after it
+        let saved = load_session("chat-scripted").await.unwrap();
+        let texts: Vec<_> = saved
+            .messages
+            .iter()
+            .map(|m| m.content.as_deref().unwrap_or_default())
+            .collect();
+        assert_eq!(texts, ["again", "Hi there"]);
+        assert!(saved.settings.is_some());
+    }
+
+    #[tokio::test]
+    async fn chat_without_a_session_keeps_the_history_in_memory() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut runner = test_runner(quiet_options()).await;
+        let seen = Arc::new(AtomicUsize::new(0));
+        let script = answer(&["ok"], calls.clone());
+        let seen_by_script = seen.clone();
+        runner.script = Some(Arc::new(move |messages| {
+            seen_by_script.store(messages.len(), Ordering::SeqCst);
+            script(messages)
+        }));
+
+        chat_loop(runner, None, Some("first"), "/save\nsecond\n".as_bytes())
+            .await
+            .unwrap();
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        // The second request carries the first exchange
+        assert_eq!(seen.load(Ordering::SeqCst), 3);
+    }
+}
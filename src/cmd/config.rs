@@ -0,0 +1,101 @@
+use crate::args::ConfigCommands;
+use crate::core::config::{
+    config_file_path, delete_config, list_configs, load_config, mask_config_secrets,
+};
+use anyhow::{Context, Result};
+use colored::Colorize;
+use std::io::Write;
+
+pub async fn run_config_command(command: &ConfigCommands) -> Result<()> {
+    match command {
+        ConfigCommands::List => {
+            let entries = list_configs()
+                .await
+                .with_context(|| anyhow::anyhow!("Failed to list configs, try `ragent init`"))?;
+            for entry in entries {
+                println!("{}", entry.name);
+            }
+        }
+        ConfigCommands::Show { name } => {
+            if !config_file_path(name)?.exists() {
+                anyhow::bail!("Config '{}' does not exist, see `ragent config list`", name);
+            }
+            let body = load_config(name.to_string()).await?;
+            let mut table: toml::Table = toml::from_str(&body)
+                .with_context(|| anyhow::anyhow!("Config '{}' is not valid TOML", name))?;
+            mask_config_secrets(&mut table);
+            println!("{}", name.to_string().magenta().bold());
+            println!("{}", toml::to_string_pretty(&table)?);
+        }
+        ConfigCommands::Delete { name, yes } => {
+            if !config_file_path(name)?.exists() {
+                anyhow::bail!("Config '{}' does not exist", name);
+            }
+            if !yes && !confirm(&format!("Delete config '{}'?", name))? {
+                println!("Aborted");
+                return Ok(());
+            }
+            delete_config(name).await?;
+            println!("Deleted config: {}", name.to_string().green().bold());
+        }
+    }
+    Ok(())
+}
+
+/// Ask a yes/no question on stderr, anything but `y`/`yes` is a no
+pub(crate) fn confirm(question: &str) -> Result<bool> {
+    if !atty::is(atty::Stream::Stdin) {
+        anyhow::bail!("Cannot ask for confirmation without a terminal, pass --yes");
+    }
+    eprint!("{} [y/N] ", question);
+    std::io::stderr().flush()?;
+    let mut answer = String::new();
+    std::io::stdin().read_line(&mut answer)?;
+    Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::tests::test_home;
+
+    #[tokio::test]
+    async fn show_and_delete_refuse_missing_configs() {
+        test_home();
+        let name = "config-never-saved".to_string();
+        let show = run_config_command(&ConfigCommands::Show { name: name.clone() }).await;
+        assert!(show.unwrap_err().to_string().contains("does not exist"));
+        let delete = run_config_command(&ConfigCommands::Delete { name, yes: true }).await;
+        assert!(delete.unwrap_err().to_string().contains("does not exist"));
+    }
+
+    #[tokio::test]
+    async fn delete_removes_only_the_named_config() {
+        let config_dir = test_home().join("config");
+        std::fs::write(config_dir.join("config-delete.toml"), r#"model = "a""#).unwrap();
+        std::fs::write(config_dir.join("config-kept.toml"), r#"model = "b""#).unwrap();
+
+        run_config_command(&ConfigCommands::Show {
+            name: "config-delete".to_string(),
+        })
+        .await
+        .unwrap();
+        run_config_command(&ConfigCommands::Delete {
+            name: "config-delete".to_string(),
+            yes: true,
+        })
+        .await
+        .unwrap();
+        assert!(!config_dir.join("config-delete.toml").exists());
+        assert!(config_dir.join("config-kept.toml").exists());
+
+        let names: Vec<String> = list_configs()
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|entry| entry.name)
+            .collect();
+        assert!(names.contains(&"config-kept".to_string()));
+        assert!(!names.contains(&"config-delete".to_string()));
+    }
+}
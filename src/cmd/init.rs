@@ -1,7 +1,14 @@
-use crate::core::config::{create_config_dir, default_agents, save_default_agent_configs};
-use crate::core::data::create_data_source;
+use crate::core::config::{
+    R_AGENT_KEYS, create_config_dir, default_agents, repair_config_table,
+    save_default_agent_configs, validate_sampling,
+};
+use crate::core::data::{create_data_source, write_atomic};
 use crate::core::session::create_session_dir;
-use anyhow::Result;
+use anyhow::{Context, Result};
+use colored::Colorize;
+use forge::api::agents::AgentBuilder;
+use std::path::{Path, PathBuf};
+use tokio::fs;
 
 pub async fn run_init(fix: bool) -> Result<()> {
     if fix {
@@ -13,8 +20,15 @@ pub async fn run_init(fix: bool) -> Result<()> {
     let config_path = create_config_dir().await?;
     let agents = default_agents();
 
-    // Save default agent configs
+    if fix {
+        repair_configs(&config_path).await?;
+    }
+
+    // Save default agent configs, --fix only restores the missing ones and keeps user edits
     for agent in agents.iter() {
+        if fix && default_config_file(&config_path, &agent.model).exists() {
+            continue;
+        }
         save_default_agent_configs(agent, config_path.clone()).await?;
     }
 
@@ -24,3 +38,240 @@ pub async fn run_init(fix: bool) -> Result<()> {
 
     Ok(())
 }
+
+/// Check every config in `config_dir`, rewriting the broken ones after backing them up next to the original
+async fn repair_configs(config_dir: &Path) -> Result<()> {
+    let mut read_dir = fs::read_dir(config_dir).await?;
+    let mut paths = Vec::new();
+    while let Some(entry) = read_dir.next_entry().await? {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) == Some("toml") {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+
+    let (mut repaired, mut broken) = (0, 0);
+    for path in &paths {
+        let name = path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or_default()
+            .to_string();
+        let body = fs::read_to_string(path)
+            .await
+            .with_context(|| anyhow::anyhow!("Failed to read {}", path.display()))?;
+
+        let mut table: toml::Table = match toml::from_str(&body) {
+            Ok(table) => table,
+            Err(e) => {
+                // Nothing to salvage field by field, keep a copy and let the user look at it
+                let backup = backup_config(path).await?;
+                eprintln!(
+                    "{} {} is not valid TOML, fix it by hand (copy at {}):\n{}",
+                    "x".red(),
+                    name.yellow(),
+                    backup.display(),
+                    e
+                );
+                broken += 1;
+                continue;
+            }
+        };
+
+        let Err(e) = check_config_table(&table) else {
+            continue;
+        };
+        eprintln!("{} {}: {:#}", "!".yellow(), name.yellow(), e);
+
+        let changes = repair_config_table(&mut table, &fallback_model(&name));
+        if let Err(e) = check_config_table(&table) {
+            eprintln!(
+                "{} {} could not be repaired: {:#}",
+                "x".red(),
+                name.yellow(),
+                e
+            );
+            broken += 1;
+            continue;
+        }
+
+        let backup = backup_config(path).await?;
+        write_atomic(path, toml::to_string(&table)?).await?;
+        for change in &changes {
+            println!("  {}", change.dimmed());
+        }
+        println!(
+            "{} Repaired {} (original at {})",
+            "+".green(),
+            name.green().bold(),
+            backup.display()
+        );
+        repaired += 1;
+    }
+
+    println!(
+        "Checked {} configs: {} repaired, {} need a manual fix",
+        paths.len(),
+        repaired,
+        broken
+    );
+    Ok(())
+}
+
+/// Whether the stored config builds an agent, without resolving an API key from the environment.
+/// The r_agent-only keys are taken out first, [`crate::core::config::parse_agent_builder`] handles them when the config is loaded.
+fn check_config_table(table: &toml::Table) -> Result<()> {
+    let mut table = table.clone();
+    for key in R_AGENT_KEYS {
+        table.remove(key);
+    }
+    validate_sampling(&table)?;
+    let builder: AgentBuilder = table.try_into()?;
+    builder.build()?;
+    Ok(())
+}
+
+/// Config files are named after their model, so a default agent with the same file name knows the real model.
+/// Otherwise the file name is the best guess left.
+fn fallback_model(config_name: &str) -> String {
+    default_agents()
+        .into_iter()
+        .map(|agent| agent.model)
+        .find(|model| sanitize_model(model) == config_name)
+        .unwrap_or_else(|| config_name.to_string())
+}
+
+fn default_config_file(config_dir: &Path, model: &str) -> PathBuf {
+    config_dir.join(format!("{}.toml", sanitize_model(model)))
+}
+
+fn sanitize_model(model: &str) -> String {
+    model.replace("/", "_").replace(":", "_")
+}
+
+/// Backups kept per config, `init --fix` deletes older ones
+const MAX_BACKUPS: usize = 5;
+
+/// Copies the config to `backups/<name>.toml.<timestamp>.bak` in the configs directory,
+/// out of the way of `list`, `--config` and the config count
+async fn backup_config(path: &Path) -> Result<PathBuf> {
+    let dir = path.parent().unwrap_or(Path::new(".")).join("backups");
+    fs::create_dir_all(&dir).await?;
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    // Microseconds keep the names sortable and apart, a backup made in the same instant waits for the next one
+    let backup = loop {
+        let stamp = chrono::Local::now().format("%Y%m%d%H%M%S%6f");
+        let backup = dir.join(format!("{}.{}.bak", file_name, stamp));
+        if !fs::try_exists(&backup).await? {
+            break backup;
+        }
+    };
+    fs::copy(path, &backup)
+        .await
+        .with_context(|| anyhow::anyhow!("Failed to back up {}", path.display()))?;
+    prune_backups(&dir, &file_name).await?;
+    Ok(backup)
+}
+
+/// Delete all but the newest [`MAX_BACKUPS`] backups of the config named `file_name`
+async fn prune_backups(dir: &Path, file_name: &str) -> Result<()> {
+    let prefix = format!("{}.", file_name);
+    let mut backups = Vec::new();
+    let mut read_dir = fs::read_dir(dir).await?;
+    while let Some(entry) = read_dir.next_entry().await? {
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if name.starts_with(&prefix) && name.ends_with(".bak") {
+            backups.push(entry.path());
+        }
+    }
+    // The timestamps sort oldest first
+    backups.sort();
+    let excess = backups.len().saturating_sub(MAX_BACKUPS);
+    for old in &backups[..excess] {
+        fs::remove_file(old).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::data::tests::temp_dir;
+
+    #[tokio::test]
+    async fn backups_are_kept_apart_and_pruned() {
+        let dir = temp_dir("backups");
+        let config = dir.join("qwen_qwen3-8b.toml");
+        let other = dir
+            .join("backups")
+            .join("other.toml.20200101000000000000.bak");
+        fs::create_dir_all(other.parent().unwrap()).await.unwrap();
+        fs::write(&other, "other").await.unwrap();
+
+        let mut made = Vec::new();
+        for version in 0..MAX_BACKUPS + 2 {
+            fs::write(&config, format!("v{}", version)).await.unwrap();
+            made.push(backup_config(&config).await.unwrap());
+        }
+
+        let mut kept = Vec::new();
+        let mut entries = fs::read_dir(dir.join("backups")).await.unwrap();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            kept.push(entry.path());
+        }
+        kept.sort();
+        // Only the newest backups of this config go, other configs' backups stay
+        assert_eq!(kept.len(), MAX_BACKUPS + 1);
+        assert!(kept.contains(&other));
+        assert!(!made[0].exists() && !made[1].exists());
+        for (version, backup) in made.iter().enumerate().skip(2) {
+            assert_eq!(
+                fs::read_to_string(backup).await.unwrap(),
+                format!("v{}", version)
+            );
+        }
+        assert_eq!(
+            fs::read_to_string(&config).await.unwrap(),
+            format!("v{}", MAX_BACKUPS + 1)
+        );
+    }
+
+    #[test]
+    fn corrupted_config_is_repaired_until_it_builds() {
+        let mut table: toml::Table = toml::from_str(
+            r#"
+            model = ""
+            url = 8080
+            temperature = 1
+            top_p = "high"
+            max_tokens = -5
+            "#,
+        )
+        .unwrap();
+        assert!(check_config_table(&table).is_err());
+
+        let changes = repair_config_table(&mut table, &fallback_model("qwen_qwen3-8b"));
+        assert_eq!(
+            changes,
+            [
+                "dropped `url`, it must be a string",
+                "turned `temperature` into a float",
+                "dropped `top_p`, it must be a number",
+                "dropped `max_tokens`, it must be a positive integer",
+                "set `model` to \"qwen/qwen3-8b\"",
+                "added the default `url`",
+                "added the default `api_key`",
+                "added the default `system_prompt`",
+            ]
+        );
+        check_config_table(&table).unwrap();
+        assert_eq!(table["temperature"].as_float(), Some(1.0));
+
+        // A sound config is left as it is
+        assert!(repair_config_table(&mut table, "unused").is_empty());
+    }
+}
@@ -0,0 +1,73 @@
+use crate::core::config::list_configs;
+use crate::core::data::StoredEntry;
+use crate::core::session::{list_sessions, read_session_stats};
+use anyhow::{Context, Result};
+use colored::Colorize;
+
+pub async fn run_list(sessions: bool, configs: bool) -> Result<()> {
+    // No filter means both
+    let (sessions, configs) = if !sessions && !configs {
+        (true, true)
+    } else {
+        (sessions, configs)
+    };
+
+    if configs {
+        let entries = list_configs()
+            .await
+            .with_context(|| anyhow::anyhow!("Failed to list configs, try `ragent init`"))?;
+        print_entries("Configs", &entries);
+    }
+
+    if sessions {
+        let entries = list_sessions()
+            .await
+            .with_context(|| anyhow::anyhow!("Failed to list sessions, try `ragent init`"))?;
+        print_sessions(&entries).await;
+    }
+
+    Ok(())
+}
+
+/// Like [`print_entries`] with each session's stats, a file that doesn't parse is marked corrupt
+/// instead of failing the whole listing
+async fn print_sessions(entries: &[StoredEntry]) {
+    println!(
+        "{} ({})",
+        "Sessions".to_string().magenta().bold(),
+        entries.len()
+    );
+    let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    for entry in entries {
+        let stats = match read_session_stats(&entry.path).await {
+            Ok(stats) => format!(
+                "{} messages, {} tool calls, ~{} tokens, {}",
+                stats.messages, stats.tool_calls, stats.estimated_tokens, stats.last_model_used
+            )
+            .normal(),
+            Err(_) => "corrupt".red(),
+        };
+        println!(
+            " {:<width$}  {}  {}",
+            entry.name.cyan(),
+            entry.modified.format("%Y-%m-%d %H:%M").to_string().dimmed(),
+            stats,
+            width = width
+        );
+    }
+    println!();
+}
+
+fn print_entries(title: &str, entries: &[StoredEntry]) {
+    println!("{} ({})", title.to_string().magenta().bold(), entries.len());
+    let width = entries.iter().map(|e| e.name.len()).max().unwrap_or(0);
+    for entry in entries {
+        println!(
+            " {:<width$}  {}",
+            entry.name.cyan(),
+            entry.modified.format("%Y-%m-%d %H:%M").to_string().dimmed(),
+            width = width
+        );
+    }
+    println!();
+}
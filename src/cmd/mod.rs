@@ -1,3 +1,9 @@
 pub mod ascii;
+pub mod chat;
+pub mod config;
 pub mod init;
+pub mod list;
+pub mod models;
+pub mod replay;
 pub mod run;
+pub mod session;
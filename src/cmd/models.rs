@@ -0,0 +1,29 @@
+use crate::core::config::{ConfigOverrides, load_config, parse_agent_builder};
+use crate::core::models::{MODELS_TIMEOUT, list_models};
+use anyhow::{Context, Result};
+use colored::Colorize;
+
+pub async fn run_models(config: &str) -> Result<()> {
+    let config_body = load_config(config.to_string()).await?;
+    let agent = parse_agent_builder(&config_body, &ConfigOverrides::default())?.build()?;
+
+    let models = list_models(&agent.url, &agent.api_key, MODELS_TIMEOUT)
+        .await
+        .with_context(|| anyhow::anyhow!("Failed to list the models of '{}'", config))?;
+
+    println!(
+        "{} ({}) {}",
+        "Models".to_string().magenta().bold(),
+        models.len(),
+        agent.url.dimmed()
+    );
+    for model in &models {
+        if *model == agent.model {
+            println!(" {} {}", "*".green(), model.cyan());
+        } else {
+            println!("   {}", model);
+        }
+    }
+
+    Ok(())
+}
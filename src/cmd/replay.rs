@@ -0,0 +1,206 @@
+use crate::args::RunArgs;
+use crate::cmd::run::{check_endpoint, prepare_runner};
+use crate::core::runner::RunnerContext;
+use crate::core::session::{Session, get_default_session_path, load_session};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use colored::Colorize;
+use forge::api::dtos::Message;
+use forge::api::dtos::Role::USER;
+
+/// Send the stored user turns of `name` again, one by one, and save the fresh answers as a new session.
+/// Tool calls are not replayed, the agent runs its tools anew for each turn.
+pub async fn run_replay(name: &str, args: &RunArgs) -> Result<()> {
+    if args.task.is_some() {
+        anyhow::bail!("replay re-sends the stored user turns, it takes no task");
+    }
+
+    let original = load_session(name)
+        .await
+        .with_context(|| anyhow::anyhow!("Failed to load session '{}'", name))?;
+    let user_turns: Vec<&Message> = original
+        .messages
+        .iter()
+        .filter(|m| matches!(m.role, USER))
+        .collect();
+    if user_turns.is_empty() {
+        anyhow::bail!("Session '{}' has no user turns to replay", name);
+    }
+
+    let target = args
+        .session
+        .clone()
+        .unwrap_or_else(|| format!("{}-replay", name));
+    let session_path = get_default_session_path()?;
+    if target == name || session_path.join(format!("{}.json", target)).exists() {
+        anyhow::bail!(
+            "Session '{}' already exists, pick another name with --session",
+            target
+        );
+    }
+
+    // The runner must not load or save a session itself, the replay owns the new one
+    let mut run_args = args.clone();
+    run_args.session = None;
+    let (runner, _) = prepare_runner(&run_args, &None).await?;
+    if !args.no_preflight {
+        check_endpoint(&runner).await?;
+    }
+    runner.cancel_on_ctrl_c();
+
+    eprintln!(
+        "Replaying {} turns of {} into {}\n",
+        user_turns.len().to_string().cyan().bold(),
+        name.to_string().yellow(),
+        target.to_string().green().bold()
+    );
+
+    let mut replay = Session::new(&target, &args.config, session_path);
+    replay_turns(&runner, user_turns, &mut replay).await?;
+
+    eprintln!(
+        "Saved the replay as {}, compare with `ragent session export`",
+        target.to_string().green().bold()
+    );
+    Ok(())
+}
+
+/// Send `user_turns` one by one, `replay` is saved after every answer
+async fn replay_turns(
+    runner: &RunnerContext,
+    user_turns: Vec<&Message>,
+    replay: &mut Session,
+) -> Result<()> {
+    let mut history = Vec::new();
+    for (i, user_message) in user_turns.into_iter().enumerate() {
+        eprintln!(
+            "{} {}",
+            format!("[{}]", i + 1).dimmed(),
+            user_text(user_message).yellow()
+        );
+        let sent_at = Utc::now();
+        runner
+            .turn_message(user_message.clone(), &mut history)
+            .await
+            .with_context(|| anyhow::anyhow!("Turn {} of the replay failed", i + 1))?;
+        println!();
+
+        replay.update_messages(history.clone(), sent_at);
+        replay.last_model_used = runner.agent_config.model.clone();
+        replay.settings = Some(runner.settings());
+        replay.save_to_disk().await?;
+
+        if runner.cancel.is_cancelled() {
+            eprintln!("{}", "Interrupted".to_string().yellow());
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// The start of what the user typed (context included), images and other parts left out
+fn user_text(message: &Message) -> String {
+    let text = message
+        .content
+        .clone()
+        .or_else(|| {
+            message
+                .multi_content
+                .iter()
+                .flatten()
+                .find_map(|part| part.text.clone())
+        })
+        .unwrap_or_default();
+    let line = text.lines().next().unwrap_or_default();
+    match line.char_indices().nth(100) {
+        Some((end, _)) => format!("{}...", &line[..end]),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{Args, Commands};
+    use crate::core::config::tests::test_home;
+    use crate::core::messages::MessageExt;
+    use crate::core::runner::tests::{Requests, record};
+    use clap::Parser;
+    use forge::api::dtos::{FunctionCall, ToolCall};
+
+    #[tokio::test]
+    async fn two_user_turns_replay_into_a_new_session() {
+        let config = r#"
+            model = "qwen3-8b"
+            url = "http://127.0.0.1:9/v1"
+            api_key = "local"
+            system_prompt = "You are a test agent."
+        "#;
+        std::fs::write(test_home().join("config").join("replay.toml"), config).unwrap();
+
+        let mut original = Session::new("replay-original", "qwen3-8b", Default::default());
+        original.messages = vec![
+            Message::user_text("What time is it?"),
+            Message::assistant_tool_calls(vec![ToolCall {
+                id: "call_1_0".to_string(),
+                r#type: "function".to_string(),
+                function: FunctionCall {
+                    name: "get_time_tool".to_string(),
+                    arguments: "{}".to_string(),
+                },
+            }]),
+            Message::tool_result("call_1_0", "get_time_tool", "12:00"),
+            Message::assistant_text("It is noon."),
+            Message::user_text("And in an hour?"),
+            Message::assistant_text("1 pm."),
+        ];
+        original.save_to_disk().await.unwrap();
+        let original_file = get_default_session_path()
+            .unwrap()
+            .join("replay-original.json");
+        let stored = std::fs::read_to_string(&original_file).unwrap();
+
+        let args =
+            Args::try_parse_from(["ragent", "replay", "replay-original", "-c", "replay"]).unwrap();
+        let Some(Commands::Replay { run, .. }) = args.command else {
+            unreachable!()
+        };
+        let (mut runner, _) = prepare_runner(&run, &None).await.unwrap();
+        runner.options.render.silent = true;
+        let requests = Requests::default();
+        runner.script = Some(record(&["Replayed."], requests.clone()));
+
+        let user_turns: Vec<&Message> = original
+            .messages
+            .iter()
+            .filter(|m| matches!(m.role, USER))
+            .collect();
+        let mut replay = Session::new("replay-original-replay", "qwen3-8b", Default::default());
+        replay_turns(&runner, user_turns, &mut replay)
+            .await
+            .unwrap();
+
+        let saved = load_session("replay-original-replay").await.unwrap();
+        let contents: Vec<&str> = saved
+            .messages
+            .iter()
+            .map(|m| m.content.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            contents,
+            [
+                "What time is it?",
+                "Replayed.",
+                "And in an hour?",
+                "Replayed."
+            ]
+        );
+        // Only the user turns went out, the old tool calls and answers stayed home
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].len(), 3);
+        assert_eq!(requests[1][1].content.as_deref(), Some("Replayed."));
+
+        assert_eq!(std::fs::read_to_string(&original_file).unwrap(), stored);
+    }
+}
@@ -1,59 +1,315 @@
-use crate::core::config::load_config;
-use crate::core::runner::RunnerContext;
-use crate::core::session::Session;
+use crate::args::{OutputFormat, PipedContext, RunArgs};
+use crate::cmd::config::confirm;
+use crate::core::config::{
+    ConfigOverrides, DEFAULT_CONTEXT_WINDOW, config_preferences, load_config, mask_config_secrets,
+    parse_agent_builder,
+};
+use crate::core::data::write_atomic;
+use crate::core::debug_log::DebugLog;
+use crate::core::events::{ToolCallRecord, Transcript};
+use crate::core::models::{EndpointStatus, MODELS_TIMEOUT, closest_model, ping};
+use crate::core::render::{RenderOptions, pause_spinner, terminal_width};
+use crate::core::retry::RetryPolicy;
+use crate::core::runner::{RunnerContext, RunnerOptions, Timeouts};
+use crate::core::session::{CompactionPolicy, Session};
 use crate::core::session::{get_default_session_path, load_session};
+use crate::core::tokens::estimate_text;
+use crate::core::tools::{Approver, ToolOptions};
 use anyhow::{Context, Result};
 use base64::Engine;
 use base64::prelude::BASE64_STANDARD;
 use colored::Colorize;
+use forge::api::agents::{Agent, AgentBuilder};
+use serde::Serialize;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+/// Only the answer goes to stdout, the preamble and tool activity go to stderr so `> out.txt` captures just the answer
 pub async fn run_agent(
     task: &str,
-    _plan: &Option<String>,
-    image: &Option<String>,
-    config: &str,
-    session: &Option<String>,
-    context: &Option<String>,
+    args: &RunArgs,
+    piped_input: &Option<String>,
+    format: OutputFormat,
 ) -> Result<()> {
-    println!("\nRunning agent...\n");
-    println!("Task: {}", task.to_string().yellow());
-    println!("Config: {}", config.to_string().yellow());
+    let context = &resolve_context(args, piped_input).await?;
+
+    if format == OutputFormat::Text && !args.show_config {
+        print_preamble(task, args, context)?;
+    }
+
+    let (mut runner_context, mut session_data) = prepare_runner(args, context).await?;
+
+    if args.show_config {
+        println!("{}", show_config(&runner_context.agent_config, format)?);
+        return Ok(());
+    }
+
+    if args.dry_run {
+        let history = session_data
+            .as_ref()
+            .map(|s| s.context_messages())
+            .unwrap_or_default();
+        let (body, estimated) = runner_context.request_preview(task, &history)?;
+        println!("{}", serde_json::to_string_pretty(&body)?);
+        eprintln!(
+            "\n{} ~{} tokens, nothing was sent",
+            "Dry run:".to_string().yellow(),
+            estimated.to_string().cyan().bold()
+        );
+        return Ok(());
+    }
+
+    if !args.no_preflight {
+        check_endpoint(&runner_context).await?;
+    }
+
+    // `-` swaps the live rendering for the plain answer once it is complete
+    let plain_stdout = args.save_output.as_deref() == Some(std::path::Path::new("-"));
+    runner_context.options.render.silent = format == OutputFormat::Json || plain_stdout;
+    runner_context.options.render.jsonl = format == OutputFormat::Jsonl;
+    runner_context.cancel_on_ctrl_c();
+
+    let transcript = if let Some(ref mut session) = session_data {
+        let transcript = runner_context
+            .run_session(task.to_string(), session)
+            .await?;
+        runner_context.session = Some(session.clone()); // keep context in sync if needed
+        transcript
+    } else {
+        // No session to save
+        runner_context.run(task.to_string()).await?
+    };
+
+    if let Some(ref path) = args.save_output {
+        save_answer(path, format, &transcript.text).await?;
+    }
+
+    match final_output(format, &transcript, &runner_context, args)? {
+        Some(output) => println!("{}", output),
+        None if runner_context.cancel.is_cancelled() => {
+            eprintln!("\n{}", "Interrupted".to_string().yellow());
+        }
+        None => {}
+    }
+
+    Ok(())
+}
+
+/// `--save-output`: the raw answer, without wrapping or colors, written to `path` or printed for `-`
+async fn save_answer(path: &std::path::Path, format: OutputFormat, answer: &str) -> Result<()> {
+    if path == std::path::Path::new("-") {
+        if format == OutputFormat::Text {
+            println!("{}", answer);
+        }
+        return Ok(());
+    }
+    write_atomic(path, answer)
+        .await
+        .with_context(|| anyhow::anyhow!("Failed to save the answer to {}", path.display()))
+}
+
+/// What `--format json` prints once the run is done, or the line closing a `--format jsonl` run.
+/// Text runs have nothing left to print.
+fn final_output(
+    format: OutputFormat,
+    transcript: &Transcript,
+    runner: &RunnerContext,
+    args: &RunArgs,
+) -> Result<Option<String>> {
+    let usage = Usage {
+        prompt_tokens: transcript.prompt_tokens,
+        completion_tokens: estimate_text(&transcript.text),
+        estimated: true,
+    };
+    let cancelled = runner.cancel.is_cancelled();
+    let output = match format {
+        OutputFormat::Json => {
+            let output = JsonOutput {
+                answer: &transcript.text,
+                model: &runner.agent_config.model,
+                session: args.session.as_deref(),
+                tool_calls: args
+                    .include_tools
+                    .then_some(transcript.tool_calls.as_slice()),
+                usage,
+                cancelled,
+            };
+            serde_json::to_string_pretty(&output)?
+        }
+        // The events are already out, the last line closes the run
+        OutputFormat::Jsonl => serde_json::json!({
+            "type": "done",
+            "model": runner.agent_config.model,
+            "session": args.session,
+            "usage": usage,
+            "cancelled": cancelled,
+        })
+        .to_string(),
+        OutputFormat::Text => return Ok(None),
+    };
+    Ok(Some(output))
+}
+
+/// The agent a run resolves to as `--show-config` prints it, TOML or JSON with `--format json`, the API key masked
+fn show_config(agent: &Agent, format: OutputFormat) -> Result<String> {
+    let mut table: toml::Table =
+        toml::from_str(&AgentBuilder::convert_to_builder(agent).to_toml_string()?)?;
+    mask_config_secrets(&mut table);
+    Ok(match format {
+        OutputFormat::Text => toml::to_string_pretty(&table)?,
+        OutputFormat::Json => serde_json::to_string_pretty(&table)?,
+        OutputFormat::Jsonl => serde_json::to_string(&table)?,
+    })
+}
+
+/// Best-effort preflight: fail before the task is sent when the server definitely can't answer it (rejected key,
+/// unknown model), so the error names the cause instead of surfacing deep in the stream.
+/// A server that doesn't answer only gets a warning, it may be back by the time the retries run.
+pub(crate) async fn check_endpoint(runner: &RunnerContext) -> Result<()> {
+    let agent = &runner.agent_config;
+    match ping(&agent.url, &agent.api_key, &agent.model, MODELS_TIMEOUT).await {
+        EndpointStatus::Reachable => Ok(()),
+        EndpointStatus::AuthFailed => anyhow::bail!(
+            "{} rejected the api key, check `api_key` in the config",
+            agent.url
+        ),
+        EndpointStatus::ModelMissing(available) => {
+            // Servers may still resolve a name they don't list, so the run goes ahead
+            let hint = match closest_model(&agent.model, &available) {
+                Some(suggestion) => format!(
+                    "did you mean '{}'? (--model {} for this run)",
+                    suggestion, suggestion
+                ),
+                None => "see `ragent models --config <NAME>`".to_string(),
+            };
+            eprintln!(
+                "{}",
+                format!(
+                    "Warning: {} does not list the model '{}', {}\n",
+                    agent.url, agent.model, hint
+                )
+                .yellow()
+            );
+            Ok(())
+        }
+        EndpointStatus::Unreachable(reason) => {
+            eprintln!(
+                "{}",
+                format!(
+                    "Warning: {} did not answer the preflight check ({}), trying anyway\n",
+                    agent.url, reason
+                )
+                .yellow()
+            );
+            Ok(())
+        }
+    }
+}
+
+/// The single object printed by `--format json`
+#[derive(Serialize)]
+struct JsonOutput<'a> {
+    answer: &'a str,
+    model: &'a str,
+    session: Option<&'a str>,
+    /// Only with `--include-tools`, the output stays compact otherwise
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_calls: Option<&'a [ToolCallRecord]>,
+    usage: Usage,
+    cancelled: bool,
+}
+
+/// forge doesn't hand back the server's usage block, so these are the local estimates
+#[derive(Serialize)]
+struct Usage {
+    prompt_tokens: usize,
+    completion_tokens: usize,
+    estimated: bool,
+}
 
-    if let Some(image_path) = image {
-        let encoded_image = encode_image(image_path)?;
-        println!(
+fn print_preamble(task: &str, args: &RunArgs, context: &Option<String>) -> Result<()> {
+    eprintln!("\nRunning agent...\n");
+    eprintln!("Task: {}", task.to_string().yellow());
+    eprintln!("Config: {}", args.config.to_string().yellow());
+
+    if args.image.is_empty() {
+        eprintln!("Image: None");
+    }
+    for image in &args.image {
+        if is_image_url(image) {
+            eprintln!("Image: {} (sent as-is)", shorten(image, 60).yellow());
+            continue;
+        }
+        let encoded_image = image_url(image)?;
+        eprintln!(
             "Image: {} (encoded to {} chars)",
-            image_path.to_string().yellow(),
+            image.to_string().yellow(),
             encoded_image.len().to_string().cyan().bold()
         );
-    } else {
-        println!("Image: None");
     }
 
-    if let Some(s) = session {
-        println!("Session: {}", s);
+    if let Some(plan) = &args.plan {
+        eprintln!("Plan: {}", plan.to_string().yellow());
+    }
+
+    if let Some(s) = &args.session {
+        eprintln!("Session: {}", s);
     } else {
-        println!("Session: None");
+        eprintln!("Session: None");
     }
     if let Some(ctx) = context {
-        println!("Context: {} chars", ctx.len().to_string().cyan().bold());
+        eprintln!("Context: {} chars", ctx.len().to_string().cyan().bold());
     } else {
-        println!("Context: None");
+        eprintln!("Context: None");
     }
 
-    println!();
+    eprintln!();
+    Ok(())
+}
+
+/// Load the config, session and images named by the run flags and build the runner from them
+pub async fn prepare_runner(
+    args: &RunArgs,
+    context: &Option<String>,
+) -> Result<(RunnerContext, Option<Session>)> {
+    let image = &args.image;
+    let config = args.config.as_str();
+    let session = &args.session;
 
-    // Load agent config
+    // Load agent config, CLI flags win over the stored values
     let config_body = load_config(config.to_string()).await?;
+    let system_prompt = match args.system_file {
+        Some(ref path) => Some(tokio::fs::read_to_string(path).await.with_context(|| {
+            anyhow::anyhow!("Failed to read system prompt file: {}", path.display())
+        })?),
+        None => args.system.clone(),
+    };
+    let overrides = ConfigOverrides {
+        model: args.model.clone(),
+        temperature: args.temperature,
+        top_p: args.top_p,
+        max_tokens: args.max_tokens,
+        system_prompt,
+        provider: args.provider,
+        url: args.url.clone(),
+    }
+    .with_preset(args.preset);
+    let agent_builder = parse_agent_builder(&config_body, &overrides)
+        .with_context(|| anyhow::anyhow!("Failed to parse config: {}", config))?;
+    let preferences = config_preferences(&config_body)
+        .with_context(|| anyhow::anyhow!("Failed to parse config: {}", config))?;
+    // --no-stream and --context-size win over the config's preferences
+    let stream = !args.no_stream && preferences.stream;
+    let context_window = args.context_size.or(preferences.context_window);
 
-    let mut session_data = if let Some(session_name) = session {
+    let session_data = if let Some(session_name) = session {
         let session_path = get_default_session_path()
             .with_context(|| anyhow::anyhow!("Failed to get default session path"))?;
         let full_path = session_path.join(format!("{}.json", session_name));
 
         // Try to load existing session, or create a new one if it doesn't exist
         let session = if full_path.exists() {
-            println!(
+            eprintln!(
                 "Loading session: {}\n",
                 session_name.to_string().green().bold()
             );
@@ -61,7 +317,7 @@ pub async fn run_agent(
                 .await
                 .with_context(|| anyhow::anyhow!("Failed to load session"))?
         } else {
-            println!(
+            eprintln!(
                 "Creating session: {}\n",
                 session_name.to_string().green().bold()
             );
@@ -74,28 +330,133 @@ pub async fn run_agent(
 
     let context = context.clone();
 
-    // Encoded the image
-    let image = if let Some(image_path) = image {
-        Some(encode_image(image_path)?)
-    } else {
-        None
+    // Files are encoded to data URLs, URLs are passed through
+    let image = image
+        .iter()
+        .map(|i| image_url(i))
+        .collect::<Result<Vec<_>>>()?;
+
+    let options = RunnerOptions {
+        timeouts: Timeouts {
+            request: Duration::from_secs(args.timeout),
+            idle: Duration::from_secs(args.idle_timeout),
+        },
+        retry: RetryPolicy {
+            max_retries: args.retries,
+            ..RetryPolicy::default()
+        },
+        render: RenderOptions {
+            char_delay: if !stream {
+                Duration::ZERO
+            } else {
+                Duration::from_millis(args.typewriter_delay)
+            },
+            buffered: args.buffered || !stream,
+            show_tools: !args.quiet,
+            wrap_len: args.width.unwrap_or_else(terminal_width),
+            code_blocks: !args.wrap_code,
+            ..RenderOptions::default()
+        },
+        compaction: args.compact_after.map(|max_messages| CompactionPolicy {
+            max_messages,
+            ..CompactionPolicy::default()
+        }),
+        tools: !args.no_tools,
+        tool_options: ToolOptions {
+            max_concurrent: args.max_concurrent_tools,
+            read_only: args.read_only,
+            max_repeats: args.max_repeated_calls,
+            max_output: args.max_tool_output,
+        },
+        stream,
+        context_size: context_window.unwrap_or(DEFAULT_CONTEXT_WINDOW),
+        enforce_context_size: context_window.is_some(),
     };
 
-    let mut runner_context = RunnerContext::pre_load(&config_body, &session_data, &context, &image)
-        .await
-        .with_context(|| anyhow::anyhow!("Failed to preload runner context"))?;
+    let mut runner_context = RunnerContext::pre_load(
+        agent_builder,
+        &session_data,
+        &context,
+        &image,
+        &args.plan,
+        options,
+    )
+    .await
+    .with_context(|| anyhow::anyhow!("Failed to preload runner context"))?;
+    if !args.yes {
+        runner_context.approver = Some(cli_approver());
+    }
+    runner_context.debug_log = DebugLog::resolve(args.log_file.clone());
+    if let Some(ref session) = session_data {
+        warn_changed_settings(session, &runner_context);
+    }
 
-    if let Some(ref mut session) = session_data {
-        runner_context
-            .run_session(task.to_string(), session)
-            .await?;
-        runner_context.session = Some(session.clone()); // keep context in sync if needed
-    } else {
-        runner_context.run(task.to_string()).await?;
-        // No session to save
+    Ok((runner_context, session_data))
+}
+
+/// The session goes on under the current config, but a silent change of settings mid-conversation is confusing
+fn warn_changed_settings(session: &Session, runner: &RunnerContext) {
+    let Some(ref stored) = session.settings else {
+        return;
+    };
+    let changes = stored.differences(&runner.settings());
+    if changes.is_empty() {
+        return;
     }
+    eprintln!(
+        "{}",
+        format!(
+            "Warning: the settings changed since session '{}' was last used: {}\n",
+            session.name,
+            changes.join(", ")
+        )
+        .yellow()
+    );
+}
 
-    Ok(())
+/// Asks on stderr before a tool that needs approval runs, one question at a time.
+/// Without a terminal to ask on, the call is denied.
+fn cli_approver() -> Approver {
+    let asking = Arc::new(Mutex::new(()));
+    Arc::new(move |name: &str, args: &serde_json::Value| {
+        let _asking = asking.lock().unwrap_or_else(|e| e.into_inner());
+        let _spinner = pause_spinner();
+        let question = format!("\nAllow {} {}?", name.yellow(), args);
+        confirm(&question).unwrap_or_else(|e| {
+            eprintln!("{}", format!("Denied {}: {}", name, e).red());
+            false
+        })
+    })
+}
+
+/// Context comes from piped stdin, `--context-file`, or both combined as `--piped-context` says.
+/// Giving both without `--piped-context` is ambiguous and rejected.
+pub async fn resolve_context(
+    args: &RunArgs,
+    piped_input: &Option<String>,
+) -> Result<Option<String>> {
+    let Some(ref path) = args.context_file else {
+        return Ok(piped_input.clone());
+    };
+    let Some(ref piped) = *piped_input else {
+        return read_context_file(path).await.map(Some);
+    };
+    let context = match args.piped_context {
+        None => anyhow::bail!(
+            "Both piped input and --context-file were given, \
+            choose how to combine them with --piped-context prepend|append|replace"
+        ),
+        Some(PipedContext::Replace) => piped.clone(),
+        Some(PipedContext::Prepend) => format!("{}\n\n{}", piped, read_context_file(path).await?),
+        Some(PipedContext::Append) => format!("{}\n\n{}", read_context_file(path).await?, piped),
+    };
+    Ok(Some(context))
+}
+
+async fn read_context_file(path: &std::path::Path) -> Result<String> {
+    tokio::fs::read_to_string(path)
+        .await
+        .with_context(|| anyhow::anyhow!("Failed to read context file: {}", path.display()))
 }
 
 pub async fn read_stdin() -> Option<String> {
@@ -119,9 +480,582 @@ pub async fn read_stdin() -> Option<String> {
     }
 }
 
-fn encode_image(image_path: &String) -> Result<String> {
-    let image_data = std::fs::read(image_path)
-        .with_context(|| anyhow::anyhow!("Failed to read image file: {}", image_path))?;
+/// The URL to send for an `--image` value: `http(s)://` and `data:` URLs are used verbatim,
+/// anything else is read as a file and encoded to a base64 data URL
+fn image_url(image: &str) -> Result<String> {
+    if is_image_url(image) {
+        return Ok(image.to_string());
+    }
+    let path = std::path::Path::new(image);
+    if !path.is_file() {
+        anyhow::bail!("Image file not found: {}", image);
+    }
+    let image_data = std::fs::read(path)
+        .with_context(|| anyhow::anyhow!("Failed to read image file: {}", image))?;
     let encoded = BASE64_STANDARD.encode(&image_data);
-    Ok(encoded)
+    Ok(format!("data:{};base64,{}", image_mime(path), encoded))
+}
+
+fn is_image_url(image: &str) -> bool {
+    ["http://", "https://", "data:"]
+        .iter()
+        .any(|prefix| image.starts_with(prefix))
+}
+
+/// Guessed from the extension, servers are lenient enough that jpeg is a fine fallback
+fn image_mime(path: &std::path::Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+/// Data URLs run to megabytes, the preamble only needs the start
+fn shorten(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((end, _)) => format!("{}...", &text[..end]),
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::args::{Args, Commands};
+    use crate::core::config::tests::test_home;
+    use crate::core::data::tests::temp_dir;
+    use crate::core::messages::MessageExt;
+    use crate::core::runner::tests::{TEST_API_KEY, answer, test_runner};
+    use crate::core::test_server::{Reply, TestServer};
+    use clap::Parser;
+    use forge::api::dtos::Message;
+    use std::sync::atomic::AtomicUsize;
+
+    /// The run flags of `ragent run <flags>`
+    fn run_args(flags: &[&str]) -> RunArgs {
+        let args = Args::try_parse_from(["ragent", "run"].iter().chain(flags)).unwrap();
+        match args.command {
+            Some(Commands::Run(run)) => run,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Store a config named `name` under the test home, pointing at nothing
+    fn save_test_config(name: &str) {
+        let config = r#"
+            model = "qwen3-8b"
+            url = "http://127.0.0.1:9/v1"
+            api_key = "local"
+            system_prompt = "You are a test agent."
+        "#;
+        std::fs::write(
+            test_home().join("config").join(format!("{}.toml", name)),
+            config,
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn image_flags_reach_the_runner() {
+        save_test_config("run-images");
+        let args = run_args(&[
+            "describe",
+            "--config",
+            "run-images",
+            "--image",
+            "https://example.com/a.png",
+            "-i",
+            "data:image/png;base64,iVBORw0KGgo=",
+        ]);
+        assert_eq!(args.task.as_deref(), Some("describe"));
+
+        let (runner, _) = prepare_runner(&args, &None).await.unwrap();
+        assert_eq!(runner.image_urls, args.image);
+
+        let args = run_args(&["describe", "--config", "run-images"]);
+        assert!(args.image.is_empty());
+        let args = run_args(&["describe", "-c", "run-images", "-i", "missing.png"]);
+        let err = prepare_runner(&args, &None).await.err().unwrap();
+        assert_eq!(err.to_string(), "Image file not found: missing.png");
+    }
+
+    #[test]
+    fn image_values_become_urls() {
+        let dir = temp_dir("image-urls");
+        let png = dir.join("shot.PNG");
+        std::fs::write(&png, [0x89, b'P', b'N', b'G']).unwrap();
+        assert_eq!(
+            image_url(png.to_str().unwrap()).unwrap(),
+            "data:image/png;base64,iVBORw=="
+        );
+        let photo = dir.join("photo");
+        std::fs::write(&photo, b"jpg").unwrap();
+        assert_eq!(
+            image_url(photo.to_str().unwrap()).unwrap(),
+            "data:image/jpeg;base64,anBn"
+        );
+
+        // URLs are sent as they are, nothing is fetched or encoded twice
+        for url in [
+            "http://example.com/a.png",
+            "https://example.com/a.png",
+            "data:image/webp;base64,UklGRg==",
+        ] {
+            assert_eq!(image_url(url).unwrap(), url);
+        }
+    }
+
+    #[tokio::test]
+    async fn system_flags_replace_the_system_message() {
+        save_test_config("run-system");
+        let system_message = |args: RunArgs| async move {
+            let (runner, _) = prepare_runner(&args, &None).await.unwrap();
+            let (body, _) = runner.request_preview("Hi", &[]).unwrap();
+            assert_eq!(body["messages"][0]["role"], "system");
+            body["messages"][0]["content"].as_str().unwrap().to_string()
+        };
+
+        let stored = system_message(run_args(&["Hi", "-c", "run-system"])).await;
+        assert_eq!(stored, "You are a test agent.");
+
+        let flag = run_args(&["Hi", "-c", "run-system", "--system", "Talk like a pirate."]);
+        assert_eq!(system_message(flag).await, "Talk like a pirate.");
+
+        let file = temp_dir("system-file").join("persona.md");
+        std::fs::write(&file, "Answer in haiku.").unwrap();
+        let from_file = run_args(&[
+            "Hi",
+            "-c",
+            "run-system",
+            "--system-file",
+            file.to_str().unwrap(),
+        ]);
+        assert_eq!(system_message(from_file).await, "Answer in haiku.");
+
+        let both = ["Hi", "--system", "a", "--system-file", "b.md"];
+        assert!(Args::try_parse_from(["ragent", "run"].iter().chain(&both)).is_err());
+    }
+
+    #[tokio::test]
+    async fn wrap_width_falls_back_to_80_without_a_terminal() {
+        save_test_config("run-width");
+        let (runner, _) = prepare_runner(&run_args(&["Hi", "-c", "run-width"]), &None)
+            .await
+            .unwrap();
+        // cargo test pipes stdout, a terminal would report its own width
+        if !atty::is(atty::Stream::Stdout) {
+            assert_eq!(terminal_width(), 80);
+            assert_eq!(runner.options.render.wrap_len, 80);
+        }
+
+        let args = run_args(&["Hi", "-c", "run-width", "--width", "120"]);
+        let (runner, _) = prepare_runner(&args, &None).await.unwrap();
+        assert_eq!(runner.options.render.wrap_len, 120);
+    }
+
+    #[tokio::test]
+    async fn max_tool_output_flag_reaches_the_tools() {
+        save_test_config("run-max-output");
+        let (runner, _) = prepare_runner(&run_args(&["Hi", "-c", "run-max-output"]), &None)
+            .await
+            .unwrap();
+        assert_eq!(runner.options.tool_options.max_output, None);
+
+        let args = run_args(&["Hi", "-c", "run-max-output", "--max-tool-output", "2000"]);
+        let (runner, _) = prepare_runner(&args, &None).await.unwrap();
+        assert_eq!(runner.options.tool_options.max_output, Some(2000));
+    }
+
+    #[tokio::test]
+    async fn saved_output_is_the_returned_answer() {
+        save_test_config("run-save-output");
+        let file = temp_dir("save-output").join("answer.md");
+        let flags = [
+            "Explain it",
+            "-c",
+            "run-save-output",
+            "--save-output",
+            file.to_str().unwrap(),
+        ];
+        let chunks = [
+            "# Lifetimes\n\n",
+            "A **long** line ",
+            "that a terminal would wrap.\n",
+            "```rust\nfn f<'a>() {}\n```",
+        ];
+        let runner = scripted_runner(&flags, &chunks).await;
+        let transcript = runner.run(flags[0].to_string()).await.unwrap();
+
+        let args = run_args(&flags);
+        let path = args.save_output.as_deref().unwrap();
+        save_answer(path, OutputFormat::Text, &transcript.text)
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), transcript.text);
+        assert_eq!(transcript.text, chunks.concat());
+
+        // A second run replaces the file
+        save_answer(path, OutputFormat::Text, "Shorter.")
+            .await
+            .unwrap();
+        assert_eq!(std::fs::read_to_string(&file).unwrap(), "Shorter.");
+    }
+
+    #[tokio::test]
+    async fn context_file_ends_up_in_the_prompt() {
+        save_test_config("run-context");
+        let file = temp_dir("context-file").join("notes.md");
+        std::fs::write(&file, "The build uses cargo-make.").unwrap();
+        let file = file.to_str().unwrap();
+
+        let args = run_args(&[
+            "How is it built?",
+            "-c",
+            "run-context",
+            "--context-file",
+            file,
+        ]);
+        let context = resolve_context(&args, &None).await.unwrap();
+        assert_eq!(context.as_deref(), Some("The build uses cargo-make."));
+        let (runner, _) = prepare_runner(&args, &context).await.unwrap();
+        let (body, _) = runner.request_preview("How is it built?", &[]).unwrap();
+        assert_eq!(
+            body["messages"][1]["content"],
+            "Context: The build uses cargo-make.\n\n User: How is it built?"
+        );
+    }
+
+    #[tokio::test]
+    async fn piped_context_combines_as_asked() {
+        save_test_config("run-piped");
+        let file = temp_dir("piped-context").join("notes.md");
+        std::fs::write(&file, "The build uses cargo-make.").unwrap();
+        let file = file.to_str().unwrap();
+        let piped = Some("cat output".to_string());
+
+        // One source alone needs no flag, none at all gives no context
+        let plain = run_args(&["task", "-c", "run-piped"]);
+        assert_eq!(resolve_context(&plain, &piped).await.unwrap(), piped);
+        assert_eq!(resolve_context(&plain, &None).await.unwrap(), None);
+
+        // Both sources only go together when told how
+        let with_file = run_args(&["task", "-c", "run-piped", "--context-file", file]);
+        let err = resolve_context(&with_file, &piped).await.unwrap_err();
+        assert!(err.to_string().contains("--piped-context"), "{}", err);
+
+        for (how, context) in [
+            ("prepend", "cat output\n\nThe build uses cargo-make."),
+            ("append", "The build uses cargo-make.\n\ncat output"),
+            ("replace", "cat output"),
+        ] {
+            let args = run_args(&[
+                "task",
+                "-c",
+                "run-piped",
+                "--context-file",
+                file,
+                "--piped-context",
+                how,
+            ]);
+            let resolved = resolve_context(&args, &piped).await.unwrap();
+            assert_eq!(resolved.as_deref(), Some(context), "{}", how);
+
+            let (runner, _) = prepare_runner(&args, &resolved).await.unwrap();
+            let (body, _) = runner.request_preview("task", &[]).unwrap();
+            assert_eq!(
+                body["messages"][1]["content"],
+                format!("Context: {}\n\n User: task", context),
+                "{}",
+                how
+            );
+        }
+
+        // Without piped input the flag changes nothing
+        let args = run_args(&[
+            "task",
+            "-c",
+            "run-piped",
+            "--context-file",
+            file,
+            "--piped-context",
+            "replace",
+        ]);
+        assert_eq!(
+            resolve_context(&args, &None).await.unwrap().as_deref(),
+            Some("The build uses cargo-make.")
+        );
+    }
+
+    #[tokio::test]
+    async fn no_tools_leaves_the_tools_out_of_the_request() {
+        save_test_config("run-no-tools");
+        let (runner, _) = prepare_runner(&run_args(&["hi", "-c", "run-no-tools"]), &None)
+            .await
+            .unwrap();
+        let (body, _) = runner.request_preview("hi", &[]).unwrap();
+        let names: Vec<&str> = body["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["function"]["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"read_file_tool"), "{:?}", names);
+
+        let args = run_args(&["hi", "-c", "run-no-tools", "--no-tools"]);
+        let (runner, _) = prepare_runner(&args, &None).await.unwrap();
+        assert!(!runner.options.tools);
+        let (body, _) = runner.request_preview("hi", &[]).unwrap();
+        assert!(body.get("tools").is_none(), "{}", body);
+    }
+
+    /// A runner for `flags` answering every request with `chunks`, rendering nothing
+    async fn scripted_runner(flags: &[&str], chunks: &[&str]) -> RunnerContext {
+        let (mut runner, _) = prepare_runner(&run_args(flags), &None).await.unwrap();
+        runner.options.render.silent = true;
+        runner.script = Some(answer(chunks, Arc::new(AtomicUsize::new(0))));
+        runner
+    }
+
+    #[tokio::test]
+    async fn include_tools_adds_the_tool_trace() {
+        save_test_config("run-include-tools");
+        let runner = scripted_runner(&["hi", "-c", "run-include-tools"], &[]).await;
+        let transcript = Transcript {
+            text: "It is noon.".to_string(),
+            tool_calls: vec![
+                ToolCallRecord {
+                    name: "get_time_tool".to_string(),
+                    args: serde_json::json!({}),
+                    output: Some("12:00".to_string()),
+                },
+                // Cancelled before it returned
+                ToolCallRecord {
+                    name: "list_tool".to_string(),
+                    args: serde_json::json!({ "path": "." }),
+                    output: None,
+                },
+            ],
+            ..Transcript::default()
+        };
+        let json_of = |flags: &[&str]| {
+            let json = final_output(OutputFormat::Json, &transcript, &runner, &run_args(flags))
+                .unwrap()
+                .unwrap();
+            serde_json::from_str::<serde_json::Value>(&json).unwrap()
+        };
+
+        let compact = json_of(&["hi", "-c", "run-include-tools"]);
+        assert!(compact.get("tool_calls").is_none(), "{}", compact);
+        assert_eq!(compact["answer"], "It is noon.");
+
+        let traced = json_of(&["hi", "-c", "run-include-tools", "--include-tools"]);
+        assert_eq!(
+            traced["tool_calls"],
+            serde_json::json!([
+                { "name": "get_time_tool", "args": {}, "output": "12:00" },
+                { "name": "list_tool", "args": { "path": "." }, "output": null },
+            ])
+        );
+        assert_eq!(traced["answer"], "It is noon.");
+    }
+
+    #[tokio::test]
+    async fn json_mode_prints_one_parsable_object() {
+        save_test_config("run-json");
+        let flags = ["What time is it?", "-c", "run-json"];
+        let runner = scripted_runner(&flags, &["It is ", "noon."]).await;
+        let transcript = runner.run(flags[0].to_string()).await.unwrap();
+
+        let args = run_args(&flags);
+        let json = final_output(OutputFormat::Json, &transcript, &runner, &args)
+            .unwrap()
+            .unwrap();
+        let output: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(output["answer"], "It is noon.");
+        assert_eq!(output["model"], "qwen3-8b");
+        assert_eq!(output["session"], serde_json::Value::Null);
+        assert_eq!(output["cancelled"], false);
+        assert_eq!(output["usage"]["estimated"], true);
+        assert!(output["usage"]["prompt_tokens"].as_u64().unwrap() > 0);
+        assert!(output["usage"]["completion_tokens"].as_u64().unwrap() > 0);
+
+        let done = final_output(OutputFormat::Jsonl, &transcript, &runner, &args)
+            .unwrap()
+            .unwrap();
+        assert!(!done.contains('\n'));
+        let done: serde_json::Value = serde_json::from_str(&done).unwrap();
+        assert_eq!(done["type"], "done");
+        assert!(
+            final_output(OutputFormat::Text, &transcript, &runner, &args)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[tokio::test]
+    async fn config_stream_preference_picks_the_path() {
+        save_test_config("run-streaming");
+        let body =
+            std::fs::read_to_string(test_home().join("config").join("run-streaming.toml")).unwrap();
+        std::fs::write(
+            test_home().join("config").join("run-blocking.toml"),
+            format!("{}\nstream = false\n", body),
+        )
+        .unwrap();
+
+        for (flags, stream) in [
+            (&["Hi", "-c", "run-streaming"][..], true),
+            (&["Hi", "-c", "run-blocking"][..], false),
+            (&["Hi", "-c", "run-streaming", "--no-stream"][..], false),
+            (&["Hi", "-c", "run-blocking", "--no-stream"][..], false),
+        ] {
+            let (runner, _) = prepare_runner(&run_args(flags), &None).await.unwrap();
+            assert_eq!(runner.options.stream, stream, "{:?}", flags);
+            let (preview, _) = runner.request_preview("Hi", &[]).unwrap();
+            assert_eq!(preview["stream"], stream, "{:?}", flags);
+        }
+
+        std::fs::write(
+            test_home().join("config").join("run-bad-stream.toml"),
+            format!("{}\nstream = \"no\"\n", body),
+        )
+        .unwrap();
+        let err = prepare_runner(&run_args(&["Hi", "-c", "run-bad-stream"]), &None)
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            format!("{:#}", err).contains("stream must be true or false"),
+            "{:#}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn no_stream_gives_the_same_answer_and_session() {
+        save_test_config("run-no-stream");
+        let chunks = ["Streaming ", "or not, ", "same words."];
+        let mut saved = Vec::new();
+        for (session, extra) in [
+            ("run-streamed", None),
+            ("run-not-streamed", Some("--no-stream")),
+        ] {
+            let mut flags = vec!["Say it", "-c", "run-no-stream", "-s", session];
+            flags.extend(extra);
+            let runner = scripted_runner(&flags, &chunks).await;
+            assert_eq!(runner.options.stream, extra.is_none());
+
+            let mut session = Session::new(session, "qwen3-8b", Default::default());
+            let transcript = runner
+                .run_session("Say it".to_string(), &mut session)
+                .await
+                .unwrap();
+            assert_eq!(transcript.text, chunks.concat());
+            saved.push(load_session(&session.name).await.unwrap().messages);
+        }
+
+        let contents = |messages: &[Message]| -> Vec<Option<String>> {
+            messages.iter().map(|m| m.content.clone()).collect()
+        };
+        assert_eq!(contents(&saved[0]), contents(&saved[1]));
+        assert_eq!(saved[0].len(), 2);
+    }
+
+    #[tokio::test]
+    async fn dry_run_sends_nothing() {
+        save_test_config("run-dry");
+        let server = TestServer::start(vec![Reply::new(200, "{}")]).await;
+        let url = format!("{}/v1", server.url);
+        let flags = ["Summarize", "-c", "run-dry", "--url", &url, "--dry-run"];
+        let args = run_args(&flags);
+
+        run_agent(
+            "Summarize",
+            &args,
+            &Some("notes".to_string()),
+            OutputFormat::Json,
+        )
+        .await
+        .unwrap();
+        assert_eq!(server.hits(), 0);
+
+        let (runner, _) = prepare_runner(&args, &Some("notes".to_string()))
+            .await
+            .unwrap();
+        let history = [
+            Message::user_text("Earlier question"),
+            Message::assistant_text("Earlier answer"),
+        ];
+        let (body, estimated) = runner.request_preview("Summarize", &history).unwrap();
+        let printed = serde_json::to_string_pretty(&body).unwrap();
+        let parsed: serde_json::Value = serde_json::from_str(&printed).unwrap();
+        assert_eq!(parsed["model"], "qwen3-8b");
+        assert_eq!(parsed["messages"].as_array().unwrap().len(), 4);
+        assert_eq!(
+            parsed["messages"][3]["content"],
+            "Context: notes\n\n User: Summarize"
+        );
+        assert!(estimated > 0);
+        assert_eq!(server.hits(), 0);
+    }
+
+    #[tokio::test]
+    async fn show_config_reflects_the_cli_overrides() {
+        save_test_config("run-show-config");
+        let stored =
+            std::fs::read_to_string(test_home().join("config").join("run-show-config.toml"))
+                .unwrap();
+        let args = run_args(&[
+            "-c",
+            "run-show-config",
+            "--show-config",
+            "--model",
+            "llama-3.2-3b",
+            "--temperature",
+            "1.5",
+            "--system",
+            "Be terse.",
+        ]);
+        let (runner, _) = prepare_runner(&args, &None).await.unwrap();
+
+        let shown: toml::Table =
+            toml::from_str(&show_config(&runner.agent_config, OutputFormat::Text).unwrap())
+                .unwrap();
+        assert_eq!(shown["model"].as_str(), Some("llama-3.2-3b"));
+        assert_eq!(shown["temperature"].as_float(), Some(1.5));
+        assert_eq!(shown["system_prompt"].as_str(), Some("Be terse."));
+        // Not overridden, the config's value stands
+        assert_eq!(shown["url"].as_str(), Some("http://127.0.0.1:9/v1"));
+
+        let shown: serde_json::Value =
+            serde_json::from_str(&show_config(&runner.agent_config, OutputFormat::Json).unwrap())
+                .unwrap();
+        assert_eq!(shown["model"], "llama-3.2-3b");
+        assert_eq!(shown["temperature"], 1.5);
+
+        let on_disk =
+            std::fs::read_to_string(test_home().join("config").join("run-show-config.toml"))
+                .unwrap();
+        assert_eq!(on_disk, stored);
+    }
+
+    #[tokio::test]
+    async fn dry_run_and_show_config_never_print_the_api_key() {
+        let runner = test_runner(RunnerOptions::default()).await;
+
+        let (body, _) = runner.request_preview("hello", &[]).unwrap();
+        assert!(!body.to_string().contains(TEST_API_KEY));
+
+        for format in [OutputFormat::Text, OutputFormat::Json, OutputFormat::Jsonl] {
+            let shown = show_config(&runner.agent_config, format).unwrap();
+            assert!(!shown.contains(TEST_API_KEY), "{}", shown);
+            assert!(shown.contains("****cdef"), "{}", shown);
+        }
+    }
 }
@@ -0,0 +1,67 @@
+use crate::args::SessionCommands;
+use crate::core::session::{delete_session, load_session, rename_session};
+use anyhow::{Context, Result};
+use colored::Colorize;
+
+pub async fn run_session_command(command: &SessionCommands) -> Result<()> {
+    match command {
+        SessionCommands::Delete { name } => {
+            delete_session(name).await?;
+            println!("Deleted session: {}", name.to_string().green().bold());
+        }
+        SessionCommands::Rename { old, new } => {
+            rename_session(old, new).await?;
+            println!(
+                "Renamed session: {} -> {}",
+                old.to_string().yellow(),
+                new.to_string().green().bold()
+            );
+        }
+        SessionCommands::Info { name } => {
+            let session = load_session(name)
+                .await
+                .with_context(|| anyhow::anyhow!("Failed to load session '{}'", name))?;
+            let stats = session.stats();
+            println!("{}", session.name.to_string().magenta().bold());
+            println!(" Messages:   {}", stats.messages.to_string().cyan());
+            println!(" Tool calls: {}", stats.tool_calls.to_string().cyan());
+            println!(
+                " Tokens:     ~{}",
+                stats.estimated_tokens.to_string().cyan()
+            );
+            println!(" Model:      {}", stats.last_model_used.yellow());
+            println!(
+                " Created:    {}",
+                session.created_at.format("%Y-%m-%d %H:%M UTC")
+            );
+            println!(
+                " Updated:    {}",
+                stats.updated_at.format("%Y-%m-%d %H:%M UTC")
+            );
+            if let Some(average) = stats.average_response {
+                println!(
+                    " Avg reply:  {:.1}s",
+                    average.num_milliseconds() as f64 / 1000.0
+                );
+            }
+        }
+        SessionCommands::Export { name, output } => {
+            let session = load_session(name)
+                .await
+                .with_context(|| anyhow::anyhow!("Failed to load session '{}'", name))?;
+            let markdown = session.to_markdown();
+            match output {
+                Some(path) => {
+                    tokio::fs::write(path, markdown).await?;
+                    eprintln!(
+                        "Exported session {} to {}",
+                        name.to_string().green().bold(),
+                        path.display().to_string().yellow()
+                    );
+                }
+                None => print!("{}", markdown),
+            }
+        }
+    }
+    Ok(())
+}
@@ -1,5 +1,7 @@
+use crate::core::data::{StoredEntry, list_stored, write_atomic};
 use crate::core::tools::get_default_toolset;
 use anyhow::{Context, Result};
+use colored::Colorize;
 use forge::api::agents::{Agent, AgentBuilder};
 use std::path::PathBuf;
 use std::sync::Arc;
@@ -7,15 +9,18 @@ use tokio::fs;
 
 pub const SYSTEM_PROMPT: &str = r#"
 You are an expert AI coding agent operating inside a local software project.
+The project is {project_name} at {cwd}, on {os}.
 
 You have access to a set of tools that allow you to:
 - List files and directories in the current project
 - Tree-visual the project structure
 - Search code using ripgrep-style search
-- Read files from disk
+- Find files by glob pattern
+- Read files from disk, or a single function or type definition from a large file
+- Fetch web pages, such as documentation referenced in the code
 - Inspect git diffs, logs and repository state
 - Determine the current working directory
-- Check background process status
+- Start long-running commands in the background and check their status
 
 IMPORTANT TOOL GUIDELINES:
 - Use tools whenever information is needed from the project instead of asking the user
@@ -60,32 +65,32 @@ pub fn default_agents() -> Vec<Agent> {
     vec![
         AgentBuilder::new()
             .model("qwen/qwen3-8b")
-            .url("http://localhost:1234/v1")
-            .api_key("local")
+            .url(LM_STUDIO.url)
+            .api_key(LOCAL_API_KEY)
             .system_prompt(SYSTEM_PROMPT)
             .tool_registry(Arc::new(get_default_toolset()))
             .build()
             .unwrap(),
         AgentBuilder::new()
             .model("qwen/qwen3-vl-8b")
-            .url("http://localhost:1234/v1")
-            .api_key("local")
+            .url(LM_STUDIO.url)
+            .api_key(LOCAL_API_KEY)
             .system_prompt(SYSTEM_PROMPT)
             .tool_registry(Arc::new(get_default_toolset()))
             .build()
             .unwrap(),
         AgentBuilder::new()
             .model("zai-org/glm-4.6v-flash")
-            .url("http://localhost:1234/v1")
-            .api_key("local")
+            .url(LM_STUDIO.url)
+            .api_key(LOCAL_API_KEY)
             .system_prompt(SYSTEM_PROMPT)
             .tool_registry(Arc::new(get_default_toolset()))
             .build()
             .unwrap(),
         AgentBuilder::new()
             .model("qwen/qwen3-coder:free")
-            .url("https://openrouter.ai/v1")
-            .api_key("YOUR_OPENROUTER_API_KEY")
+            .url(OPENROUTER.url)
+            .api_key("${OPENROUTER_API_KEY}")
             .system_prompt(SYSTEM_PROMPT)
             .tool_registry(Arc::new(get_default_toolset()))
             .build()
@@ -100,11 +105,451 @@ pub async fn get_agent_configs(config_dir: PathBuf, model: &str) -> Result<Agent
     // Get the config in dir
     let file_path = config_dir.join(file_format);
     let config_data = tokio::fs::read_to_string(&file_path).await?;
-    let agent_builder = AgentBuilder::load_from_toml(&config_data)?;
-    let agent = agent_builder.build()?;
+    let agent_builder = parse_agent_builder(&config_data, &ConfigOverrides::default())?;
+    let mut agent = agent_builder.build()?;
+    agent.system_prompt = expand_prompt_variables(&agent.system_prompt);
     Ok(agent)
 }
 
+/// The key sent to local servers, which accept anything
+pub const LOCAL_API_KEY: &str = "local";
+
+/// Where a provider serves its OpenAI-compatible API and how it wants to be authenticated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ProviderDefaults {
+    pub url: &'static str,
+    /// Environment variable holding the key, `None` for local servers that need none
+    pub api_key_env: Option<&'static str>,
+}
+
+const LM_STUDIO: ProviderDefaults = ProviderDefaults {
+    url: "http://localhost:1234/v1",
+    api_key_env: None,
+};
+
+const OPENROUTER: ProviderDefaults = ProviderDefaults {
+    url: "https://openrouter.ai/api/v1",
+    api_key_env: Some("OPENROUTER_API_KEY"),
+};
+
+/// Servers known well enough to fill in the url and the API key for
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Provider {
+    /// LM Studio on localhost:1234
+    #[value(name = "lmstudio")]
+    LmStudio,
+    /// Ollama on localhost:11434
+    Ollama,
+    /// openrouter.ai, key from OPENROUTER_API_KEY
+    #[value(name = "openrouter")]
+    OpenRouter,
+    /// api.openai.com, key from OPENAI_API_KEY
+    #[value(name = "openai")]
+    OpenAi,
+}
+
+pub fn provider_defaults(provider: Provider) -> ProviderDefaults {
+    match provider {
+        Provider::LmStudio => LM_STUDIO,
+        Provider::Ollama => ProviderDefaults {
+            url: "http://localhost:11434/v1",
+            api_key_env: None,
+        },
+        Provider::OpenRouter => OPENROUTER,
+        Provider::OpenAi => ProviderDefaults {
+            url: "https://api.openai.com/v1",
+            api_key_env: Some("OPENAI_API_KEY"),
+        },
+    }
+}
+
+/// Named sampling settings for people who'd rather not pick numbers
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum SamplingPreset {
+    /// temperature 0.2, top_p 0.8
+    Precise,
+    /// temperature 0.7, top_p 0.9
+    Balanced,
+    /// temperature 1.0, top_p 0.95
+    Creative,
+}
+
+impl SamplingPreset {
+    /// `(temperature, top_p)`
+    pub fn sampling(self) -> (f32, f32) {
+        match self {
+            SamplingPreset::Precise => (0.2, 0.8),
+            SamplingPreset::Balanced => (0.7, 0.9),
+            SamplingPreset::Creative => (1.0, 0.95),
+        }
+    }
+}
+
+/// Per-run settings that take precedence over the stored config
+#[derive(Clone, Debug, Default)]
+pub struct ConfigOverrides {
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub top_p: Option<f32>,
+    pub max_tokens: Option<u32>,
+    /// Replaces the stored prompt, `system_prompt_append` included
+    pub system_prompt: Option<String>,
+    /// Replaces the stored url and API key with the provider's
+    pub provider: Option<Provider>,
+    /// Wins over the provider's url
+    pub url: Option<String>,
+}
+
+impl ConfigOverrides {
+    /// Fill in the temperature and top_p the preset stands for, explicit values are kept
+    pub fn with_preset(mut self, preset: Option<SamplingPreset>) -> Self {
+        if let Some(preset) = preset {
+            let (temperature, top_p) = preset.sampling();
+            self.temperature = self.temperature.or(Some(temperature));
+            self.top_p = self.top_p.or(Some(top_p));
+        }
+        self
+    }
+}
+
+impl ConfigOverrides {
+    /// Point the config at the provider, before the API key is resolved so its variable is read
+    fn apply_provider(&self, table: &mut toml::Table) {
+        let Some(provider) = self.provider else {
+            return;
+        };
+        let defaults = provider_defaults(provider);
+        table.insert(
+            "url".to_string(),
+            toml::Value::String(defaults.url.to_string()),
+        );
+        match defaults.api_key_env {
+            Some(var) => {
+                table.insert(
+                    "api_key_env".to_string(),
+                    toml::Value::String(var.to_string()),
+                );
+            }
+            None => {
+                table.remove("api_key_env");
+                table.insert(
+                    "api_key".to_string(),
+                    toml::Value::String(LOCAL_API_KEY.to_string()),
+                );
+            }
+        }
+    }
+
+    fn apply(&self, table: &mut toml::Table) {
+        if let Some(ref url) = self.url {
+            table.insert("url".to_string(), toml::Value::String(url.clone()));
+        }
+        if let Some(ref system_prompt) = self.system_prompt {
+            table.insert(
+                "system_prompt".to_string(),
+                toml::Value::String(system_prompt.clone()),
+            );
+            table.remove("system_prompt_append");
+        }
+        if let Some(ref model) = self.model {
+            table.insert("model".to_string(), toml::Value::String(model.clone()));
+        }
+        if let Some(temperature) = self.temperature {
+            table.insert(
+                "temperature".to_string(),
+                toml::Value::Float(temperature as f64),
+            );
+        }
+        if let Some(top_p) = self.top_p {
+            table.insert("top_p".to_string(), toml::Value::Float(top_p as f64));
+        }
+        if let Some(max_tokens) = self.max_tokens {
+            table.insert(
+                "max_tokens".to_string(),
+                toml::Value::Integer(max_tokens as i64),
+            );
+        }
+    }
+}
+
+/// Parse a stored agent config, resolving the API key from the environment when the config asks for it,
+/// then apply the overrides. Everything happens in memory, the file on disk is never touched.
+pub fn parse_agent_builder(config_body: &str, overrides: &ConfigOverrides) -> Result<AgentBuilder> {
+    let mut table: toml::Table = toml::from_str(config_body)?;
+    for key in ["stream", "context_window"] {
+        table.remove(key);
+    }
+    overrides.apply_provider(&mut table);
+    resolve_api_key(&mut table)?;
+    overrides.apply(&mut table);
+    validate_sampling(&table)?;
+    resolve_system_prompt(&mut table)?;
+    Ok(table.try_into()?)
+}
+
+/// Config keys only r_agent reads, forge's builder never sees them
+pub const R_AGENT_KEYS: [&str; 4] = [
+    "api_key_env",
+    "system_prompt_append",
+    "stream",
+    "context_window",
+];
+
+/// Context window assumed when neither the config nor `--context-size` gives one
+pub const DEFAULT_CONTEXT_WINDOW: usize = 8192;
+
+/// How the runner should talk to this config's server
+#[derive(Clone, Copy, Debug)]
+pub struct ConfigPreferences {
+    /// `stream = false` for servers that misbehave with streaming, streaming is the default
+    pub stream: bool,
+    /// `context_window = <tokens>`, the model's context size
+    pub context_window: Option<usize>,
+}
+
+pub fn config_preferences(config_body: &str) -> Result<ConfigPreferences> {
+    let table: toml::Table = toml::from_str(config_body)?;
+    let stream = match table.get("stream") {
+        Some(toml::Value::Boolean(stream)) => *stream,
+        Some(other) => anyhow::bail!("stream must be true or false, got: {}", other),
+        None => true,
+    };
+    let context_window = match table.get("context_window") {
+        Some(toml::Value::Integer(n)) if *n > 0 => Some(*n as usize),
+        Some(other) => anyhow::bail!("context_window must be a positive integer, got: {}", other),
+        None => None,
+    };
+    Ok(ConfigPreferences {
+        stream,
+        context_window,
+    })
+}
+
+/// A blank `system_prompt` falls back to [`SYSTEM_PROMPT`] instead of running without guidance,
+/// and `system_prompt_append` adds to it, so one extra line doesn't mean copying the whole default.
+/// Its variables are left in, they are expanded per run (see [`expand_prompt_variables`]).
+fn resolve_system_prompt(table: &mut toml::Table) -> Result<()> {
+    let blank = table
+        .get("system_prompt")
+        .and_then(|v| v.as_str())
+        .is_none_or(|prompt| prompt.trim().is_empty());
+    if blank {
+        eprintln!(
+            "{}",
+            "Warning: the config has no system prompt, using the default one".yellow()
+        );
+        table.insert(
+            "system_prompt".to_string(),
+            toml::Value::String(SYSTEM_PROMPT.to_string()),
+        );
+    }
+
+    match table.remove("system_prompt_append") {
+        Some(toml::Value::String(extra)) => {
+            if let Some(toml::Value::String(prompt)) = table.get_mut("system_prompt") {
+                *prompt = join_system_prompt(prompt, &extra);
+            }
+        }
+        Some(other) => anyhow::bail!("system_prompt_append must be a string, got: {}", other),
+        None => {}
+    }
+    Ok(())
+}
+
+/// Placeholders a system prompt may use, filled in when the agent is built
+pub const PROMPT_VARIABLES: [&str; 4] = ["cwd", "os", "date", "project_name"];
+
+/// Replace the [`PROMPT_VARIABLES`] in `prompt` with their value for this run.
+/// Any other `{...}` is left as written, prompts often quote code or JSON.
+pub fn expand_prompt_variables(prompt: &str) -> String {
+    let mut expanded = prompt.to_string();
+    for name in PROMPT_VARIABLES {
+        let placeholder = format!("{{{}}}", name);
+        if expanded.contains(&placeholder) {
+            expanded = expanded.replace(&placeholder, &prompt_variable(name));
+        }
+    }
+    expanded
+}
+
+fn prompt_variable(name: &str) -> String {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    match name {
+        "cwd" => cwd.display().to_string(),
+        "os" => std::env::consts::OS.to_string(),
+        "date" => chrono::Local::now().format("%Y-%m-%d").to_string(),
+        "project_name" => cwd
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_else(|| cwd.display().to_string()),
+        _ => format!("{{{}}}", name),
+    }
+}
+
+/// Rebuild `agent` with `extra` added after its system prompt
+pub fn append_system_prompt(agent: &Agent, extra: &str) -> Result<Agent> {
+    AgentBuilder::convert_to_builder(agent)
+        .system_prompt(&join_system_prompt(&agent.system_prompt, extra))
+        .build()
+}
+
+fn join_system_prompt(prompt: &str, extra: &str) -> String {
+    if extra.trim().is_empty() {
+        return prompt.to_string();
+    }
+    format!("{}\n\n{}", prompt.trim_end(), extra.trim())
+}
+
+/// Accepted ranges for the sampling parameters, wide enough for every legitimate extreme
+pub const TEMPERATURE_RANGE: std::ops::RangeInclusive<f64> = 0.0..=2.0;
+pub const TOP_P_RANGE: std::ops::RangeInclusive<f64> = 0.0..=1.0;
+
+/// Reject `temperature` and `top_p` values servers answer with a 400 (NaN and negatives included),
+/// naming the field and the value
+pub fn validate_sampling(table: &toml::Table) -> Result<()> {
+    for (key, range) in [("temperature", TEMPERATURE_RANGE), ("top_p", TOP_P_RANGE)] {
+        let value = match table.get(key) {
+            Some(toml::Value::Float(value)) => *value,
+            Some(toml::Value::Integer(value)) => *value as f64,
+            _ => continue,
+        };
+        if !range.contains(&value) {
+            anyhow::bail!(
+                "`{}` is {}, it must be between {} and {}",
+                key,
+                value,
+                range.start(),
+                range.end()
+            );
+        }
+    }
+    Ok(())
+}
+
+/// How a secret is shown on screen or in a log: all but its last 4 chars hidden.
+/// Short keys (like "local") would be mostly visible, they are hidden completely.
+pub fn mask_secret(secret: &str) -> String {
+    let len = secret.chars().count();
+    let visible: String = if len > 8 {
+        secret.chars().skip(len - 4).collect()
+    } else {
+        String::new()
+    };
+    format!("****{}", visible)
+}
+
+/// Mask the `api_key` of a config table before it is displayed. `${VAR}` placeholders name a variable,
+/// not a key, and are shown as-is
+pub fn mask_config_secrets(table: &mut toml::Table) {
+    if let Some(toml::Value::String(key)) = table.get_mut("api_key")
+        && !key.starts_with("${")
+    {
+        *key = mask_secret(key);
+    }
+}
+
+/// Supports `api_key_env = "VAR"` as well as `api_key = "${VAR}"`, a plain `api_key` is left as-is
+fn resolve_api_key(table: &mut toml::Table) -> Result<()> {
+    let env_var = match table.remove("api_key_env") {
+        Some(toml::Value::String(var)) => Some(var),
+        Some(other) => anyhow::bail!("api_key_env must be a string, got: {}", other),
+        None => table
+            .get("api_key")
+            .and_then(|v| v.as_str())
+            .and_then(|key| key.strip_prefix("${"))
+            .and_then(|key| key.strip_suffix('}'))
+            .map(|var| var.to_string()),
+    };
+
+    if let Some(var) = env_var {
+        let key = std::env::var(&var).map_err(|_| {
+            anyhow::anyhow!(
+                "The config reads its API key from ${}, but that environment variable is not set",
+                var
+            )
+        })?;
+        table.insert("api_key".to_string(), toml::Value::String(key));
+    }
+
+    Ok(())
+}
+
+/// Bring a config table back to a shape [`AgentBuilder`] accepts, returning what was changed.
+/// Fields of the wrong type are dropped (integers are kept as floats where a float is expected),
+/// missing required fields get the default agent's values and a missing `model` becomes `fallback_model`.
+pub fn repair_config_table(table: &mut toml::Table, fallback_model: &str) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    for key in [
+        "model",
+        "url",
+        "api_key",
+        "api_key_env",
+        "system_prompt",
+        "system_prompt_append",
+    ] {
+        if table.get(key).is_some_and(|v| !v.is_str()) {
+            table.remove(key);
+            changes.push(format!("dropped `{}`, it must be a string", key));
+        }
+    }
+    for key in ["temperature", "top_p"] {
+        match table.get(key) {
+            Some(toml::Value::Integer(n)) => {
+                let value = *n as f64;
+                table.insert(key.to_string(), toml::Value::Float(value));
+                changes.push(format!("turned `{}` into a float", key));
+            }
+            Some(toml::Value::Float(value)) if !value.is_finite() => {
+                table.remove(key);
+                changes.push(format!("dropped `{}`, it must be a number", key));
+            }
+            Some(toml::Value::Float(_)) | None => {}
+            Some(_) => {
+                table.remove(key);
+                changes.push(format!("dropped `{}`, it must be a number", key));
+            }
+        }
+    }
+    if table.get("max_tokens").is_some_and(|v| {
+        !v.as_integer()
+            .is_some_and(|n| n > 0 && n <= u32::MAX as i64)
+    }) {
+        table.remove("max_tokens");
+        changes.push("dropped `max_tokens`, it must be a positive integer".to_string());
+    }
+
+    if table
+        .get("model")
+        .and_then(|v| v.as_str())
+        .is_none_or(|model| model.trim().is_empty())
+    {
+        table.insert(
+            "model".to_string(),
+            toml::Value::String(fallback_model.to_string()),
+        );
+        changes.push(format!("set `model` to \"{}\"", fallback_model));
+    }
+
+    let defaults = [
+        ("url", LM_STUDIO.url),
+        ("api_key", LOCAL_API_KEY),
+        ("system_prompt", SYSTEM_PROMPT),
+    ];
+    for (key, default) in defaults {
+        // A key read from the environment stands in for `api_key`
+        if key == "api_key" && table.contains_key("api_key_env") {
+            continue;
+        }
+        if !table.contains_key(key) {
+            table.insert(key.to_string(), toml::Value::String(default.to_string()));
+            changes.push(format!("added the default `{}`", key));
+        }
+    }
+
+    changes
+}
+
 pub async fn save_default_agent_configs(agent: &Agent, path: PathBuf) -> Result<()> {
     let agent_str = AgentBuilder::convert_to_builder(agent).to_toml_string()?;
 
@@ -112,7 +557,7 @@ pub async fn save_default_agent_configs(agent: &Agent, path: PathBuf) -> Result<
     let sanitized_name = agent.model.replace("/", "_").replace(":", "_");
     let file_name = format!("{}.toml", sanitized_name);
     let file_path = path.join(file_name);
-    fs::write(file_path, agent_str).await?;
+    write_atomic(&file_path, agent_str).await?;
 
     Ok(())
 }
@@ -128,10 +573,35 @@ pub async fn load_config(agent_config: String) -> Result<String> {
     Ok(config_body)
 }
 
-pub fn get_default_config_path() -> Result<PathBuf> {
+pub async fn delete_config(agent_config: &str) -> Result<()> {
+    let config_path = config_file_path(agent_config)?;
+    if !config_path.exists() {
+        anyhow::bail!("Config '{}' does not exist", agent_config);
+    }
+    fs::remove_file(&config_path).await?;
+    Ok(())
+}
+
+pub fn config_file_path(agent_config: &str) -> Result<PathBuf> {
+    Ok(get_default_config_path()?.join(format!("{}.toml", agent_config)))
+}
+
+pub async fn list_configs() -> Result<Vec<StoredEntry>> {
+    list_stored(&get_default_config_path()?, "toml").await
+}
+
+/// Root of every r_agent dotfile, `R_AGENT_HOME` overrides the default `~/.config/r_agent`
+pub fn get_base_path() -> Result<PathBuf> {
+    if let Some(dir) = std::env::var_os("R_AGENT_HOME").filter(|d| !d.is_empty()) {
+        return Ok(PathBuf::from(dir));
+    }
     let home_dir =
         dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
-    let config_path = home_dir.join(".config").join("r_agent").join("config");
+    Ok(home_dir.join(".config").join("r_agent"))
+}
+
+pub fn get_default_config_path() -> Result<PathBuf> {
+    let config_path = get_base_path()?.join("config");
     Ok(config_path)
 }
 
@@ -141,3 +611,381 @@ pub async fn create_config_dir() -> Result<PathBuf> {
 
     Ok(config_path)
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::path::Path;
+    use std::sync::LazyLock;
+
+    static TEST_HOME: LazyLock<PathBuf> = LazyLock::new(|| {
+        let home = crate::core::data::tests::temp_dir("home");
+        std::fs::create_dir_all(home.join("config")).unwrap();
+        std::fs::create_dir_all(home.join("sessions")).unwrap();
+        // SAFETY: set once, before any test reads it, and never changed afterwards
+        unsafe { std::env::set_var("R_AGENT_HOME", &home) };
+        home
+    });
+
+    /// The `R_AGENT_HOME` every test shares, with empty `config` and `sessions` dirs.
+    /// Tests pick unique config and session names, they run in parallel.
+    pub(crate) fn test_home() -> &'static Path {
+        &TEST_HOME
+    }
+
+    #[tokio::test]
+    async fn r_agent_home_moves_every_path() {
+        let home = test_home();
+        assert_eq!(get_base_path().unwrap(), home);
+        assert_eq!(get_default_config_path().unwrap(), home.join("config"));
+        assert_eq!(
+            config_file_path("qwen3-8b").unwrap(),
+            home.join("config").join("qwen3-8b.toml")
+        );
+        assert_eq!(
+            crate::core::session::get_default_session_path().unwrap(),
+            home.join("sessions")
+        );
+        let data = crate::core::data::create_data_source().await.unwrap();
+        assert_eq!(data, home.join("data"));
+        assert!(data.is_dir());
+    }
+
+    const STORED_CONFIG: &str = r#"
+        model = "qwen3-8b"
+        url = "http://localhost:1234/v1"
+        api_key = "local"
+        system_prompt = "Be brief."
+        temperature = 0.5
+        top_p = 0.9
+    "#;
+
+    #[tokio::test]
+    async fn overrides_change_the_agent_but_not_the_file() {
+        test_home();
+        let path = config_file_path("config-overrides").unwrap();
+        std::fs::write(&path, STORED_CONFIG).unwrap();
+
+        let overrides = ConfigOverrides {
+            model: Some("llama-3.2-3b".to_string()),
+            temperature: Some(1.2),
+            max_tokens: Some(256),
+            ..Default::default()
+        };
+        let body = load_config("config-overrides".to_string()).await.unwrap();
+        let agent = parse_agent_builder(&body, &overrides)
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(agent.model, "llama-3.2-3b");
+        assert_eq!(agent.temperature, 1.2);
+        assert_eq!(agent.max_tokens, Some(256));
+        // Not overridden, the config's value stands
+        assert_eq!(agent.top_p, 0.9);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), STORED_CONFIG);
+
+        let agent = parse_agent_builder(&body, &ConfigOverrides::default())
+            .unwrap()
+            .build()
+            .unwrap();
+        assert_eq!(agent.model, "qwen3-8b");
+        assert_eq!(agent.temperature, 0.5);
+        assert_eq!(agent.max_tokens, None);
+    }
+
+    #[test]
+    fn mask_secret_shows_only_the_end_of_long_keys() {
+        assert_eq!(mask_secret("sk-or-v1-abcdef123456"), "****3456");
+        assert_eq!(mask_secret("123456789"), "****6789");
+    }
+
+    #[test]
+    fn mask_secret_hides_short_keys_completely() {
+        assert_eq!(mask_secret(""), "****");
+        assert_eq!(mask_secret("local"), "****");
+        assert_eq!(mask_secret("12345678"), "****");
+    }
+
+    #[test]
+    fn mask_config_secrets_leaves_placeholders_alone() {
+        let mut table: toml::Table = toml::from_str(r#"api_key = "${OPENAI_API_KEY}""#).unwrap();
+        mask_config_secrets(&mut table);
+        assert_eq!(table["api_key"].as_str(), Some("${OPENAI_API_KEY}"));
+
+        let mut table: toml::Table = toml::from_str(r#"api_key = "sk-abcdefghijkl""#).unwrap();
+        mask_config_secrets(&mut table);
+        assert_eq!(table["api_key"].as_str(), Some("****ijkl"));
+    }
+
+    fn with_sampling(temperature: f32, top_p: f32) -> Result<AgentBuilder> {
+        let overrides = ConfigOverrides {
+            temperature: Some(temperature),
+            top_p: Some(top_p),
+            ..Default::default()
+        };
+        parse_agent_builder(STORED_CONFIG, &overrides)
+    }
+
+    #[test]
+    fn sampling_boundaries_are_accepted() {
+        for (temperature, top_p) in [(0.0, 0.0), (2.0, 1.0), (0.7, 0.95)] {
+            let agent = with_sampling(temperature, top_p).unwrap().build().unwrap();
+            assert_eq!(agent.temperature, temperature);
+            assert_eq!(agent.top_p, top_p);
+        }
+    }
+
+    #[test]
+    fn out_of_range_sampling_names_the_field() {
+        let err = with_sampling(2.5, 0.9).err().unwrap().to_string();
+        assert!(err.starts_with("`temperature` is 2.5"), "{}", err);
+        assert!(err.ends_with("between 0 and 2"), "{}", err);
+
+        let err = with_sampling(-0.5, 0.9).err().unwrap().to_string();
+        assert!(err.starts_with("`temperature` is -0.5"), "{}", err);
+
+        let err = with_sampling(0.5, 1.5).err().unwrap().to_string();
+        assert!(err.starts_with("`top_p` is 1.5"), "{}", err);
+
+        let err = with_sampling(0.5, f32::NAN).err().unwrap().to_string();
+        assert!(err.starts_with("`top_p` is NaN"), "{}", err);
+
+        // Straight from the file, just past the bounds
+        for (line, message) in [
+            ("temperature = 2.01", "`temperature` is 2.01"),
+            ("temperature = -0.01", "`temperature` is -0.01"),
+            ("temperature = nan", "`temperature` is NaN"),
+        ] {
+            let body = STORED_CONFIG.replace("temperature = 0.5", line);
+            let err = parse_agent_builder(&body, &ConfigOverrides::default())
+                .err()
+                .unwrap();
+            assert!(err.to_string().starts_with(message), "{}", err);
+        }
+    }
+
+    #[test]
+    fn presets_set_their_documented_sampling() {
+        for (preset, temperature, top_p) in [
+            (SamplingPreset::Precise, 0.2, 0.8),
+            (SamplingPreset::Balanced, 0.7, 0.9),
+            (SamplingPreset::Creative, 1.0, 0.95),
+        ] {
+            let overrides = ConfigOverrides::default().with_preset(Some(preset));
+            let agent = parse_agent_builder(STORED_CONFIG, &overrides)
+                .unwrap()
+                .build()
+                .unwrap();
+            assert_eq!((agent.temperature, agent.top_p), (temperature, top_p));
+        }
+
+        // An explicit value beats the preset, the preset fills in the rest
+        let overrides = ConfigOverrides {
+            temperature: Some(0.0),
+            ..Default::default()
+        }
+        .with_preset(Some(SamplingPreset::Creative));
+        assert_eq!(overrides.temperature, Some(0.0));
+        assert_eq!(overrides.top_p, Some(0.95));
+
+        let untouched = ConfigOverrides::default().with_preset(None);
+        assert_eq!((untouched.temperature, untouched.top_p), (None, None));
+    }
+
+    #[test]
+    fn each_provider_maps_to_its_defaults() {
+        // SAFETY: variables only this test reads
+        unsafe {
+            std::env::set_var("OPENROUTER_API_KEY", "sk-or-test");
+            std::env::set_var("OPENAI_API_KEY", "sk-openai-test");
+        }
+        for (name, url, api_key_env, api_key) in [
+            ("lmstudio", "http://localhost:1234/v1", None, LOCAL_API_KEY),
+            ("ollama", "http://localhost:11434/v1", None, LOCAL_API_KEY),
+            (
+                "openrouter",
+                "https://openrouter.ai/api/v1",
+                Some("OPENROUTER_API_KEY"),
+                "sk-or-test",
+            ),
+            (
+                "openai",
+                "https://api.openai.com/v1",
+                Some("OPENAI_API_KEY"),
+                "sk-openai-test",
+            ),
+        ] {
+            let provider = <Provider as clap::ValueEnum>::from_str(name, false).unwrap();
+            let defaults = provider_defaults(provider);
+            assert_eq!((defaults.url, defaults.api_key_env), (url, api_key_env));
+
+            let overrides = ConfigOverrides {
+                provider: Some(provider),
+                ..Default::default()
+            };
+            let agent = parse_agent_builder(STORED_CONFIG, &overrides)
+                .unwrap()
+                .build()
+                .unwrap();
+            assert_eq!(agent.url, url);
+            assert_eq!(agent.api_key, api_key);
+            assert_eq!(agent.model, "qwen3-8b");
+
+            // An explicit url still wins
+            let overrides = ConfigOverrides {
+                url: Some("http://gpu-box:8080/v1".to_string()),
+                ..overrides
+            };
+            let agent = parse_agent_builder(STORED_CONFIG, &overrides)
+                .unwrap()
+                .build()
+                .unwrap();
+            assert_eq!(agent.url, "http://gpu-box:8080/v1");
+            assert_eq!(agent.api_key, api_key);
+        }
+    }
+
+    fn resolved_key(config: &str) -> Result<Option<String>> {
+        let mut table: toml::Table = toml::from_str(config).unwrap();
+        resolve_api_key(&mut table)?;
+        assert!(!table.contains_key("api_key_env"));
+        Ok(table
+            .get("api_key")
+            .and_then(|v| v.as_str())
+            .map(str::to_string))
+    }
+
+    #[test]
+    fn api_key_is_read_from_the_environment() {
+        // SAFETY: a variable only this test reads
+        unsafe { std::env::set_var("R_AGENT_TEST_KEY", "sk-from-env") };
+        assert_eq!(
+            resolved_key(r#"api_key = "${R_AGENT_TEST_KEY}""#).unwrap(),
+            Some("sk-from-env".to_string())
+        );
+        assert_eq!(
+            resolved_key(
+                r#"
+                api_key = "stale"
+                api_key_env = "R_AGENT_TEST_KEY"
+                "#
+            )
+            .unwrap(),
+            Some("sk-from-env".to_string())
+        );
+    }
+
+    #[test]
+    fn missing_key_variable_is_an_error() {
+        let err = resolved_key(r#"api_key = "${R_AGENT_TEST_UNSET_KEY}""#).unwrap_err();
+        assert!(
+            err.to_string().contains("$R_AGENT_TEST_UNSET_KEY"),
+            "{}",
+            err
+        );
+        assert!(resolved_key(r#"api_key_env = "R_AGENT_TEST_UNSET_KEY""#).is_err());
+        assert!(resolved_key("api_key_env = 1").is_err());
+    }
+
+    #[test]
+    fn literal_api_keys_pass_through() {
+        assert_eq!(
+            resolved_key(r#"api_key = "sk-literal""#).unwrap(),
+            Some("sk-literal".to_string())
+        );
+        assert_eq!(
+            resolved_key(r#"api_key = "${unterminated""#).unwrap(),
+            Some("${unterminated".to_string())
+        );
+        assert_eq!(resolved_key("").unwrap(), None);
+    }
+
+    fn system_prompt_of(config: &str) -> String {
+        let mut table: toml::Table = toml::from_str(config).unwrap();
+        resolve_system_prompt(&mut table).unwrap();
+        assert!(!table.contains_key("system_prompt_append"));
+        table["system_prompt"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn blank_system_prompt_falls_back_to_the_default() {
+        assert_eq!(system_prompt_of(""), SYSTEM_PROMPT);
+        assert_eq!(
+            system_prompt_of(r#"system_prompt = "  \n ""#),
+            SYSTEM_PROMPT
+        );
+        assert_eq!(
+            system_prompt_of(r#"system_prompt = "Be brief.""#),
+            "Be brief."
+        );
+    }
+
+    #[test]
+    fn system_prompt_append_adds_a_paragraph() {
+        let prompt = system_prompt_of(
+            r#"
+            system_prompt = "Be brief.\n"
+            system_prompt_append = "  Answer in French. "
+            "#,
+        );
+        assert_eq!(prompt, "Be brief.\n\nAnswer in French.");
+
+        let prompt = system_prompt_of(r#"system_prompt_append = "Answer in French.""#);
+        assert!(prompt.starts_with(SYSTEM_PROMPT.trim_end()));
+        assert!(prompt.ends_with("\n\nAnswer in French."));
+
+        let prompt = system_prompt_of(
+            r#"
+            system_prompt = "Be brief."
+            system_prompt_append = " "
+            "#,
+        );
+        assert_eq!(prompt, "Be brief.");
+
+        let mut table: toml::Table = toml::from_str("system_prompt_append = 3").unwrap();
+        assert!(resolve_system_prompt(&mut table).is_err());
+    }
+
+    #[test]
+    fn each_prompt_variable_is_substituted() {
+        let cwd = std::env::current_dir().unwrap();
+        let expand = expand_prompt_variables;
+
+        assert_eq!(expand("In {cwd}."), format!("In {}.", cwd.display()));
+        assert_eq!(expand("On {os}."), format!("On {}.", std::env::consts::OS));
+        let today = chrono::Local::now().format("%Y-%m-%d").to_string();
+        assert_eq!(expand("Today is {date}."), format!("Today is {}.", today));
+        // cargo runs the tests from the crate's root
+        let project = cwd.file_name().unwrap().to_str().unwrap();
+        assert_eq!(
+            expand("Working on {project_name}."),
+            format!("Working on {}.", project)
+        );
+        assert_eq!(
+            expand("{os} and {os}"),
+            format!("{0} and {0}", std::env::consts::OS)
+        );
+
+        // Not a variable, left as written
+        for text in [
+            "Hello {user}.",
+            r#"Reply with {"ok": true}."#,
+            "{ cwd }",
+            "{CWD}",
+        ] {
+            assert_eq!(expand(text), text);
+        }
+    }
+
+    #[test]
+    fn resolved_system_prompt_keeps_its_variables_for_each_run() {
+        let prompt = system_prompt_of(
+            r#"
+            system_prompt = "Working in {project_name}."
+            system_prompt_append = "Today is {date}."
+            "#,
+        );
+        assert_eq!(prompt, "Working in {project_name}.\n\nToday is {date}.");
+        assert!(!expand_prompt_variables(&prompt).contains('{'));
+    }
+}
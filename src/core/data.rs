@@ -1,13 +1,153 @@
+use crate::core::config::get_base_path;
 use anyhow::Result;
-use std::path::PathBuf;
+use chrono::{DateTime, Local};
+use std::path::{Path, PathBuf};
 use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// A config or session file, named after its file stem
+#[derive(Clone, Debug)]
+pub struct StoredEntry {
+    pub name: String,
+    pub path: PathBuf,
+    pub modified: DateTime<Local>,
+}
 
 pub async fn create_data_source() -> Result<PathBuf> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
-    let source_path = home_dir.join(".config").join("r_agent").join("data");
+    let source_path = get_base_path()?.join("data");
 
     fs::create_dir_all(&source_path).await?;
 
     Ok(source_path)
 }
+
+/// Replace `path` with `contents` through a temporary file in the same directory and a rename,
+/// so a crash mid-write leaves either the old file or the new one, never a truncated mix.
+/// An existing file keeps its permissions.
+pub async fn write_atomic(path: &Path, contents: impl AsRef<[u8]>) -> Result<()> {
+    let file_name = path
+        .file_name()
+        .ok_or_else(|| anyhow::anyhow!("{} is not a file path", path.display()))?;
+    let mut temp_name = std::ffi::OsString::from(".");
+    temp_name.push(file_name);
+    temp_name.push(format!(".{}.tmp", std::process::id()));
+    let temp_path = path.with_file_name(temp_name);
+
+    let written = async {
+        let mut file = fs::File::create(&temp_path).await?;
+        file.write_all(contents.as_ref()).await?;
+        file.sync_all().await?;
+        if let Ok(metadata) = fs::metadata(path).await {
+            fs::set_permissions(&temp_path, metadata.permissions()).await?;
+        }
+        fs::rename(&temp_path, path).await
+    }
+    .await;
+    if written.is_err() {
+        let _ = fs::remove_file(&temp_path).await;
+    }
+    Ok(written?)
+}
+
+/// List the files in `dir` with the given extension, sorted by name
+pub async fn list_stored(dir: &Path, extension: &str) -> Result<Vec<StoredEntry>> {
+    let mut entries = Vec::new();
+    let mut read_dir = fs::read_dir(dir).await?;
+
+    while let Some(entry) = read_dir.next_entry().await? {
+        let path = entry.path();
+        if path.extension().and_then(|e| e.to_str()) != Some(extension) {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()) else {
+            continue;
+        };
+        let modified = entry.metadata().await?.modified()?;
+        entries.push(StoredEntry {
+            name: name.to_string(),
+            path: path.clone(),
+            modified: DateTime::<Local>::from(modified),
+        });
+    }
+
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// A fresh, empty directory under the system temp dir, unique to `name` and this test run
+    pub(crate) fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("r_agent-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn file_names(dir: &Path) -> Vec<String> {
+        let mut names: Vec<String> = std::fs::read_dir(dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().to_string_lossy().into_owned())
+            .collect();
+        names.sort();
+        names
+    }
+
+    #[tokio::test]
+    async fn listing_keeps_matching_files_sorted_by_name() {
+        let dir = temp_dir("list-stored");
+        for name in ["qwen3-8b.toml", "llama.toml", "notes.txt", "session.json"] {
+            std::fs::write(dir.join(name), "").unwrap();
+        }
+        std::fs::create_dir(dir.join("backups")).unwrap();
+
+        let entries = list_stored(&dir, "toml").await.unwrap();
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["llama", "qwen3-8b"]);
+        assert_eq!(entries[0].path, dir.join("llama.toml"));
+        let age = Local::now() - entries[0].modified;
+        assert!(age < chrono::Duration::minutes(1), "{}", age);
+
+        let sessions = list_stored(&dir, "json").await.unwrap();
+        assert_eq!(sessions.len(), 1);
+        assert!(list_stored(&dir.join("missing"), "json").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn write_atomic_replaces_the_file_and_cleans_up() {
+        let dir = temp_dir("write-atomic");
+        let path = dir.join("config.toml");
+
+        write_atomic(&path, "first").await.unwrap();
+        write_atomic(&path, "second").await.unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "second");
+        assert_eq!(file_names(&dir), ["config.toml"]);
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o600)).unwrap();
+            write_atomic(&path, "third").await.unwrap();
+            let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_rename_leaves_the_original_alone() {
+        let dir = temp_dir("write-atomic-fail");
+        // Nothing can be renamed over a non-empty directory, the write fails after the temp file was written
+        let path = dir.join("session.json");
+        std::fs::create_dir(&path).unwrap();
+        std::fs::write(path.join("keep"), "original").unwrap();
+
+        assert!(write_atomic(&path, "new").await.is_err());
+        assert_eq!(
+            std::fs::read_to_string(path.join("keep")).unwrap(),
+            "original"
+        );
+        assert_eq!(file_names(&dir), ["session.json"]);
+    }
+}
@@ -0,0 +1,54 @@
+use anyhow::Result;
+use serde_json::Value;
+use std::path::PathBuf;
+use tokio::fs;
+use tokio::io::AsyncWriteExt;
+
+/// Once the log grows past this it is moved to `<path>.1` (replacing the previous one) and started over
+pub const MAX_LOG_BYTES: u64 = 10 * 1024 * 1024;
+
+/// Set to a path to log every exchange without passing `--log-file`
+pub const LOG_FILE_ENV: &str = "R_AGENT_LOG_FILE";
+
+/// Appends one JSON object per line for every exchange with the server, whatever the terminal shows.
+/// At most two files exist at a time, the current one and the rotated `<path>.1`.
+#[derive(Clone, Debug)]
+pub struct DebugLog {
+    path: PathBuf,
+}
+
+impl DebugLog {
+    pub fn new(path: PathBuf) -> Self {
+        DebugLog { path }
+    }
+
+    /// `path` when given, otherwise the file named by [`LOG_FILE_ENV`]
+    pub fn resolve(path: Option<PathBuf>) -> Option<Self> {
+        path.or_else(|| {
+            std::env::var_os(LOG_FILE_ENV)
+                .filter(|p| !p.is_empty())
+                .map(PathBuf::from)
+        })
+        .map(DebugLog::new)
+    }
+
+    pub async fn append(&self, entry: &Value) -> Result<()> {
+        if let Ok(metadata) = fs::metadata(&self.path).await
+            && metadata.len() > MAX_LOG_BYTES
+        {
+            let mut rotated = self.path.clone().into_os_string();
+            rotated.push(".1");
+            fs::rename(&self.path, rotated).await?;
+        }
+
+        let mut line = serde_json::to_string(entry)?;
+        line.push('\n');
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .await?;
+        file.write_all(line.as_bytes()).await?;
+        Ok(())
+    }
+}
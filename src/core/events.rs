@@ -0,0 +1,193 @@
+use anyhow::Result;
+use futures_util::stream::{self, Stream, StreamExt};
+use serde::Serialize;
+use serde_json::{Value, json};
+use std::future::Future;
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+
+/// Everything that happens during a turn, in the order it happens
+#[derive(Clone, Debug)]
+pub enum AgentEvent {
+    /// A chunk of the assistant's answer
+    Text(String),
+    /// The model asked for a tool, sent before the tool runs
+    ToolCall { name: String, args: Value },
+    /// What the tool handed back to the model
+    ToolResult { name: String, output: String },
+    /// The connection dropped and the answer was generated anew, the text before it is discarded
+    Restarted,
+    /// The answer is complete
+    Final,
+}
+
+impl AgentEvent {
+    /// One self-describing object per event, `type` tells them apart (the shape of `--format jsonl`)
+    pub fn to_json(&self) -> Value {
+        match self {
+            AgentEvent::Text(text) => json!({ "type": "text", "text": text }),
+            AgentEvent::ToolCall { name, args } => {
+                json!({ "type": "tool_call", "name": name, "args": args })
+            }
+            AgentEvent::ToolResult { name, output } => {
+                json!({ "type": "tool_result", "name": name, "output": output })
+            }
+            AgentEvent::Restarted => json!({ "type": "restarted" }),
+            AgentEvent::Final => json!({ "type": "final" }),
+        }
+    }
+}
+
+pub type EventSender = UnboundedSender<AgentEvent>;
+
+/// A tool call of the turn, `output` stays empty when the turn stopped before the tool returned
+#[derive(Clone, Debug, Serialize)]
+pub struct ToolCallRecord {
+    pub name: String,
+    pub args: Value,
+    pub output: Option<String>,
+}
+
+/// What a turn produced, collected from its events
+#[derive(Clone, Debug, Default)]
+pub struct Transcript {
+    pub text: String,
+    pub tool_calls: Vec<ToolCallRecord>,
+    /// Estimated size of the request in tokens
+    pub prompt_tokens: usize,
+}
+
+impl Transcript {
+    pub fn record(&mut self, event: &AgentEvent) {
+        match event {
+            AgentEvent::Text(chunk) => self.text.push_str(chunk),
+            AgentEvent::ToolCall { name, args } => self.tool_calls.push(ToolCallRecord {
+                name: name.clone(),
+                args: args.clone(),
+                output: None,
+            }),
+            AgentEvent::ToolResult { name, output } => {
+                if let Some(call) = self
+                    .tool_calls
+                    .iter_mut()
+                    .rev()
+                    .find(|c| c.name == *name && c.output.is_none())
+                {
+                    call.output = Some(output.clone());
+                }
+            }
+            AgentEvent::Restarted => self.text.clear(),
+            AgentEvent::Final => {}
+        }
+    }
+}
+
+/// Merge the tool events reported by the tools with the events of the answer (its text chunks).
+/// `request` resolves once forge is done with the tool iterations, so tool events come first
+/// and the stream ends with [`AgentEvent::Final`] after the last chunk.
+pub fn agent_events<F, S>(
+    request: F,
+    mut tool_events: UnboundedReceiver<AgentEvent>,
+) -> impl Stream<Item = Result<AgentEvent>>
+where
+    F: Future<Output = Result<S>>,
+    S: Stream<Item = Result<AgentEvent>>,
+{
+    let tool_events = stream::poll_fn(move |cx| tool_events.poll_recv(cx)).map(Ok);
+
+    let answer = stream::once(request).flat_map(|response| match response {
+        Ok(events) => events
+            .chain(stream::once(async { Ok(AgentEvent::Final) }))
+            .left_stream(),
+        Err(e) => stream::once(async { Err(e) }).right_stream(),
+    });
+
+    // Tool events already reported go out before any text that is ready at the same time
+    stream::select_with_strategy(tool_events, answer, |_: &mut ()| stream::PollNext::Left)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The events as `type` strings, text chunks by their content
+    fn kinds(events: &[AgentEvent]) -> Vec<String> {
+        events
+            .iter()
+            .map(|event| match event {
+                AgentEvent::Text(text) => text.clone(),
+                other => other.to_json()["type"].as_str().unwrap().to_string(),
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn tool_events_come_before_the_answer() {
+        let (sender, receiver) = tokio::sync::mpsc::unbounded_channel();
+        // Like forge: the tool runs (and reports itself) before the answer is streamed
+        let request = async move {
+            sender
+                .send(AgentEvent::ToolCall {
+                    name: "time_tool".into(),
+                    args: json!({}),
+                })
+                .unwrap();
+            sender
+                .send(AgentEvent::ToolResult {
+                    name: "time_tool".into(),
+                    output: "12:00".into(),
+                })
+                .unwrap();
+            // The request for the answer after the tool ran
+            tokio::task::yield_now().await;
+            let chunks: Vec<Result<AgentEvent>> = vec![
+                Ok(AgentEvent::Text("It is ".into())),
+                Ok(AgentEvent::Text("noon.".into())),
+            ];
+            Ok(stream::iter(chunks))
+        };
+
+        let events: Vec<AgentEvent> = agent_events(request, receiver)
+            .map(|event| event.unwrap())
+            .collect()
+            .await;
+        assert_eq!(
+            kinds(&events),
+            ["tool_call", "tool_result", "It is ", "noon.", "final"]
+        );
+
+        let mut transcript = Transcript::default();
+        events.iter().for_each(|event| transcript.record(event));
+        assert_eq!(transcript.text, "It is noon.");
+        assert_eq!(transcript.tool_calls.len(), 1);
+        assert_eq!(transcript.tool_calls[0].output.as_deref(), Some("12:00"));
+    }
+
+    #[tokio::test]
+    async fn a_failed_request_ends_the_stream_with_its_error() {
+        // No tools ran, the sender is already gone like the toolset of a request that failed
+        let (_, receiver) = tokio::sync::mpsc::unbounded_channel();
+        let request = async {
+            Err::<stream::Empty<Result<AgentEvent>>, _>(anyhow::anyhow!("server said no"))
+        };
+        let events: Vec<Result<AgentEvent>> = agent_events(request, receiver).collect().await;
+        assert_eq!(events.len(), 1);
+        assert_eq!(
+            events[0].as_ref().unwrap_err().to_string(),
+            "server said no"
+        );
+    }
+
+    #[test]
+    fn restarted_drops_the_text_before_it() {
+        let mut transcript = Transcript::default();
+        for event in [
+            AgentEvent::Text("The old ans".into()),
+            AgentEvent::Restarted,
+            AgentEvent::Text("The new answer".into()),
+            AgentEvent::Final,
+        ] {
+            transcript.record(&event);
+        }
+        assert_eq!(transcript.text, "The new answer");
+    }
+}
@@ -0,0 +1,300 @@
+use colored::Colorize;
+
+/// What the highlighter knows about a language, enough to color keywords, strings, comments and numbers
+pub struct Language {
+    keywords: &'static [&'static str],
+    /// Everything after one of these is a comment, block comments are only colored up to the end of their line
+    comments: &'static [&'static str],
+    quotes: &'static [char],
+}
+
+const RUST: Language = Language {
+    keywords: &[
+        "as", "async", "await", "break", "const", "continue", "crate", "dyn", "else", "enum",
+        "extern", "false", "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move",
+        "mut", "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+        "true", "type", "unsafe", "use", "where", "while",
+    ],
+    comments: &["//", "/*"],
+    // '...' is left alone, it is as often a lifetime as a char
+    quotes: &['"'],
+};
+
+const PYTHON: Language = Language {
+    keywords: &[
+        "and", "as", "assert", "async", "await", "break", "class", "continue", "def", "del",
+        "elif", "else", "except", "False", "finally", "for", "from", "global", "if", "import",
+        "in", "is", "lambda", "None", "nonlocal", "not", "or", "pass", "raise", "return", "True",
+        "try", "while", "with", "yield",
+    ],
+    comments: &["#"],
+    quotes: &['"', '\''],
+};
+
+const JAVASCRIPT: Language = Language {
+    keywords: &[
+        "async",
+        "await",
+        "break",
+        "case",
+        "catch",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "else",
+        "export",
+        "extends",
+        "false",
+        "finally",
+        "for",
+        "from",
+        "function",
+        "if",
+        "import",
+        "in",
+        "instanceof",
+        "interface",
+        "let",
+        "new",
+        "null",
+        "of",
+        "return",
+        "static",
+        "switch",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "type",
+        "typeof",
+        "undefined",
+        "var",
+        "void",
+        "while",
+        "yield",
+    ],
+    comments: &["//", "/*"],
+    quotes: &['"', '\'', '`'],
+};
+
+const GO: Language = Language {
+    keywords: &[
+        "break",
+        "case",
+        "chan",
+        "const",
+        "continue",
+        "default",
+        "defer",
+        "else",
+        "false",
+        "for",
+        "func",
+        "go",
+        "if",
+        "import",
+        "interface",
+        "map",
+        "nil",
+        "package",
+        "range",
+        "return",
+        "select",
+        "struct",
+        "switch",
+        "true",
+        "type",
+        "var",
+    ],
+    comments: &["//", "/*"],
+    quotes: &['"', '\'', '`'],
+};
+
+const C_LIKE: Language = Language {
+    keywords: &[
+        "abstract",
+        "auto",
+        "bool",
+        "break",
+        "case",
+        "catch",
+        "char",
+        "class",
+        "const",
+        "continue",
+        "default",
+        "delete",
+        "do",
+        "double",
+        "else",
+        "enum",
+        "extends",
+        "false",
+        "final",
+        "float",
+        "for",
+        "if",
+        "implements",
+        "import",
+        "include",
+        "int",
+        "long",
+        "namespace",
+        "new",
+        "null",
+        "nullptr",
+        "package",
+        "private",
+        "protected",
+        "public",
+        "return",
+        "short",
+        "signed",
+        "sizeof",
+        "static",
+        "struct",
+        "switch",
+        "template",
+        "this",
+        "throw",
+        "true",
+        "try",
+        "typedef",
+        "union",
+        "unsigned",
+        "using",
+        "virtual",
+        "void",
+        "volatile",
+        "while",
+    ],
+    comments: &["//", "/*"],
+    quotes: &['"', '\''],
+};
+
+const SHELL: Language = Language {
+    keywords: &[
+        "case", "do", "done", "elif", "else", "esac", "export", "fi", "for", "function", "if",
+        "in", "local", "return", "then", "until", "while",
+    ],
+    comments: &["#"],
+    quotes: &['"', '\''],
+};
+
+const TOML: Language = Language {
+    keywords: &["true", "false"],
+    comments: &["#"],
+    quotes: &['"', '\''],
+};
+
+const JSON: Language = Language {
+    keywords: &["true", "false", "null"],
+    comments: &[],
+    quotes: &['"'],
+};
+
+/// The language named by the tag after a code fence, `None` for the ones the highlighter doesn't know
+pub fn language(tag: &str) -> Option<&'static Language> {
+    let language = match tag.to_lowercase().as_str() {
+        "rust" | "rs" => &RUST,
+        "python" | "py" => &PYTHON,
+        "javascript" | "js" | "jsx" | "typescript" | "ts" | "tsx" => &JAVASCRIPT,
+        "go" | "golang" => &GO,
+        "c" | "h" | "cpp" | "c++" | "hpp" | "cc" | "java" | "kotlin" | "cs" | "csharp" => &C_LIKE,
+        "sh" | "bash" | "shell" | "zsh" | "console" => &SHELL,
+        "toml" => &TOML,
+        "json" => &JSON,
+        _ => return None,
+    };
+    Some(language)
+}
+
+/// Color one line of code. Keywords, strings, comments, numbers and capitalized names (types, mostly)
+/// get their own color, everything else is printed as it is.
+pub fn highlight_line(line: &str, language: &Language) -> String {
+    let chars: Vec<char> = line.chars().collect();
+    let mut out = String::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let rest = &chars[i..];
+
+        if language
+            .comments
+            .iter()
+            .any(|prefix| rest.iter().copied().take(prefix.len()).eq(prefix.chars()))
+        {
+            let comment: String = rest.iter().collect();
+            out.push_str(&comment.bright_black().to_string());
+            break;
+        }
+
+        let start = i;
+        if language.quotes.contains(&c) {
+            i += 1;
+            while i < chars.len() && chars[i] != c {
+                // Skip the escaped char, an escaped quote doesn't end the string
+                i += if chars[i] == '\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(chars.len());
+            let string: String = chars[start..i].iter().collect();
+            out.push_str(&string.green().to_string());
+        } else if c.is_ascii_digit() {
+            while i < chars.len() && (chars[i].is_alphanumeric() || matches!(chars[i], '.' | '_')) {
+                i += 1;
+            }
+            let number: String = chars[start..i].iter().collect();
+            out.push_str(&number.yellow().to_string());
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                i += 1;
+            }
+            let word: String = chars[start..i].iter().collect();
+            if language.keywords.contains(&word.as_str()) {
+                out.push_str(&word.magenta().to_string());
+            } else if c.is_uppercase() {
+                out.push_str(&word.cyan().to_string());
+            } else {
+                out.push_str(&word);
+            }
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_color_leaves_code_untouched() {
+        colored::control::set_override(false);
+        let samples = [
+            ("rust", r#"pub fn main() -> u8 { let s = "a \" b"; 42 } // done"#),
+            ("python", "def f(x): return 'y' if x else None  # why"),
+            ("js", "const n = 0x1f; /* note */"),
+            ("toml", "name = \"r-agent\" # crate"),
+            ("json", r#"{"ok": true, "n": 1.5e3}"#),
+        ];
+        for (tag, line) in samples {
+            let language = language(tag).unwrap();
+            assert_eq!(highlight_line(line, language), line, "{}", tag);
+        }
+    }
+
+    #[test]
+    fn unknown_languages_are_not_highlighted() {
+        assert!(language("").is_none());
+        assert!(language("brainfuck").is_none());
+        assert!(language("text").is_none());
+        // Tags are matched case-insensitively
+        assert!(language("Rust").is_some());
+        assert!(language("TS").is_some());
+    }
+}
@@ -0,0 +1,162 @@
+use forge::api::dtos::Role::{ASSISTANT, SYSTEM, TOOL, USER};
+use forge::api::dtos::{ImageUrl, Message, MultiContent, Role, ToolCall};
+
+/// Constructors for the common message shapes, so callers don't spell out every unused field.
+/// `Message` comes from forge, hence a trait, import it to call `Message::user_text(..)` and friends.
+pub trait MessageExt {
+    fn system_text(text: &str) -> Self;
+    fn user_text(text: &str) -> Self;
+    /// Text first, then one block per image. Without images this is a plain [`MessageExt::user_text`].
+    fn user_with_images(text: &str, images: Vec<ImageUrl>) -> Self;
+    fn assistant_text(text: &str) -> Self;
+    /// An assistant message that only carries tool calls
+    fn assistant_tool_calls(calls: Vec<ToolCall>) -> Self;
+    fn tool_result(id: &str, name: &str, content: &str) -> Self;
+}
+
+impl MessageExt for Message {
+    fn system_text(text: &str) -> Self {
+        text_message(SYSTEM, text)
+    }
+
+    fn user_text(text: &str) -> Self {
+        text_message(USER, text)
+    }
+
+    fn user_with_images(text: &str, images: Vec<ImageUrl>) -> Self {
+        if images.is_empty() {
+            return Message::user_text(text);
+        }
+
+        let mut parts = vec![MultiContent {
+            r#type: "text".to_string(),
+            text: Some(text.to_string()),
+            image_url: None,
+        }];
+        parts.extend(images.into_iter().map(|image| MultiContent {
+            r#type: "image_url".to_string(),
+            text: None,
+            image_url: Some(image),
+        }));
+
+        Message {
+            role: USER,
+            content: None,
+            multi_content: Some(parts),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        }
+    }
+
+    fn assistant_text(text: &str) -> Self {
+        text_message(ASSISTANT, text)
+    }
+
+    fn assistant_tool_calls(calls: Vec<ToolCall>) -> Self {
+        Message {
+            role: ASSISTANT,
+            content: None,
+            multi_content: None,
+            tool_calls: Some(calls),
+            tool_call_id: None,
+            name: None,
+        }
+    }
+
+    fn tool_result(id: &str, name: &str, content: &str) -> Self {
+        Message {
+            role: TOOL,
+            content: Some(content.to_string()),
+            multi_content: None,
+            tool_calls: None,
+            tool_call_id: Some(id.to_string()),
+            name: Some(name.to_string()),
+        }
+    }
+}
+
+fn text_message(role: Role, text: &str) -> Message {
+    Message {
+        role,
+        content: Some(text.to_string()),
+        multi_content: None,
+        tool_calls: None,
+        tool_call_id: None,
+        name: None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::Value;
+
+    fn json(message: &Message) -> Value {
+        serde_json::to_value(message).unwrap()
+    }
+
+    #[test]
+    fn user_text_matches_the_manual_message() {
+        let manual = Message {
+            role: USER,
+            content: Some("Hello".to_string()),
+            multi_content: None,
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        assert_eq!(json(&Message::user_text("Hello")), json(&manual));
+        // No images, no content blocks
+        assert_eq!(
+            json(&Message::user_with_images("Hello", Vec::new())),
+            json(&manual)
+        );
+    }
+
+    #[test]
+    fn user_with_images_matches_the_manual_message() {
+        let image = ImageUrl {
+            url: "data:image/png;base64,iVBORw0KGgo=".to_string(),
+        };
+        let manual = Message {
+            role: USER,
+            content: None,
+            multi_content: Some(vec![
+                MultiContent {
+                    r#type: "text".to_string(),
+                    text: Some("What is this?".to_string()),
+                    image_url: None,
+                },
+                MultiContent {
+                    r#type: "image_url".to_string(),
+                    text: None,
+                    image_url: Some(image.clone()),
+                },
+            ]),
+            tool_calls: None,
+            tool_call_id: None,
+            name: None,
+        };
+        assert_eq!(
+            json(&Message::user_with_images("What is this?", vec![image])),
+            json(&manual)
+        );
+    }
+
+    #[test]
+    fn tool_result_matches_the_manual_message() {
+        let manual = Message {
+            role: TOOL,
+            content: Some("12:00".to_string()),
+            multi_content: None,
+            tool_calls: None,
+            tool_call_id: Some("call_0".to_string()),
+            name: Some("get_time_tool".to_string()),
+        };
+        assert_eq!(
+            json(&Message::tool_result("call_0", "get_time_tool", "12:00")),
+            json(&manual)
+        );
+    }
+}
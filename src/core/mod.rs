@@ -1,5 +1,17 @@
 pub mod config;
 pub mod data;
+pub mod debug_log;
+pub mod events;
+pub mod highlight;
+pub mod messages;
+pub mod models;
+pub mod render;
+pub mod retry;
 pub mod runner;
 pub mod session;
+pub mod stream;
+#[cfg(test)]
+pub(crate) mod test_server;
+pub mod tokens;
+pub mod tool_args;
 pub mod tools;
@@ -0,0 +1,373 @@
+use anyhow::{Context, Result};
+use reqwest::Response;
+use serde_json::Value;
+use std::fmt;
+use std::time::Duration;
+
+/// Short enough that the splash screen never feels stuck on a dead server
+pub const REACHABILITY_TIMEOUT: Duration = Duration::from_secs(2);
+
+pub const MODELS_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Whether an OpenAI-compatible server answered on its `/models` endpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Reachability {
+    Up,
+    Down,
+    Unknown,
+}
+
+impl fmt::Display for Reachability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Reachability::Up => write!(f, "up"),
+            Reachability::Down => write!(f, "down"),
+            Reachability::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
+pub fn models_endpoint(url: &str) -> String {
+    format!("{}/models", url.trim_end_matches('/'))
+}
+
+/// `GET /models` on the server at `url`, the one request behind every check in this module
+async fn get_models(url: &str, api_key: &str, timeout: Duration) -> reqwest::Result<Response> {
+    reqwest::Client::builder()
+        .timeout(timeout)
+        .build()?
+        .get(models_endpoint(url))
+        .bearer_auth(api_key)
+        .send()
+        .await
+}
+
+/// Any HTTP answer counts as up (a 401 still means the server is running),
+/// a refused connection is down and everything else, timeouts included, is unknown
+pub async fn check_reachability(url: &str, api_key: &str, timeout: Duration) -> Reachability {
+    match get_models(url, api_key, timeout).await {
+        Ok(_) => Reachability::Up,
+        Err(e) if e.is_connect() => Reachability::Down,
+        Err(_) => Reachability::Unknown,
+    }
+}
+
+/// What a [`ping`] found out about an agent's server and model
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EndpointStatus {
+    Reachable,
+    /// The server answered 401/403 to the configured api key
+    AuthFailed,
+    /// The server lists its models and the configured one is not among them, holds the listed ones
+    ModelMissing(Vec<String>),
+    /// No HTTP answer at all, with the reason
+    Unreachable(String),
+}
+
+impl fmt::Display for EndpointStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EndpointStatus::Reachable => write!(f, "reachable"),
+            EndpointStatus::AuthFailed => write!(f, "authentication failed"),
+            EndpointStatus::ModelMissing(_) => write!(f, "model not served"),
+            EndpointStatus::Unreachable(reason) => write!(f, "unreachable ({})", reason),
+        }
+    }
+}
+
+/// Check that the server at `url` answers, accepts `api_key` and serves `model`, with a single `GET /models`.
+/// Servers without a usable `/models` endpoint can't be checked further and count as reachable.
+pub async fn ping(url: &str, api_key: &str, model: &str, timeout: Duration) -> EndpointStatus {
+    let response = match get_models(url, api_key, timeout).await {
+        Ok(response) => response,
+        Err(e) if e.is_timeout() => return EndpointStatus::Unreachable("timed out".to_string()),
+        Err(e) => return EndpointStatus::Unreachable(e.without_url().to_string()),
+    };
+
+    let status = response.status();
+    if status == reqwest::StatusCode::UNAUTHORIZED || status == reqwest::StatusCode::FORBIDDEN {
+        return EndpointStatus::AuthFailed;
+    }
+    if !status.is_success() {
+        return EndpointStatus::Reachable;
+    }
+
+    let ids = match response.json::<Value>().await {
+        Ok(body) => parse_model_ids(&body).unwrap_or_default(),
+        Err(_) => Vec::new(),
+    };
+    if ids.is_empty() || serves_model(&ids, model) {
+        EndpointStatus::Reachable
+    } else {
+        EndpointStatus::ModelMissing(ids)
+    }
+}
+
+/// Whether `model` names one of the served `ids`. Besides an exact match, tags and routing suffixes are ignored
+/// (Ollama serves `qwen3` as `qwen3:latest`, OpenRouter accepts `:free` variants) and so is a provider prefix
+/// on the served id (`gpt-4o` for `openai/gpt-4o`), since servers resolve those names themselves.
+pub fn serves_model(ids: &[String], model: &str) -> bool {
+    let base = |name: &str| -> String {
+        let name = name.to_lowercase();
+        match name.split_once(':') {
+            Some((base, _)) => base.to_string(),
+            None => name,
+        }
+    };
+    let wanted = base(model);
+    ids.iter().any(|id| {
+        let id = base(id);
+        id == wanted || id.ends_with(&format!("/{}", wanted))
+    })
+}
+
+/// The served model closest to `model` by edit distance, as long as it is close enough to be a likely typo
+pub fn closest_model<'a>(model: &str, available: &'a [String]) -> Option<&'a str> {
+    let max_distance = (model.chars().count() / 3).max(2);
+    available
+        .iter()
+        .map(|candidate| (edit_distance(model, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.as_str())
+}
+
+/// Levenshtein distance over chars, case-insensitive since servers disagree on casing
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Fetch the model ids served at `url`, see [`parse_model_ids`] for the accepted response shapes
+pub async fn list_models(url: &str, api_key: &str, timeout: Duration) -> Result<Vec<String>> {
+    let endpoint = models_endpoint(url);
+    let body: Value = get_models(url, api_key, timeout)
+        .await
+        .with_context(|| anyhow::anyhow!("Failed to reach {}", endpoint))?
+        .error_for_status()?
+        .json()
+        .await
+        .with_context(|| anyhow::anyhow!("{} did not answer with JSON", endpoint))?;
+    parse_model_ids(&body)
+}
+
+/// The OpenAI shape is `{"data": [{"id": ..}]}`, but some servers answer with `{"models": [..]}`
+/// or a bare array, and name the id field `name` or `model`. All of them are accepted.
+pub fn parse_model_ids(body: &Value) -> Result<Vec<String>> {
+    let entries = match body {
+        Value::Array(entries) => entries,
+        Value::Object(map) => map
+            .get("data")
+            .or_else(|| map.get("models"))
+            .and_then(|v| v.as_array())
+            .ok_or_else(|| anyhow::anyhow!("Unexpected /models response: {}", body))?,
+        _ => anyhow::bail!("Unexpected /models response: {}", body),
+    };
+
+    let ids = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Value::String(id) => Some(id.clone()),
+            Value::Object(map) => ["id", "name", "model"]
+                .iter()
+                .find_map(|key| map.get(*key).and_then(|v| v.as_str()))
+                .map(|id| id.to_string()),
+            _ => None,
+        })
+        .collect();
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_server::{Reply, TestServer};
+
+    const TIMEOUT: Duration = Duration::from_secs(5);
+
+    async fn ping_reply(reply: Reply, model: &str) -> EndpointStatus {
+        let server = TestServer::start(vec![reply]).await;
+        ping(&server.url, "key", model, TIMEOUT).await
+    }
+
+    #[tokio::test]
+    async fn reachability_of_up_down_and_silent_servers() {
+        for status in [200, 401, 500] {
+            let server = TestServer::start(vec![Reply::new(status, "{}")]).await;
+            let reachability = check_reachability(&server.url, "key", TIMEOUT).await;
+            assert_eq!(reachability, Reachability::Up, "status {}", status);
+        }
+        assert_eq!(
+            check_reachability("http://127.0.0.1:9", "key", TIMEOUT).await,
+            Reachability::Down
+        );
+        let silent =
+            TestServer::start(vec![Reply::new(200, "{}").delayed(Duration::from_secs(10))]).await;
+        assert_eq!(
+            check_reachability(&silent.url, "key", Duration::from_millis(200)).await,
+            Reachability::Unknown
+        );
+    }
+
+    #[tokio::test]
+    async fn ping_reports_each_status() {
+        let listed = r#"{"object":"list","data":[{"id":"qwen3-8b"},{"id":"gemma-3-12b"}]}"#;
+        assert_eq!(
+            ping_reply(Reply::new(200, listed), "qwen3-8b").await,
+            EndpointStatus::Reachable
+        );
+        assert_eq!(
+            ping_reply(Reply::new(200, listed), "llama3").await,
+            EndpointStatus::ModelMissing(served(&["qwen3-8b", "gemma-3-12b"]))
+        );
+        assert_eq!(
+            ping_reply(Reply::new(401, "bad key"), "qwen3-8b").await,
+            EndpointStatus::AuthFailed
+        );
+        assert_eq!(
+            ping_reply(Reply::new(403, "forbidden"), "qwen3-8b").await,
+            EndpointStatus::AuthFailed
+        );
+        assert!(matches!(
+            ping("http://127.0.0.1:9", "key", "qwen3-8b", TIMEOUT).await,
+            EndpointStatus::Unreachable(_)
+        ));
+    }
+
+    #[tokio::test]
+    async fn ping_cannot_check_without_a_model_list() {
+        // No /models endpoint, or one that lists nothing, says nothing about the model
+        assert_eq!(
+            ping_reply(Reply::new(404, "not found"), "qwen3-8b").await,
+            EndpointStatus::Reachable
+        );
+        assert_eq!(
+            ping_reply(Reply::new(200, r#"{"data":[]}"#), "qwen3-8b").await,
+            EndpointStatus::Reachable
+        );
+        assert_eq!(
+            ping_reply(Reply::new(200, "<html>"), "qwen3-8b").await,
+            EndpointStatus::Reachable
+        );
+    }
+
+    fn served(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[test]
+    fn model_aliases_count_as_served() {
+        let ollama = served(&["qwen3:latest", "gemma3:12b"]);
+        assert!(serves_model(&ollama, "qwen3"));
+        assert!(serves_model(&ollama, "qwen3:latest"));
+        assert!(serves_model(&ollama, "gemma3"));
+        assert!(!serves_model(&ollama, "qwen"));
+
+        let openrouter = served(&["openai/gpt-4o", "meta-llama/llama-3.3-70b-instruct"]);
+        assert!(serves_model(&openrouter, "openai/gpt-4o:free"));
+        assert!(serves_model(&openrouter, "gpt-4o"));
+        assert!(serves_model(
+            &openrouter,
+            "Meta-Llama/Llama-3.3-70B-Instruct"
+        ));
+        assert!(!serves_model(&openrouter, "gpt-4"));
+    }
+
+    #[tokio::test]
+    async fn near_miss_model_gets_a_suggestion() {
+        let listed = r#"{"data":[{"id":"qwen/qwen3-8b"},{"id":"google/gemma-3-12b"}]}"#;
+        let status = ping_reply(Reply::new(200, listed), "qwen/qwen3-8").await;
+        let EndpointStatus::ModelMissing(available) = status else {
+            panic!("expected a missing model, got {:?}", status);
+        };
+        assert_eq!(
+            closest_model("qwen/qwen3-8", &available),
+            Some("qwen/qwen3-8b")
+        );
+    }
+
+    #[test]
+    fn edit_distance_counts_single_char_edits() {
+        assert_eq!(edit_distance("", ""), 0);
+        assert_eq!(edit_distance("qwen", ""), 4);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("qwen3-8b", "qwen3-8b"), 0);
+        assert_eq!(edit_distance("Qwen3-8B", "qwen3-8b"), 0);
+        assert_eq!(edit_distance("llama3", "lama3"), 1);
+    }
+
+    #[test]
+    fn closest_model_suggests_a_likely_typo() {
+        let available = served(&["qwen/qwen3-8b", "google/gemma-3-12b", "openai/gpt-oss-20b"]);
+        assert_eq!(
+            closest_model("qwen/qwen3-8", &available),
+            Some("qwen/qwen3-8b")
+        );
+        assert_eq!(
+            closest_model("google/gema-3-12b", &available),
+            Some("google/gemma-3-12b")
+        );
+        assert_eq!(closest_model("mistral-large", &available), None);
+        assert_eq!(closest_model("qwen3-8b", &[]), None);
+    }
+
+    #[test]
+    fn parse_model_ids_accepts_every_known_shape() {
+        // LM Studio / OpenAI
+        let openai = serde_json::json!({
+            "object": "list",
+            "data": [
+                { "id": "qwen/qwen3-8b", "object": "model", "owned_by": "organization_owner" },
+                { "id": "text-embedding-nomic-embed-text-v1.5", "object": "model" },
+            ],
+        });
+        assert_eq!(
+            parse_model_ids(&openai).unwrap(),
+            served(&["qwen/qwen3-8b", "text-embedding-nomic-embed-text-v1.5"])
+        );
+
+        // Ollama's native listing
+        let ollama = serde_json::json!({
+            "models": [
+                { "name": "qwen3:latest", "model": "qwen3:latest", "size": 5225388164u64 },
+                { "model": "gemma3:12b" },
+            ],
+        });
+        assert_eq!(
+            parse_model_ids(&ollama).unwrap(),
+            served(&["qwen3:latest", "gemma3:12b"])
+        );
+
+        // A bare array, entries without an id are skipped
+        let bare = serde_json::json!(["llama3", { "id": "mistral" }, { "size": 1 }, 42]);
+        assert_eq!(
+            parse_model_ids(&bare).unwrap(),
+            served(&["llama3", "mistral"])
+        );
+
+        assert!(parse_model_ids(&serde_json::json!({ "error": "nope" })).is_err());
+        assert!(parse_model_ids(&serde_json::json!("models")).is_err());
+    }
+
+    #[tokio::test]
+    async fn list_models_reads_the_served_ids() {
+        let server = TestServer::start(vec![
+            Reply::new(200, r#"{"data":[{"id":"qwen3-8b"}]}"#),
+            Reply::new(500, "down"),
+        ])
+        .await;
+        let models = list_models(&format!("{}/v1/", server.url), "key", TIMEOUT).await;
+        assert_eq!(models.unwrap(), served(&["qwen3-8b"]));
+        assert!(list_models(&server.url, "key", TIMEOUT).await.is_err());
+    }
+}
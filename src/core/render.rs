@@ -0,0 +1,940 @@
+use crate::core::events::{AgentEvent, Transcript};
+use crate::core::highlight;
+use anyhow::Result;
+use colored::Colorize;
+use futures_util::{Stream, StreamExt};
+use std::io::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// How the streamed answer is printed
+#[derive(Clone, Copy, Debug)]
+pub struct RenderOptions {
+    pub wrap_len: usize,
+    /// Delay between two printed characters, zero prints text as soon as it is available
+    pub char_delay: Duration,
+    /// Collect the whole answer before printing anything (the old behavior)
+    pub buffered: bool,
+    /// Print tool calls and results (to stderr)
+    pub show_tools: bool,
+    /// Print nothing at all, the caller reports the transcript itself
+    pub silent: bool,
+    /// Print each event as one JSON object per line on stdout instead of rendering it
+    pub jsonl: bool,
+    /// Print fenced code blocks verbatim (and dimmed) instead of wrapping them like prose
+    pub code_blocks: bool,
+}
+
+impl Default for RenderOptions {
+    fn default() -> Self {
+        RenderOptions {
+            wrap_len: terminal_width(),
+            char_delay: Duration::from_millis(10),
+            buffered: false,
+            show_tools: true,
+            silent: false,
+            jsonl: false,
+            code_blocks: true,
+        }
+    }
+}
+
+/// Wrap width used when stdout is not a terminal
+pub const FALLBACK_WIDTH: usize = 80;
+
+/// Columns of the terminal stdout is attached to, [`FALLBACK_WIDTH`] when it isn't one (pipes, files, CI)
+pub fn terminal_width() -> usize {
+    terminal_size::terminal_size_of(std::io::stdout())
+        .map(|(terminal_size::Width(width), _)| width as usize)
+        .filter(|width| *width > 0)
+        .unwrap_or(FALLBACK_WIDTH)
+}
+
+/// Print the events of a turn: the answer wrapped to `wrap_len` columns with a typewriter effect,
+/// tool calls and results as dimmed lines on stderr (with a spinner while tools run), so stdout only ever holds the answer.
+/// Returns the transcript with the raw (unwrapped) text for session storage, or what arrived so far once `cancel` fires.
+pub async fn typewriter<S>(
+    stream: S,
+    options: &RenderOptions,
+    cancel: &CancellationToken,
+) -> Result<Transcript>
+where
+    S: Stream<Item = Result<AgentEvent>> + Unpin,
+{
+    typewriter_to(stream, options, cancel, &mut std::io::stdout()).await
+}
+
+/// [`typewriter`] printing the answer to `out` instead of stdout
+pub async fn typewriter_to<S, W>(
+    mut stream: S,
+    options: &RenderOptions,
+    cancel: &CancellationToken,
+    out: &mut W,
+) -> Result<Transcript>
+where
+    S: Stream<Item = Result<AgentEvent>> + Unpin,
+    W: Write,
+{
+    let mut transcript = Transcript::default();
+    let mut wrapper = MarkdownWrapper::new(options.wrap_len, options.code_blocks);
+    let mut spinner = Spinner::new(
+        options.show_tools && !options.silent && !options.jsonl && atty::is(atty::Stream::Stderr),
+    );
+    let mut ticker = tokio::time::interval(SPINNER_INTERVAL);
+
+    loop {
+        let event = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => break,
+            event = stream.next() => event,
+            _ = ticker.tick(), if spinner.is_active() => {
+                spinner.draw();
+                continue;
+            }
+        };
+        spinner.clear();
+        let Some(event) = event else {
+            break;
+        };
+        let event = event?;
+        transcript.record(&event);
+        spinner.track(&event);
+        if options.jsonl {
+            writeln!(out, "{}", event.to_json())?;
+            out.flush()?;
+        }
+        match event {
+            AgentEvent::Final => break,
+            _ if options.silent || options.jsonl => {}
+            AgentEvent::Text(chunk) => {
+                if !options.buffered {
+                    print_spans(out, &wrapper.push(&chunk), options.char_delay).await?;
+                }
+            }
+            AgentEvent::Restarted => {
+                print_spans(out, &wrapper.finish(), options.char_delay).await?;
+                eprintln!(
+                    "{}",
+                    "\n[The answer was regenerated after a lost connection]".yellow()
+                );
+            }
+            AgentEvent::ToolCall { .. } | AgentEvent::ToolResult { .. } if !options.show_tools => {}
+            AgentEvent::ToolCall { name, args } => {
+                print_spans(out, &wrapper.finish(), options.char_delay).await?;
+                eprintln!("{}", format!("-> {} {}", name, args).dimmed());
+            }
+            AgentEvent::ToolResult { name, output } => {
+                eprintln!(
+                    "{}",
+                    format!("<- {} returned {} chars", name, output.chars().count()).dimmed()
+                );
+            }
+        }
+    }
+
+    spinner.clear();
+    if options.silent || options.jsonl {
+        return Ok(transcript);
+    }
+    if options.buffered && !options.code_blocks {
+        print_text(
+            out,
+            &word_wrap(&transcript.text, options.wrap_len),
+            false,
+            options.char_delay,
+        )
+        .await?;
+    } else if options.buffered {
+        let mut spans = wrapper.push(&transcript.text);
+        spans.extend(wrapper.finish());
+        print_spans(out, &spans, options.char_delay).await?;
+    } else {
+        print_spans(out, &wrapper.finish(), options.char_delay).await?;
+    }
+    writeln!(out)?;
+
+    Ok(transcript)
+}
+
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
+const SPINNER_FRAMES: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Set while something else owns the stderr line, an approval prompt waiting for its answer
+static SPINNER_PAUSED: Mutex<bool> = Mutex::new(false);
+
+/// Keeps the spinner from drawing until it is dropped
+pub struct SpinnerPause;
+
+/// Clear the spinner and keep it off the line, for prompts printed on stderr while a tool runs
+pub fn pause_spinner() -> SpinnerPause {
+    let mut paused = SPINNER_PAUSED.lock().unwrap_or_else(|e| e.into_inner());
+    *paused = true;
+    if atty::is(atty::Stream::Stderr) {
+        eprint!("\r\x1b[2K");
+        let _ = std::io::stderr().flush();
+    }
+    SpinnerPause
+}
+
+impl Drop for SpinnerPause {
+    fn drop(&mut self) {
+        *SPINNER_PAUSED.lock().unwrap_or_else(|e| e.into_inner()) = false;
+    }
+}
+
+/// A `running <tool>...` line on stderr while tools execute, redrawn in place and cleared before anything else
+/// is printed. Disabled when stderr is not a terminal, where the redraws would only be noise.
+struct Spinner {
+    enabled: bool,
+    running: Vec<String>,
+    frame: usize,
+    drawn: bool,
+}
+
+impl Spinner {
+    fn new(enabled: bool) -> Self {
+        Spinner {
+            enabled,
+            running: Vec::new(),
+            frame: 0,
+            drawn: false,
+        }
+    }
+
+    fn is_active(&self) -> bool {
+        self.enabled && !self.running.is_empty()
+    }
+
+    fn track(&mut self, event: &AgentEvent) {
+        match event {
+            AgentEvent::ToolCall { name, .. } => self.running.push(name.clone()),
+            AgentEvent::ToolResult { name, .. } => {
+                if let Some(i) = self.running.iter().position(|r| r == name) {
+                    self.running.remove(i);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn draw(&mut self) {
+        // Held while drawing, so a prompt can't be printed between the check and the redraw
+        let paused = SPINNER_PAUSED.lock().unwrap_or_else(|e| e.into_inner());
+        if *paused {
+            return;
+        }
+        let frame = SPINNER_FRAMES[self.frame % SPINNER_FRAMES.len()];
+        self.frame += 1;
+        eprint!(
+            "\r\x1b[2K{}",
+            format!("{} running {}...", frame, self.running.join(", ")).dimmed()
+        );
+        let _ = std::io::stderr().flush();
+        self.drawn = true;
+    }
+
+    fn clear(&mut self) {
+        // pause_spinner already cleared the line, which now holds the prompt
+        if *SPINNER_PAUSED.lock().unwrap_or_else(|e| e.into_inner()) {
+            self.drawn = false;
+            return;
+        }
+        if self.drawn {
+            eprint!("\r\x1b[2K");
+            let _ = std::io::stderr().flush();
+            self.drawn = false;
+        }
+    }
+}
+
+/// Code of a language the highlighter knows is highlighted when colors are on, and printed a line at a time
+/// (a typewriter would cut through its color codes). Without colors, or for other code, it is printed dimmed.
+async fn print_spans(out: &mut impl Write, spans: &[Span], char_delay: Duration) -> Result<()> {
+    for span in spans {
+        let language = span
+            .lang
+            .as_deref()
+            .filter(|_| colored::control::SHOULD_COLORIZE.should_colorize())
+            .and_then(highlight::language);
+        let Some(language) = language else {
+            print_text(out, &span.text, span.code, char_delay).await?;
+            continue;
+        };
+        for line in span.text.split_inclusive('\n') {
+            let (code, newline) = match line.strip_suffix('\n') {
+                Some(code) => (code, "\n"),
+                None => (line, ""),
+            };
+            write!(
+                out,
+                "{}{}",
+                highlight::highlight_line(code, language),
+                newline
+            )?;
+            out.flush()?;
+            if !char_delay.is_zero() {
+                tokio::time::sleep(char_delay * code.chars().count().min(20) as u32).await;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn print_text(
+    out: &mut impl Write,
+    text: &str,
+    code: bool,
+    char_delay: Duration,
+) -> Result<()> {
+    let style = |text: &str| {
+        if code {
+            text.dimmed()
+        } else {
+            text.bright_white()
+        }
+    };
+    if char_delay.is_zero() {
+        write!(out, "{}", style(text))?;
+        out.flush()?;
+        return Ok(());
+    }
+
+    for c in text.chars() {
+        write!(out, "{}", style(&c.to_string()))?;
+        out.flush()?;
+        tokio::time::sleep(char_delay).await;
+    }
+    Ok(())
+}
+
+/// Soft-wraps streamed text without buffering the whole answer.
+/// Only the current line state and the word being received are kept between chunks,
+/// a word is released once the whitespace after it arrives.
+pub struct LiveWrapper {
+    width: usize,
+    line_width: usize,
+    line_has_word: bool,
+    word: String,
+    word_width: usize,
+}
+
+impl LiveWrapper {
+    pub fn new(width: usize) -> Self {
+        LiveWrapper {
+            width: width.max(1),
+            line_width: 0,
+            line_has_word: false,
+            word: String::new(),
+            word_width: 0,
+        }
+    }
+
+    /// Feed a chunk, returns the text that is ready to be printed
+    pub fn push(&mut self, chunk: &str) -> String {
+        let mut out = String::new();
+        for c in chunk.chars() {
+            if c == '\n' {
+                self.flush_word(&mut out);
+                out.push('\n');
+                self.line_width = 0;
+                self.line_has_word = false;
+            } else if c.is_whitespace() {
+                if !self.line_has_word && self.word.is_empty() {
+                    // Leading indentation is printed as-is
+                    out.push(c);
+                    self.line_width += if c == '\t' { 4 } else { c.width().unwrap_or(0) };
+                } else {
+                    self.flush_word(&mut out);
+                }
+            } else {
+                self.word.push(c);
+                self.word_width += c.width().unwrap_or(0);
+            }
+        }
+        out
+    }
+
+    /// Release whatever is still buffered once the stream ends
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        self.flush_word(&mut out);
+        out
+    }
+
+    fn flush_word(&mut self, out: &mut String) {
+        if self.word.is_empty() {
+            return;
+        }
+
+        if self.line_has_word {
+            if self.line_width + 1 + self.word_width > self.width {
+                out.push('\n');
+                self.line_width = 0;
+            } else {
+                out.push(' ');
+                self.line_width += 1;
+            }
+        }
+
+        if self.line_width + self.word_width <= self.width {
+            out.push_str(&self.word);
+            self.line_width += self.word_width;
+        } else {
+            // Hard-break a word that can't fit on a line of its own
+            for c in self.word.chars() {
+                let char_width = c.width().unwrap_or(0);
+                if self.line_width + char_width > self.width && self.line_width > 0 {
+                    out.push('\n');
+                    self.line_width = 0;
+                }
+                out.push(c);
+                self.line_width += char_width;
+            }
+        }
+
+        self.line_has_word = true;
+        self.word.clear();
+        self.word_width = 0;
+    }
+}
+
+/// A piece of rendered answer, code is printed as it came and styled apart from prose
+#[derive(Clone, Debug, PartialEq)]
+pub struct Span {
+    pub text: String,
+    pub code: bool,
+    /// The tag after the opening fence, for the lines of the block
+    pub lang: Option<String>,
+}
+
+/// [`LiveWrapper`] that leaves fenced code blocks (```` ``` ```` or `~~~`) alone: their lines keep
+/// their indentation and length, wrapping them would make the code invalid.
+/// The start of each line is held back until it is clear whether it opens or closes a fence,
+/// code comes out a whole line at a time so it can be highlighted.
+pub struct MarkdownWrapper {
+    prose: LiveWrapper,
+    /// Detect fences at all, without it every span is prose
+    fences: bool,
+    in_code: bool,
+    /// Language tag of the open code block
+    lang: Option<String>,
+    /// The code line being received
+    code_line: String,
+    /// The start of the current line while it could still be a fence
+    line_start: String,
+    /// `line_start` is a fence, kept until its newline
+    fence_line: bool,
+    /// The start of the current line has been released
+    mid_line: bool,
+}
+
+impl MarkdownWrapper {
+    pub fn new(width: usize, fences: bool) -> Self {
+        MarkdownWrapper {
+            prose: LiveWrapper::new(width),
+            fences,
+            in_code: false,
+            lang: None,
+            code_line: String::new(),
+            line_start: String::new(),
+            fence_line: false,
+            mid_line: false,
+        }
+    }
+
+    /// Feed a chunk, returns the spans that are ready to be printed
+    pub fn push(&mut self, chunk: &str) -> Vec<Span> {
+        let mut spans = Vec::new();
+        if !self.fences {
+            push_span(&mut spans, self.prose.push(chunk), false, None);
+            return spans;
+        }
+
+        for c in chunk.chars() {
+            if self.mid_line {
+                self.release(&c.to_string(), &mut spans);
+                continue;
+            }
+
+            self.line_start.push(c);
+            if self.fence_line {
+                if c == '\n' {
+                    let fence = std::mem::take(&mut self.line_start);
+                    self.toggle_fence(&fence);
+                    push_span(&mut spans, fence, true, None);
+                }
+                continue;
+            }
+
+            let start = self.line_start.trim_start();
+            if c != '\n' && (start.starts_with("```") || start.starts_with("~~~")) {
+                self.fence_line = true;
+            } else if c == '\n' || !("```".starts_with(start) || "~~~".starts_with(start)) {
+                let line_start = std::mem::take(&mut self.line_start);
+                self.release(&line_start, &mut spans);
+            }
+        }
+        spans
+    }
+
+    /// Release whatever is still held back once the stream ends
+    pub fn finish(&mut self) -> Vec<Span> {
+        let mut spans = Vec::new();
+        let line_start = std::mem::take(&mut self.line_start);
+        if self.fence_line {
+            self.toggle_fence(&line_start);
+            push_span(&mut spans, line_start, true, None);
+        } else {
+            self.release(&line_start, &mut spans);
+        }
+        let code_line = std::mem::take(&mut self.code_line);
+        push_span(&mut spans, code_line, true, self.lang.clone());
+        push_span(&mut spans, self.prose.finish(), false, None);
+        spans
+    }
+
+    /// A fence line opens a block (taking its language tag) or closes the open one
+    fn toggle_fence(&mut self, fence: &str) {
+        self.fence_line = false;
+        self.in_code = !self.in_code;
+        self.lang = if self.in_code {
+            fence
+                .trim()
+                .trim_start_matches(['`', '~'])
+                .split_whitespace()
+                .next()
+                .map(str::to_string)
+        } else {
+            None
+        };
+    }
+
+    fn release(&mut self, text: &str, spans: &mut Vec<Span>) {
+        if text.is_empty() {
+            return;
+        }
+        if self.in_code {
+            self.code_line.push_str(text);
+            if text.ends_with('\n') {
+                let line = std::mem::take(&mut self.code_line);
+                push_span(spans, line, true, self.lang.clone());
+            }
+        } else {
+            push_span(spans, self.prose.push(text), false, None);
+        }
+        self.mid_line = !text.ends_with('\n');
+    }
+}
+
+/// Append `text`, merged into the last span when it has the same style
+fn push_span(spans: &mut Vec<Span>, text: String, code: bool, lang: Option<String>) {
+    if text.is_empty() {
+        return;
+    }
+    match spans.last_mut() {
+        Some(last) if last.code == code && last.lang == lang => last.text.push_str(&text),
+        _ => spans.push(Span { text, code, lang }),
+    }
+}
+
+/// Soft-wrap `text` to `width` terminal columns.
+/// Widths are measured in display columns (CJK counts double, combining marks count zero),
+/// existing newlines and leading indentation are kept, and over-long words are split on char boundaries.
+pub fn word_wrap(text: &str, width: usize) -> String {
+    let width = width.max(1);
+    text.split('\n')
+        .map(|line| wrap_line(line, width).join("\n"))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+    // Indentation wider than the line itself is not worth keeping
+    let indent = if indent.width() < width / 2 {
+        indent
+    } else {
+        ""
+    };
+
+    let mut lines = Vec::new();
+    let mut current = indent.to_string();
+    let mut current_width = indent.width();
+
+    for word in trimmed.split_whitespace() {
+        let word_width = word.width();
+        let sep = if current_width > indent.width() { 1 } else { 0 };
+
+        if current_width + sep + word_width <= width {
+            if sep == 1 {
+                current.push(' ');
+            }
+            current.push_str(word);
+            current_width += sep + word_width;
+            continue;
+        }
+
+        if current_width > indent.width() {
+            lines.push(std::mem::take(&mut current));
+            current.push_str(indent);
+            current_width = indent.width();
+        }
+
+        if current_width + word_width <= width {
+            current.push_str(word);
+            current_width += word_width;
+            continue;
+        }
+
+        // Hard-break a word that can't fit on a line of its own
+        for c in word.chars() {
+            let char_width = c.width().unwrap_or(0);
+            if current_width + char_width > width && current_width > indent.width() {
+                lines.push(std::mem::take(&mut current));
+                current.push_str(indent);
+                current_width = indent.width();
+            }
+            current.push(c);
+            current_width += char_width;
+        }
+    }
+
+    lines.push(current);
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_fits(wrapped: &str, width: usize) {
+        for line in wrapped.lines() {
+            assert!(line.width() <= width, "{:?} is wider than {}", line, width);
+        }
+    }
+
+    /// Everything but the whitespace the wrapping is allowed to change
+    fn content(text: &str) -> String {
+        text.chars().filter(|c| !c.is_whitespace()).collect()
+    }
+
+    /// The streamed wrapper fed one char at a time, the worst split a server can send
+    fn live_wrap(text: &str, width: usize) -> String {
+        let mut wrapper = LiveWrapper::new(width);
+        let mut out: String = text.chars().map(|c| wrapper.push(&c.to_string())).collect();
+        out.push_str(&wrapper.finish());
+        out
+    }
+
+    fn text_events(chunks: &[&str]) -> impl Stream<Item = Result<AgentEvent>> + Unpin {
+        let events: Vec<Result<AgentEvent>> = chunks
+            .iter()
+            .map(|chunk| Ok(AgentEvent::Text(chunk.to_string())))
+            .collect();
+        futures_util::stream::iter(events)
+    }
+
+    #[tokio::test]
+    async fn jsonl_prints_one_object_per_event_in_order() {
+        let events: Vec<Result<AgentEvent>> = vec![
+            Ok(AgentEvent::ToolCall {
+                name: "get_time_tool".to_string(),
+                args: serde_json::json!({}),
+            }),
+            Ok(AgentEvent::ToolResult {
+                name: "get_time_tool".to_string(),
+                output: "12:00".to_string(),
+            }),
+            Ok(AgentEvent::Text("It is ".to_string())),
+            Ok(AgentEvent::Text("noon.\nEnjoy lunch.".to_string())),
+            Ok(AgentEvent::Final),
+        ];
+        let options = RenderOptions {
+            jsonl: true,
+            ..RenderOptions::default()
+        };
+        let mut out = Vec::new();
+        let transcript = typewriter_to(
+            futures_util::stream::iter(events),
+            &options,
+            &CancellationToken::new(),
+            &mut out,
+        )
+        .await
+        .unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(out)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let types: Vec<&str> = lines.iter().map(|l| l["type"].as_str().unwrap()).collect();
+        assert_eq!(types, ["tool_call", "tool_result", "text", "text", "final"]);
+        assert_eq!(lines[0]["name"], "get_time_tool");
+        assert_eq!(lines[1]["output"], "12:00");
+        // A newline inside a chunk stays inside its line
+        assert_eq!(lines[3]["text"], "noon.\nEnjoy lunch.");
+        assert_eq!(transcript.text, "It is noon.\nEnjoy lunch.");
+    }
+
+    #[tokio::test]
+    async fn zero_delay_prints_at_once_and_returns_the_text() {
+        let chunk = "The quick brown fox jumps over the lazy dog. ".repeat(50);
+        let chunks = vec![chunk.as_str(); 20];
+        let options = RenderOptions {
+            wrap_len: 40,
+            char_delay: Duration::ZERO,
+            ..RenderOptions::default()
+        };
+
+        let started = std::time::Instant::now();
+        let transcript = typewriter(text_events(&chunks), &options, &CancellationToken::new())
+            .await
+            .unwrap();
+        // 45k chars, at the default 10ms each that would be minutes
+        assert!(started.elapsed() < Duration::from_secs(5));
+        assert_eq!(transcript.text, chunks.concat());
+    }
+
+    #[tokio::test]
+    async fn buffered_and_live_rendering_return_the_same_text() {
+        let chunks = [
+            "Hel",
+            "lo, wor",
+            "ld!\n```rust\nfn main",
+            "() {}\n```\n",
+            "Done.",
+        ];
+        for buffered in [false, true] {
+            let options = RenderOptions {
+                char_delay: Duration::ZERO,
+                buffered,
+                ..RenderOptions::default()
+            };
+            let transcript = typewriter(text_events(&chunks), &options, &CancellationToken::new())
+                .await
+                .unwrap();
+            assert_eq!(transcript.text, chunks.concat());
+        }
+    }
+
+    #[tokio::test]
+    async fn stdout_gets_the_answer_and_nothing_else() {
+        colored::control::set_override(false);
+        let events: Vec<Result<AgentEvent>> = vec![
+            Ok(AgentEvent::ToolCall {
+                name: "read_file_tool".into(),
+                args: serde_json::json!({ "path": "Cargo.toml" }),
+            }),
+            Ok(AgentEvent::ToolResult {
+                name: "read_file_tool".into(),
+                output: "[package]\nname = \"r-agent\"".into(),
+            }),
+            Ok(AgentEvent::Text("The crate is ".into())),
+            Ok(AgentEvent::Text("r-agent.".into())),
+            Ok(AgentEvent::Final),
+        ];
+        let options = RenderOptions {
+            char_delay: Duration::ZERO,
+            show_tools: true,
+            ..RenderOptions::default()
+        };
+
+        let mut stdout = Vec::new();
+        let transcript = typewriter_to(
+            futures_util::stream::iter(events),
+            &options,
+            &CancellationToken::new(),
+            &mut stdout,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(stdout).unwrap(),
+            "The crate is r-agent.\n"
+        );
+        assert_eq!(transcript.tool_calls.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn no_color_output_has_no_escape_sequences() {
+        colored::control::set_override(false);
+        let chunks = [
+            "Run this:\n",
+            "```rust\nfn main() { println!(\"hi\"); }\n```\n",
+            "Done.",
+        ];
+        for buffered in [false, true] {
+            let options = RenderOptions {
+                char_delay: Duration::ZERO,
+                buffered,
+                ..RenderOptions::default()
+            };
+            let mut stdout = Vec::new();
+            typewriter_to(
+                text_events(&chunks),
+                &options,
+                &CancellationToken::new(),
+                &mut stdout,
+            )
+            .await
+            .unwrap();
+            let stdout = String::from_utf8(stdout).unwrap();
+            assert!(!stdout.contains('\x1b'), "{:?}", stdout);
+            assert!(stdout.contains("fn main() { println!(\"hi\"); }"));
+        }
+        let warning = "Warning: careful".yellow().to_string();
+        assert_eq!(warning, "Warning: careful");
+    }
+
+    #[tokio::test]
+    async fn fenced_code_reaches_the_terminal_unwrapped() {
+        colored::control::set_override(false);
+        let code = "    let total: u32 = items.iter().map(|item| item.price).sum();";
+        let answer = format!(
+            "Add the prices up with an iterator, like this:\n```rust\nfn total(items: &[Item]) -> u32 {{\n{}\n    total\n}}\n```\nThat is all there is to it.\n",
+            code
+        );
+        // Chunks cut through the fence and the code line
+        let chunks: Vec<&str> = answer
+            .as_bytes()
+            .chunks(7)
+            .map(|c| std::str::from_utf8(c).unwrap())
+            .collect();
+        let render = |code_blocks: bool, buffered: bool| {
+            let options = RenderOptions {
+                wrap_len: 20,
+                char_delay: Duration::ZERO,
+                code_blocks,
+                buffered,
+                ..RenderOptions::default()
+            };
+            let chunks = chunks.clone();
+            async move {
+                let mut stdout = Vec::new();
+                typewriter_to(
+                    text_events(&chunks),
+                    &options,
+                    &CancellationToken::new(),
+                    &mut stdout,
+                )
+                .await
+                .unwrap();
+                String::from_utf8(stdout).unwrap()
+            }
+        };
+
+        for buffered in [false, true] {
+            let shown = render(true, buffered).await;
+            let lines: Vec<&str> = shown.lines().collect();
+            let open = lines.iter().position(|l| *l == "```rust").unwrap();
+            let close = lines.iter().rposition(|l| *l == "```").unwrap();
+            assert_eq!(
+                lines[open + 1..close],
+                ["fn total(items: &[Item]) -> u32 {", code, "    total", "}"]
+            );
+            for line in lines[..open].iter().chain(&lines[close + 1..]) {
+                assert!(line.width() <= 20, "{:?} in {}", line, shown);
+            }
+        }
+
+        // Off, the code is wrapped like the prose
+        let shown = render(false, false).await;
+        assert!(!shown.contains(code), "{}", shown);
+        assert_fits(&shown, 20);
+    }
+
+    #[test]
+    fn chunk_boundaries_do_not_change_the_wrapping() {
+        let text =
+            "Streaming should wrap like the whole text would, wherever the chunks split words.";
+        let expected = word_wrap(text, 20);
+        for size in [1, 3, 7, 50] {
+            let mut wrapper = LiveWrapper::new(20);
+            let chars: Vec<char> = text.chars().collect();
+            let mut out: String = chars
+                .chunks(size)
+                .map(|chunk| wrapper.push(&chunk.iter().collect::<String>()))
+                .collect();
+            out.push_str(&wrapper.finish());
+            assert_eq!(out, expected, "chunks of {}", size);
+        }
+    }
+
+    #[test]
+    fn wide_chars_count_double() {
+        let text = "日本語 の テキスト です";
+        let wrapped = word_wrap(text, 8);
+        assert_eq!(wrapped, "日本語\nの\nテキスト\nです");
+        assert_eq!(live_wrap(text, 8), wrapped);
+    }
+
+    #[test]
+    fn long_cjk_words_break_between_chars() {
+        let text = "日本語のテキストです";
+        for wrapped in [word_wrap(text, 7), live_wrap(text, 7)] {
+            assert_fits(&wrapped, 7);
+            assert_eq!(content(&wrapped), text);
+            assert_eq!(wrapped.lines().count(), 4);
+        }
+    }
+
+    #[test]
+    fn emoji_are_two_columns() {
+        let text = "🦀🦀 rust 🦀";
+        let wrapped = word_wrap(text, 6);
+        assert_eq!(wrapped, "🦀🦀\nrust\n🦀");
+        assert_eq!(live_wrap(text, 6), wrapped);
+    }
+
+    #[test]
+    fn combining_marks_take_no_column_and_stay_attached() {
+        let text = "cafe\u{301} cafe\u{301} cafe\u{301}";
+        let wrapped = word_wrap(text, 9);
+        assert_eq!(wrapped, "cafe\u{301} cafe\u{301}\ncafe\u{301}");
+        assert_eq!(live_wrap(text, 9), wrapped);
+
+        let broken = word_wrap("e\u{301}e\u{301}e\u{301}", 2);
+        assert_eq!(broken, "e\u{301}e\u{301}\ne\u{301}");
+    }
+
+    #[test]
+    fn words_longer_than_the_width_are_hard_broken() {
+        let text = "a supercalifragilistic word";
+        for wrapped in [word_wrap(text, 6), live_wrap(text, 6)] {
+            assert_fits(&wrapped, 6);
+            assert_eq!(content(&wrapped), content(text));
+        }
+        assert_eq!(word_wrap("abcdefgh", 3), "abc\ndef\ngh");
+    }
+
+    #[test]
+    fn code_blocks_are_not_wrapped() {
+        let answer =
+            "Some prose that wraps\n```rust\nfn a_long_function_name() {}\n```\nmore prose here\n";
+        let mut wrapper = MarkdownWrapper::new(10, true);
+        let mut spans: Vec<Span> = answer
+            .chars()
+            .flat_map(|c| wrapper.push(&c.to_string()))
+            .collect();
+        spans.extend(wrapper.finish());
+
+        let code: String = spans
+            .iter()
+            .filter(|s| s.code)
+            .map(|s| s.text.as_str())
+            .collect();
+        assert_eq!(code, "```rust\nfn a_long_function_name() {}\n```\n");
+        assert!(
+            spans
+                .iter()
+                .any(|s| s.lang.as_deref() == Some("rust") && s.text.contains("a_long"))
+        );
+        for span in spans.iter().filter(|s| !s.code) {
+            assert_fits(&span.text, 10);
+        }
+    }
+}
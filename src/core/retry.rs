@@ -0,0 +1,219 @@
+use anyhow::Result;
+use colored::Colorize;
+use reqwest::Response;
+use reqwest::header::{HeaderValue, RETRY_AFTER};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+/// Retry policy for transient server failures (connection errors, 429, 5xx)
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub base_delay: Duration,
+    pub max_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Exponential backoff with up to 50% random jitter, capped at `max_delay`
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let exp = self
+            .base_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.max_delay);
+        let jitter_ms = rand::random_range(0..=exp.as_millis() as u64 / 2);
+        exp + Duration::from_millis(jitter_ms)
+    }
+
+    /// Delay before retrying after `err`: the server's `Retry-After` if it sent one, else the backoff
+    pub fn delay(&self, err: &anyhow::Error, attempt: u32) -> Duration {
+        match err.downcast_ref::<RetryAfter>() {
+            Some(RetryAfter(delay)) => (*delay).min(self.max_delay),
+            None => self.backoff(attempt),
+        }
+    }
+}
+
+/// How long a server asked us to wait before trying again, attached as context to its error
+#[derive(Clone, Copy, Debug)]
+pub struct RetryAfter(pub Duration);
+
+impl fmt::Display for RetryAfter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "server asked to retry after {}s", self.0.as_secs())
+    }
+}
+
+/// Like [`Response::error_for_status`], but keeps the `Retry-After` header of a failed response
+pub fn error_for_status(response: Response) -> Result<Response> {
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(parse_retry_after);
+    match response.error_for_status() {
+        Ok(response) => Ok(response),
+        Err(e) => match retry_after {
+            Some(delay) => Err(anyhow::Error::new(e).context(RetryAfter(delay))),
+            None => Err(e.into()),
+        },
+    }
+}
+
+/// `Retry-After` is either a number of seconds or an HTTP date
+fn parse_retry_after(value: &HeaderValue) -> Option<Duration> {
+    let value = value.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = chrono::DateTime::parse_from_rfc2822(value).ok()?;
+    let wait = date.signed_duration_since(chrono::Utc::now());
+    Some(wait.to_std().unwrap_or(Duration::ZERO))
+}
+
+/// Run `op`, retrying transient failures with exponential backoff.
+/// Client errors (4xx other than 429) and non-network errors are returned immediately.
+pub async fn with_retry<T, F, Fut>(policy: &RetryPolicy, op: F) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    with_retry_while(policy, || true, op).await
+}
+
+/// [`with_retry`], but only while `retryable` returns true, e.g. as long as no tool has run
+pub async fn with_retry_while<T, F, Fut, R>(
+    policy: &RetryPolicy,
+    retryable: R,
+    mut op: F,
+) -> Result<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+    R: Fn() -> bool,
+{
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Ok(value) => return Ok(value),
+            Err(e) if attempt < policy.max_retries && is_transient(&e) && retryable() => {
+                let delay = policy.delay(&e, attempt);
+                attempt += 1;
+                warn!(error = %e, delay_ms = delay.as_millis() as u64, attempt, "retrying transient failure");
+                eprintln!(
+                    "{}",
+                    format!(
+                        "Server unavailable ({}), retrying in {}ms [{}/{}]",
+                        e,
+                        delay.as_millis(),
+                        attempt,
+                        policy.max_retries
+                    )
+                    .yellow()
+                );
+                tokio::time::sleep(delay).await;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Whether the error is worth retrying, based on the underlying reqwest error if there is one
+pub fn is_transient(err: &anyhow::Error) -> bool {
+    let Some(req_err) = err.chain().find_map(|e| e.downcast_ref::<reqwest::Error>()) else {
+        return false;
+    };
+
+    if req_err.is_connect() || req_err.is_timeout() {
+        return true;
+    }
+
+    match req_err.status() {
+        Some(status) => status.as_u16() == 429 || status.is_server_error(),
+        // Body/decode errors mid-request are usually a dropped connection
+        None => req_err.is_request() || req_err.is_body(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::test_server::{Reply, TestServer};
+    use std::time::Instant;
+
+    fn fast() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(20),
+        }
+    }
+
+    async fn get(url: &str) -> Result<String> {
+        let response = reqwest::get(url).await?;
+        Ok(error_for_status(response)?.text().await?)
+    }
+
+    #[tokio::test]
+    async fn succeeds_after_two_unavailable_responses() {
+        let unavailable = Reply::new(503, "busy");
+        let server = TestServer::start(vec![
+            unavailable.clone(),
+            unavailable,
+            Reply::new(200, "ok"),
+        ])
+        .await;
+
+        let body = with_retry(&fast(), || get(&server.url)).await.unwrap();
+        assert_eq!(body, "ok");
+        assert_eq!(server.hits(), 3);
+    }
+
+    #[tokio::test]
+    async fn client_errors_are_not_retried() {
+        let server = TestServer::start(vec![Reply::new(404, "missing")]).await;
+        assert!(with_retry(&fast(), || get(&server.url)).await.is_err());
+        assert_eq!(server.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn retry_after_is_honored_up_to_max_delay() {
+        let limited = Reply::with_headers(429, &[("Retry-After", "120")], "slow down");
+        let server = TestServer::start(vec![limited, Reply::new(200, "ok")]).await;
+
+        let started = Instant::now();
+        let body = with_retry(&fast(), || get(&server.url)).await.unwrap();
+        assert_eq!(body, "ok");
+        assert!(started.elapsed() < Duration::from_secs(5));
+
+        let limited = Reply::with_headers(429, &[("Retry-After", "3")], "");
+        let server = TestServer::start(vec![limited]).await;
+        let err = get(&server.url).await.unwrap_err();
+        assert_eq!(
+            err.downcast_ref::<RetryAfter>().unwrap().0,
+            Duration::from_secs(3)
+        );
+        assert_eq!(fast().delay(&err, 0), Duration::from_millis(20));
+    }
+
+    #[tokio::test]
+    async fn no_retry_once_not_retryable() {
+        let server = TestServer::start(vec![Reply::new(503, "busy")]).await;
+        assert!(
+            with_retry_while(&fast(), || false, || get(&server.url))
+                .await
+                .is_err()
+        );
+        assert_eq!(server.hits(), 1);
+    }
+}
@@ -1,167 +1,607 @@
+use crate::core::config::{DEFAULT_CONTEXT_WINDOW, append_system_prompt, expand_prompt_variables};
+use crate::core::debug_log::DebugLog;
+use crate::core::events::{AgentEvent, ToolCallRecord, Transcript, agent_events};
+use crate::core::messages::MessageExt;
+use crate::core::render::{RenderOptions, typewriter};
+use crate::core::retry::{RetryPolicy, is_transient, with_retry_while};
 use crate::core::session::MappedMessage;
-use crate::core::session::Session;
-use crate::core::tools::get_default_toolset;
-use anyhow::Result;
-use forge::api::agents::{Agent, AgentBuilder, prompt_with_tools_stream};
-use forge::api::dtos::MultiContent;
+use crate::core::session::{AgentSettings, CompactionPolicy, Session, validate_history};
+use crate::core::stream::{with_idle_timeout, with_reconnect};
+use crate::core::tokens::{estimate_messages, estimate_text};
+use crate::core::tools::{
+    Approver, READ_ONLY_NOTE, ToolOptions, get_observed_toolset, truncate_output,
+};
+use anyhow::{Context, Result, anyhow};
+use colored::Colorize;
+use forge::api::agents::{
+    Agent, AgentBuilder, ChunkStream, prompt_with_tools, prompt_with_tools_stream,
+};
 use forge::api::dtos::Role::{ASSISTANT, USER};
-use forge::api::dtos::{ImageUrl, Message};
-use forge::api::request::log_typewriter_effect;
+use forge::api::dtos::{FunctionCall, ImageUrl, Message, ToolCall};
+use futures_util::StreamExt;
+use serde_json::Value;
+use std::future::Future;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio_util::sync::CancellationToken;
+use tracing::debug;
+
+/// How long the runner waits on the server before giving up
+#[derive(Clone, Copy, Debug)]
+pub struct Timeouts {
+    /// Upper bound for the request to start streaming, tool iterations run inside this window
+    pub request: Duration,
+    /// Upper bound between two streamed chunks
+    pub idle: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Timeouts {
+            request: Duration::from_secs(300),
+            idle: Duration::from_secs(60),
+        }
+    }
+}
+
+/// Runner knobs coming from the CLI rather than the agent config
+#[derive(Clone, Copy, Debug)]
+pub struct RunnerOptions {
+    pub timeouts: Timeouts,
+    pub retry: RetryPolicy,
+    pub render: RenderOptions,
+    /// Summarize old session turns before sending once the session grows past the policy limit
+    pub compaction: Option<CompactionPolicy>,
+    /// Context size of the model in tokens
+    pub context_size: usize,
+    /// Refuse requests above `context_size` instead of warning, for sizes the user actually configured
+    pub enforce_context_size: bool,
+    /// Attach the default toolset, without it the model can only answer in text
+    pub tools: bool,
+    pub tool_options: ToolOptions,
+    /// Stream the answer, otherwise it is requested in one piece and printed at once
+    pub stream: bool,
+}
+
+impl Default for RunnerOptions {
+    fn default() -> Self {
+        RunnerOptions {
+            timeouts: Timeouts::default(),
+            retry: RetryPolicy::default(),
+            render: RenderOptions::default(),
+            compaction: None,
+            context_size: DEFAULT_CONTEXT_WINDOW,
+            enforce_context_size: false,
+            tools: true,
+            tool_options: ToolOptions::default(),
+            stream: true,
+        }
+    }
+}
 
 #[derive(Clone)]
 pub struct RunnerContext {
-    //TODO: Implement Plan Handling
+    /// Carries no tools, each request gets the approved and filtered toolset of [`RunnerOptions`]
     pub agent_config: Agent,
+    /// The system prompt before its variables were expanded, what sessions record so that
+    /// a new day or another directory doesn't count as a changed prompt
+    pub system_prompt_template: String,
     pub session: Option<Session>,
     pub context: Option<String>,
-    pub image_encoded: Option<String>,
+    /// URLs of the attached images (remote or `data:` URLs), in the order they were given
+    pub image_urls: Vec<String>,
+    /// High-level goal sent as a system message with every request, never stored in the session
+    pub plan: Option<String>,
+    pub options: RunnerOptions,
+    /// Stops the current turn, dropping the in-flight request and any remaining tool iterations.
+    /// Tools spawn their processes with `kill_on_drop`, so a running one dies with the dropped call
+    pub cancel: CancellationToken,
+    /// Asked before running a tool that needs approval, `None` runs them unasked
+    pub approver: Option<Approver>,
+    /// Every exchange is appended to it when set
+    pub debug_log: Option<DebugLog>,
+    /// Answers in place of the server in tests
+    #[cfg(test)]
+    pub(crate) script: Option<tests::Script>,
 }
 
 impl RunnerContext {
-    /// Preload context and tools before running the agent, because tools cant be serialized and be saved in json/toml
+    /// Preload context before running the agent. Tools are attached per request, because they cant be serialized
+    /// and be saved in json/toml, and because only then they get the approver and the event sender
     pub async fn pre_load(
-        agent_config: &str,
+        agent_builder: AgentBuilder,
         session_data: &Option<Session>,
         context: &Option<String>,
-        image_encoded: &Option<String>,
+        image_urls: &[String],
+        plan: &Option<String>,
+        options: RunnerOptions,
     ) -> Result<Self> {
-        let agent_builder: AgentBuilder = toml::from_str(agent_config)?;
-        let agent_config = agent_builder
-            .tool_registry(Arc::new(get_default_toolset()))
-            .build()?;
+        let mut agent_config = agent_builder.build()?;
+
+        if options.tool_options.read_only {
+            agent_config = append_system_prompt(&agent_config, READ_ONLY_NOTE)?;
+        }
+        let system_prompt_template = agent_config.system_prompt.clone();
+        agent_config.system_prompt = expand_prompt_variables(&system_prompt_template);
 
         Ok(Self {
             agent_config: agent_config.clone(),
+            system_prompt_template,
             session: session_data.clone(),
             context: context.clone(),
-            image_encoded: image_encoded.clone(),
+            image_urls: image_urls.to_vec(),
+            plan: plan.clone(),
+            options,
+            cancel: CancellationToken::new(),
+            approver: None,
+            debug_log: None,
+            #[cfg(test)]
+            script: None,
         })
     }
 
-    /// Run the agent with the given task and agent configuration, but without session.
-    pub async fn run(&self, task: String) -> Result<()> {
-        let mut user_prompt = task.clone();
+    /// The settings recorded in sessions, with the unexpanded system prompt
+    pub fn settings(&self) -> AgentSettings {
+        AgentSettings {
+            system_prompt: self.system_prompt_template.clone(),
+            ..AgentSettings::of(&self.agent_config)
+        }
+    }
 
-        // Add context to history if available
-        if let Some(ref ctx) = self.context {
-            user_prompt = format!("Context: {}\n\n User: {}", ctx, user_prompt);
+    /// Send the history and render the answer, bounded by the configured timeouts.
+    /// Transient failures before the stream starts are retried per the retry policy.
+    /// `progress`, when given, follows the transcript as the events come in.
+    async fn stream_history(
+        &self,
+        history: Vec<Message>,
+        progress: Option<&watch::Sender<Transcript>>,
+    ) -> Result<Transcript> {
+        let url = self.agent_config.url.clone();
+        let timeouts = self.options.timeouts;
+
+        let estimated = self.estimate_request(&history);
+        if estimated > self.options.context_size && self.options.enforce_context_size {
+            anyhow::bail!(
+                "The request is ~{} tokens, above the {} token context window. \
+                Start a new session, use --compact-after, or raise context_window/--context-size",
+                estimated,
+                self.options.context_size
+            );
+        }
+        if estimated > self.options.context_size {
+            eprintln!(
+                "{}",
+                format!(
+                    "Warning: request is ~{} tokens, above the {} token context size, the server may reject it\n",
+                    estimated, self.options.context_size
+                )
+                .yellow()
+            );
         }
 
-        // Create Message based on image presence
-        let history: Vec<Message> = match &self.image_encoded {
-            Some(encodings) => {
-                vec![Message {
-                    role: USER,
-                    content: None,
-                    multi_content: Some(vec![
-                        MultiContent {
-                            r#type: "text".to_string(),
-                            text: Some(user_prompt),
-                            image_url: None,
-                        },
-                        MultiContent {
-                            r#type: "image_url".to_string(),
-                            text: None,
-                            image_url: Some(ImageUrl {
-                                url: format!("data:image/jpg;base64,{}", encodings),
-                            }),
-                        },
-                    ]),
-                    tool_calls: None,
-                    tool_call_id: None,
-                    name: None,
-                }]
+        let (events_tx, events_rx) = tokio::sync::mpsc::unbounded_channel();
+        // A lost connection can't restart the turn once tools ran, that would run them again
+        let tools_called = Arc::new(AtomicBool::new(false));
+        let agent = if self.options.tools {
+            AgentBuilder::convert_to_builder(&self.agent_config)
+                .tool_registry(Arc::new(get_observed_toolset(
+                    events_tx,
+                    tools_called.clone(),
+                    &self.options.tool_options,
+                    self.approver.clone(),
+                )))
+                .build()?
+        } else {
+            self.agent_config.clone()
+        };
+
+        debug!(
+            %url,
+            model = %self.agent_config.model,
+            messages = history.len(),
+            estimated_tokens = estimated,
+            "sending request"
+        );
+        // Tools run inside the prompt call, so a failure after them must not repeat the call
+        let resumable = || !tools_called.load(Ordering::SeqCst);
+        let no_restart = |e: anyhow::Error| {
+            if is_transient(&e) && !resumable() {
+                e.context("Server failed after tools ran, not starting the turn over")
+            } else {
+                e
             }
-            None => {
-                vec![Message {
-                    role: USER,
-                    content: Some(user_prompt),
-                    multi_content: None,
-                    tool_calls: None,
-                    tool_call_id: None,
-                    name: None,
-                }]
+        };
+        let request = async {
+            if self.options.stream {
+                let connect = || async {
+                    let stream = with_retry_while(&self.options.retry, resumable, || {
+                        self.bounded(self.prompt_stream(agent.clone(), history.clone()))
+                    })
+                    .await
+                    .map_err(no_restart)?;
+                    Ok(Box::pin(with_idle_timeout(
+                        stream,
+                        timeouts.idle,
+                        url.clone(),
+                    )))
+                };
+                let stream = connect().await?;
+                Ok(with_reconnect(stream, connect, resumable, self.options.retry).left_stream())
+            } else {
+                let answer = with_retry_while(&self.options.retry, resumable, || {
+                    self.bounded(self.prompt(agent.clone(), history.clone()))
+                })
+                .await
+                .map_err(no_restart)?;
+                Ok(
+                    futures_util::stream::once(async { Ok(AgentEvent::Text(answer)) })
+                        .right_stream(),
+                )
             }
         };
 
-        let stream =
-            prompt_with_tools_stream(self.agent_config.clone(), history.clone(), 25).await?;
+        let stream = Box::pin(agent_events(request, events_rx).inspect(|event| {
+            if let (Some(progress), Ok(event)) = (progress, event) {
+                progress.send_modify(|transcript| transcript.record(event));
+            }
+        }));
 
-        let _ = log_typewriter_effect(120, stream).await?;
+        let mut transcript = typewriter(stream, &self.options.render, &self.cancel).await?;
+        transcript.prompt_tokens = estimated;
+        debug!(
+            chars = transcript.text.chars().count(),
+            tool_calls = transcript.tool_calls.len(),
+            cancelled = self.cancel.is_cancelled(),
+            "response received"
+        );
+        Ok(transcript)
+    }
 
-        Ok(())
+    /// forge's streaming tool loop, up to 25 tool iterations
+    async fn prompt_stream(&self, agent: Agent, history: Vec<Message>) -> Result<ChunkStream> {
+        #[cfg(test)]
+        if let Some(ref script) = self.script {
+            return script(&history);
+        }
+        prompt_with_tools_stream(agent, history, 25).await
     }
 
-    /// Run the agent session with the given task and update the session data.
-    pub async fn run_session(&self, task: String, session_data: &mut Session) -> Result<()> {
-        let mut user_prompt = task.clone();
+    /// [`RunnerContext::prompt_stream`] without streaming, the whole answer at once
+    async fn prompt(&self, agent: Agent, history: Vec<Message>) -> Result<String> {
+        #[cfg(test)]
+        if let Some(ref script) = self.script {
+            use futures_util::TryStreamExt;
+            let chunks: Vec<String> = script(&history)?.try_collect().await?;
+            return Ok(chunks.concat());
+        }
+        prompt_with_tools(agent, history, 25).await
+    }
+
+    /// Bound a request by the request timeout
+    async fn bounded<T>(&self, request: impl Future<Output = Result<T>>) -> Result<T> {
+        let timeout = self.options.timeouts.request;
+        tokio::time::timeout(timeout, request).await.map_err(|_| {
+            anyhow!(
+                "Request to {} timed out after {}s",
+                self.agent_config.url,
+                timeout.as_secs()
+            )
+        })?
+    }
+
+    /// Estimated tokens of a request made of `request` and the agent's system prompt
+    fn estimate_request(&self, request: &[Message]) -> usize {
+        estimate_messages(request) + estimate_text(&self.agent_config.system_prompt)
+    }
+
+    /// Build the user message for a task, with the context and images attached when present
+    fn user_message(&self, task: &str) -> Message {
+        let mut user_prompt = task.to_string();
 
         // Add context to history if available
         if let Some(ref ctx) = self.context {
             user_prompt = format!("Context: {}\n\n User: {}", ctx, user_prompt);
         }
 
-        // Create Message based on image presence
-        let mut history: Vec<Message> = match &self.image_encoded {
-            Some(encodings) => {
-                vec![Message {
-                    role: USER,
-                    content: None,
-                    multi_content: Some(vec![
-                        MultiContent {
-                            r#type: "text".to_string(),
-                            text: Some(user_prompt),
-                            image_url: None,
-                        },
-                        MultiContent {
-                            r#type: "image_url".to_string(),
-                            text: None,
-                            image_url: Some(ImageUrl {
-                                url: format!("data:image/jpg;base64,,{}", encodings),
-                            }),
-                        },
-                    ]),
-                    tool_calls: None,
-                    tool_call_id: None,
-                    name: None,
-                }]
+        let images = self
+            .image_urls
+            .iter()
+            .map(|url| ImageUrl { url: url.clone() })
+            .collect();
+        Message::user_with_images(&user_prompt, images)
+    }
+
+    /// The plan goes right after the agent's own system prompt (which forge inserts at index 0),
+    /// so it stays in front of the model for every tool iteration of the turn.
+    fn with_plan(&self, mut history: Vec<Message>) -> Vec<Message> {
+        if let Some(ref plan) = self.plan {
+            history.insert(
+                0,
+                Message::system_text(&format!(
+                    "High-level plan/goal for this task, keep every step aligned with it:\n{}",
+                    plan
+                )),
+            );
+        }
+        history
+    }
+
+    /// The chat completion body a turn would send, in the OpenAI shape, without contacting the server.
+    /// Returns it along with its estimated size in tokens.
+    pub fn request_preview(&self, task: &str, history: &[Message]) -> Result<(Value, usize)> {
+        let mut request = history.to_vec();
+        request.push(self.user_message(task));
+        self.request_body(request)
+    }
+
+    /// The body for `request` (history and user message, without the system prompt and plan)
+    fn request_body(&self, request: Vec<Message>) -> Result<(Value, usize)> {
+        // forge puts the agent's system prompt in front of everything
+        let mut messages = vec![Message::system_text(&self.agent_config.system_prompt)];
+        messages.extend(self.with_plan(request));
+
+        let mut body = serde_json::json!({
+            "model": self.agent_config.model,
+            "messages": messages,
+            "stream": self.options.stream,
+        });
+
+        // Sampling settings as the config spells them, unset ones are left to the server
+        let builder = serde_json::to_value(AgentBuilder::convert_to_builder(&self.agent_config))?;
+        for key in ["temperature", "top_p", "max_tokens"] {
+            if let Some(value) = builder.get(key).filter(|v| !v.is_null()) {
+                body[key] = value.clone();
+            }
+        }
+
+        if self.options.tools {
+            let tools: Vec<Value> = self
+                .options
+                .tool_options
+                .tools()
+                .iter()
+                .map(|t| t.description())
+                .collect();
+            body["tools"] = Value::Array(tools);
+        }
+
+        let estimated = estimate_messages(&messages) + estimate_text(&body["tools"].to_string());
+        Ok((body, estimated))
+    }
+
+    /// Send one user turn on top of `history`. On success the user message, the tool calls and results of the turn
+    /// and the reply are appended to it, on failure `history` is left untouched.
+    pub async fn turn(&self, task: &str, history: &mut Vec<Message>) -> Result<Transcript> {
+        self.turn_message(self.user_message(task), history).await
+    }
+
+    /// [`RunnerContext::turn`] with a ready user message, context and images are not attached again
+    pub async fn turn_message(
+        &self,
+        user_message: Message,
+        history: &mut Vec<Message>,
+    ) -> Result<Transcript> {
+        self.turn_with_progress(user_message, history, None).await
+    }
+
+    async fn turn_with_progress(
+        &self,
+        user_message: Message,
+        history: &mut Vec<Message>,
+        progress: Option<&watch::Sender<Transcript>>,
+    ) -> Result<Transcript> {
+        let mut request = history.clone();
+        request.push(user_message);
+        // The server would answer a broken tool call pairing with a bare 400
+        validate_history(&request).context("The history can't be sent")?;
+
+        let result = self
+            .stream_history(self.with_plan(request.clone()), progress)
+            .await;
+        if let Some(ref log) = self.debug_log {
+            self.log_exchange(log, request.clone(), &result).await;
+        }
+        let transcript = result?;
+        // A turn cancelled before any text arrived leaves no trace in the history
+        if self.cancel.is_cancelled() && transcript.text.is_empty() {
+            return Ok(transcript);
+        }
+        let call_prefix = format!("call_{}", request.len());
+        request.extend(tool_messages(&transcript.tool_calls, &call_prefix));
+        request.push(Message::assistant_text(&transcript.text));
+
+        *history = request;
+        Ok(transcript)
+    }
+
+    /// Write the request as handed to forge and what came back, a failing log never fails the turn
+    async fn log_exchange(
+        &self,
+        log: &DebugLog,
+        request: Vec<Message>,
+        result: &Result<Transcript>,
+    ) {
+        let body = self
+            .request_body(request)
+            .map(|(body, _)| body)
+            .unwrap_or_else(|e| Value::String(format!("{:#}", e)));
+        let mut entry = serde_json::json!({
+            "timestamp": chrono::Utc::now().to_rfc3339(),
+            "url": self.agent_config.url,
+            "request": body,
+        });
+        match result {
+            Ok(transcript) => {
+                entry["response"] = serde_json::json!({
+                    "text": transcript.text,
+                    "tool_calls": transcript.tool_calls,
+                    "cancelled": self.cancel.is_cancelled(),
+                });
             }
-            None => {
-                vec![Message {
-                    role: USER,
-                    content: Some(user_prompt),
-                    multi_content: None,
-                    tool_calls: None,
-                    tool_call_id: None,
-                    name: None,
-                }]
+            Err(e) => entry["error"] = Value::String(format!("{:#}", e)),
+        }
+        if let Err(e) = log.append(&entry).await {
+            eprintln!(
+                "{}",
+                format!("Warning: failed to write the debug log: {:#}", e).yellow()
+            );
+        }
+    }
+
+    /// Cancel the running turn on Ctrl-C, the partial answer is kept like a complete one
+    pub fn cancel_on_ctrl_c(&self) {
+        let cancel = self.cancel.clone();
+        tokio::spawn(async move {
+            if tokio::signal::ctrl_c().await.is_ok() {
+                cancel.cancel();
             }
-        };
+        });
+    }
 
-        let stream =
-            prompt_with_tools_stream(self.agent_config.clone(), history.clone(), 25).await?;
-
-        let stream_to_str = log_typewriter_effect(120, stream).await?;
-        let agent_message = Message {
-            role: ASSISTANT,
-            content: Some(stream_to_str),
-            multi_content: None,
-            tool_calls: None,
-            tool_call_id: None,
-            name: None,
-        };
-        history.push(agent_message);
+    /// Run the agent with the given task and agent configuration, but without session.
+    pub async fn run(&self, task: String) -> Result<Transcript> {
+        self.turn(&task, &mut Vec::new()).await
+    }
+
+    /// Run the agent session with the given task and update the session data.
+    pub async fn run_session(
+        &self,
+        task: String,
+        session_data: &mut Session,
+    ) -> Result<Transcript> {
+        if let Some(policy) = self.options.compaction
+            && session_data.needs_compaction(&policy)
+        {
+            eprintln!("Compacting session history...\n");
+            session_data.compact(&self.agent_config, &policy).await?;
+        }
+
+        // Whatever the message count, a session that no longer fits is compacted rather than sent
+        let mut request = session_data.context_messages();
+        request.push(self.user_message(&task));
+        if self.estimate_request(&self.with_plan(request)) > self.options.context_size {
+            eprintln!("Session is over the context window, compacting...\n");
+            let policy = self.options.compaction.unwrap_or_default();
+            session_data.compact(&self.agent_config, &policy).await?;
+        }
 
-        // Update session messages
-        session_data.messages = history;
         session_data.last_model_used = self.agent_config.model.clone();
+        session_data.settings = Some(self.settings());
+
+        // Prior turns go first so the model sees the whole conversation
+        let mut history = session_data.context_messages();
+        let user_message = self.user_message(&task);
+        let sent_at = chrono::Utc::now();
+
+        // The turn so far is saved while it streams, a killed run still leaves it on disk
+        let (progress, progress_rx) = watch::channel(Transcript::default());
+        let mut partial = history.clone();
+        partial.push(user_message.clone());
+        let autosave = tokio::spawn(autosave(session_data.clone(), partial, progress_rx));
+        let result = self
+            .turn_with_progress(user_message, &mut history, Some(&progress))
+            .await;
+        // The last autosave must land before the final save, not after it
+        drop(progress);
+        let _ = autosave.await;
+        let transcript = result?;
+
+        // Update session messages, history still holds every earlier turn
+        session_data.update_messages(history, sent_at);
         session_data.save_to_disk().await?;
 
-        Ok(())
+        Ok(transcript)
+    }
+}
+
+/// How often a streaming turn is saved at most, finished tool calls are saved right away
+const AUTOSAVE_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Save `session` with `request` and the partial turn each time `progress` moves on, until its sender is dropped.
+/// A failing save only warns once, the final save at the end of the turn reports for real.
+async fn autosave(
+    mut session: Session,
+    request: Vec<Message>,
+    mut progress: watch::Receiver<Transcript>,
+) {
+    let mut last_save: Option<Instant> = None;
+    let mut saved_results = 0;
+    let mut warned = false;
+
+    while progress.changed().await.is_ok() {
+        let transcript = progress.borrow_and_update().clone();
+        let results = transcript
+            .tool_calls
+            .iter()
+            .filter(|c| c.output.is_some())
+            .count();
+        if results == saved_results && last_save.is_some_and(|t| t.elapsed() < AUTOSAVE_INTERVAL) {
+            continue;
+        }
+
+        let mut messages = request.clone();
+        messages.extend(tool_messages(
+            &transcript.tool_calls,
+            &format!("call_{}", request.len()),
+        ));
+        if !transcript.text.is_empty() {
+            messages.push(Message::assistant_text(&transcript.text));
+        }
+        session.messages = messages;
+        if let Err(e) = session.save_to_disk().await
+            && !warned
+        {
+            warned = true;
+            eprintln!(
+                "{}",
+                format!("Warning: failed to autosave the session: {:#}", e).yellow()
+            );
+        }
+        last_save = Some(Instant::now());
+        saved_results = results;
     }
 }
 
+/// Tool outputs longer than this are cut when stored, the model can run the call again for the rest
+pub const STORED_TOOL_OUTPUT_LIMIT: usize = 4000;
+
+/// The tool calls of a turn in the shape the server expects in a history: one assistant message carrying the calls,
+/// then one TOOL message per result. forge doesn't report its call ids, so they are made up from `id_prefix`.
+/// Calls that never returned (the turn was cancelled) are left out, a call without a result would be rejected.
+fn tool_messages(calls: &[ToolCallRecord], id_prefix: &str) -> Vec<Message> {
+    let finished: Vec<(String, &ToolCallRecord, &String)> = calls
+        .iter()
+        .enumerate()
+        .filter_map(|(i, call)| Some((format!("{}_{}", id_prefix, i), call, call.output.as_ref()?)))
+        .collect();
+    if finished.is_empty() {
+        return Vec::new();
+    }
+
+    let calls = finished
+        .iter()
+        .map(|(id, call, _)| ToolCall {
+            id: id.clone(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: call.name.clone(),
+                arguments: call.args.to_string(),
+            },
+        })
+        .collect();
+    let mut messages = vec![Message::assistant_tool_calls(calls)];
+    messages.extend(finished.into_iter().map(|(id, call, output)| {
+        Message::tool_result(
+            &id,
+            &call.name,
+            &truncate_output(output, STORED_TOOL_OUTPUT_LIMIT),
+        )
+    }));
+    messages
+}
+
 #[allow(unused)]
 pub fn map_message_to(message: &Message) -> MappedMessage {
     match message.role {
@@ -190,21 +630,474 @@ pub fn map_message_to(message: &Message) -> MappedMessage {
 #[allow(unused)]
 pub fn map_message_from(message: &MappedMessage) -> Message {
     match message {
-        MappedMessage::User(content) => Message {
-            role: forge::api::dtos::Role::USER,
-            content: Some(content.clone()),
-            multi_content: None,
-            tool_calls: None,
-            tool_call_id: None,
-            name: None,
-        },
-        MappedMessage::Agent(content) => Message {
-            role: forge::api::dtos::Role::ASSISTANT,
-            content: Some(content.clone()),
-            multi_content: None,
-            tool_calls: None,
-            tool_call_id: None,
-            name: None,
-        },
+        MappedMessage::User(content) => Message::user_text(content),
+        MappedMessage::Agent(content) => Message::assistant_text(content),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::core::config::tests::test_home;
+    use crate::core::config::{ConfigOverrides, parse_agent_builder};
+    use crate::core::session::{get_default_session_path, load_session};
+    use crate::core::test_server::{Reply, TestServer};
+    use forge::api::dtos::Role::{self, SYSTEM, TOOL};
+    use std::path::PathBuf;
+    use std::sync::atomic::AtomicUsize;
+
+    pub(crate) const TEST_API_KEY: &str = "sk-test-0123456789abcdef";
+
+    /// Stands in for forge's prompt call: gets the request's messages, returns the answer's chunks
+    pub(crate) type Script = Arc<dyn Fn(&[Message]) -> Result<ChunkStream> + Send + Sync>;
+
+    /// A script answering every request with `chunks`, counting the requests in `calls`
+    pub(crate) fn answer(chunks: &[&str], calls: Arc<AtomicUsize>) -> Script {
+        let chunks: Vec<String> = chunks.iter().map(|c| c.to_string()).collect();
+        Arc::new(move |_| {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Ok(Box::pin(futures_util::stream::iter(
+                chunks.clone().into_iter().map(Ok),
+            )))
+        })
+    }
+
+    /// The messages of every request a script was given, in order
+    pub(crate) type Requests = Arc<std::sync::Mutex<Vec<Vec<Message>>>>;
+
+    /// [`answer`], keeping each request in `requests`
+    pub(crate) fn record(chunks: &[&str], requests: Requests) -> Script {
+        let reply = answer(chunks, Arc::new(AtomicUsize::new(0)));
+        Arc::new(move |history| {
+            requests.lock().unwrap().push(history.to_vec());
+            reply(history)
+        })
+    }
+
+    /// Options for tests: nothing printed, no delays, no retries
+    pub(crate) fn quiet_options() -> RunnerOptions {
+        RunnerOptions {
+            retry: RetryPolicy {
+                max_retries: 0,
+                ..RetryPolicy::default()
+            },
+            render: RenderOptions {
+                char_delay: Duration::ZERO,
+                silent: true,
+                show_tools: false,
+                ..RenderOptions::default()
+            },
+            ..RunnerOptions::default()
+        }
+    }
+
+    /// A runner on a config pointing at nothing, see [`RunnerContext::pre_load`]
+    pub(crate) async fn test_runner(options: RunnerOptions) -> RunnerContext {
+        let config = format!(
+            r#"
+            model = "qwen3-8b"
+            url = "http://127.0.0.1:9/v1"
+            api_key = "{}"
+            system_prompt = "You are a test agent."
+            temperature = 0.5
+            top_p = 0.9
+            "#,
+            TEST_API_KEY
+        );
+        let builder = parse_agent_builder(&config, &ConfigOverrides::default()).unwrap();
+        RunnerContext::pre_load(builder, &None, &None, &[], &None, options)
+            .await
+            .unwrap()
+    }
+
+    /// A script whose answer is the body of a GET to `url`, sent once the stream is polled
+    fn fetch(url: String) -> Script {
+        Arc::new(move |_| {
+            let url = url.clone();
+            Ok(Box::pin(futures_util::stream::once(async move {
+                Ok(reqwest::get(url).await?.text().await?)
+            })))
+        })
+    }
+
+    #[tokio::test]
+    async fn two_session_turns_leave_four_messages() {
+        test_home();
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut runner = test_runner(quiet_options()).await;
+        runner.script = Some(answer(&["Noted."], calls.clone()));
+        let mut session = Session::new("runner-two-turns", "qwen3-8b", PathBuf::new());
+
+        runner
+            .run_session("My name is Ada.".to_string(), &mut session)
+            .await
+            .unwrap();
+        runner
+            .run_session("What is my name?".to_string(), &mut session)
+            .await
+            .unwrap();
+
+        let saved = load_session("runner-two-turns").await.unwrap();
+        let contents: Vec<&str> = saved
+            .messages
+            .iter()
+            .map(|m| m.content.as_deref().unwrap())
+            .collect();
+        assert_eq!(
+            contents,
+            ["My name is Ada.", "Noted.", "What is my name?", "Noted."]
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn tool_turn_is_saved_in_order() {
+        test_home();
+        let session = Session::new("runner-tool-turn", "qwen3-8b", PathBuf::new());
+        let request = vec![Message::user_text("What is in Cargo.toml?")];
+        let (progress, progress_rx) = watch::channel(Transcript::default());
+        let saving = tokio::spawn(autosave(session, request, progress_rx));
+        progress.send_modify(|transcript| {
+            transcript.tool_calls = vec![
+                ToolCallRecord {
+                    name: "list_tool".to_string(),
+                    args: serde_json::json!({ "path": "." }),
+                    output: Some("Cargo.toml\nsrc/".to_string()),
+                },
+                ToolCallRecord {
+                    name: "read_file_tool".to_string(),
+                    args: serde_json::json!({ "path": "Cargo.toml" }),
+                    output: Some("x".repeat(STORED_TOOL_OUTPUT_LIMIT * 2)),
+                },
+            ];
+            transcript.text = "The package manifest.".to_string();
+        });
+        drop(progress);
+        saving.await.unwrap();
+
+        let saved = load_session("runner-tool-turn").await.unwrap();
+        let roles: Vec<&Role> = saved.messages.iter().map(|m| &m.role).collect();
+        assert_eq!(roles, [&USER, &ASSISTANT, &TOOL, &TOOL, &ASSISTANT]);
+
+        let calls = saved.messages[1].tool_calls.as_ref().unwrap();
+        let ids: Vec<&str> = calls.iter().map(|c| c.id.as_str()).collect();
+        assert_eq!(ids, ["call_1_0", "call_1_1"]);
+        assert_eq!(calls[0].function.name, "list_tool");
+        assert_eq!(calls[1].function.arguments, r#"{"path":"Cargo.toml"}"#);
+
+        let (listing, read) = (&saved.messages[2], &saved.messages[3]);
+        assert_eq!(listing.tool_call_id.as_deref(), Some("call_1_0"));
+        assert_eq!(listing.content.as_deref(), Some("Cargo.toml\nsrc/"));
+        assert_eq!(read.tool_call_id.as_deref(), Some("call_1_1"));
+        // Stored cut, the model can read the file again
+        assert!(
+            read.content
+                .as_deref()
+                .unwrap()
+                .ends_with("[truncated, 4000 of 8000 chars kept]")
+        );
+        assert_eq!(
+            saved.messages[4].content.as_deref(),
+            Some("The package manifest.")
+        );
+    }
+
+    #[tokio::test]
+    async fn debug_log_gets_one_entry_per_exchange() {
+        let path = crate::core::data::tests::temp_dir("debug-log").join("wire.jsonl");
+        let mut runner = test_runner(quiet_options()).await;
+        runner.debug_log = Some(DebugLog::new(path.clone()));
+
+        runner.script = Some(answer(&["It is ", "noon."], Arc::new(AtomicUsize::new(0))));
+        runner.run("What time is it?".to_string()).await.unwrap();
+        runner.script = Some(Arc::new(|_| Err(anyhow!("connection refused"))));
+        runner.run("And now?".to_string()).await.unwrap_err();
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        assert!(!log.contains(TEST_API_KEY));
+        let entries: Vec<Value> = log
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(entries.len(), 2);
+
+        let answered = &entries[0];
+        assert!(
+            chrono::DateTime::parse_from_rfc3339(answered["timestamp"].as_str().unwrap()).is_ok()
+        );
+        assert_eq!(answered["url"], "http://127.0.0.1:9/v1");
+        assert_eq!(answered["request"]["model"], "qwen3-8b");
+        let messages = answered["request"]["messages"].as_array().unwrap();
+        assert_eq!(messages[0]["role"], "system");
+        assert_eq!(messages.last().unwrap()["content"], "What time is it?");
+        assert_eq!(answered["response"]["text"], "It is noon.");
+        assert_eq!(answered["response"]["cancelled"], false);
+
+        let failed = &entries[1];
+        assert!(failed.get("response").is_none());
+        assert!(
+            failed["error"]
+                .as_str()
+                .unwrap()
+                .contains("connection refused"),
+            "{}",
+            failed
+        );
+    }
+
+    #[tokio::test]
+    async fn killed_run_leaves_the_partial_answer_on_disk() {
+        test_home();
+        let mut runner = test_runner(quiet_options()).await;
+        // The first chunk arrives, then the server goes quiet until the process dies
+        runner.script = Some(Arc::new(|_| {
+            let chunk = futures_util::stream::once(async { Ok("Half an ans".to_string()) });
+            Ok(Box::pin(chunk.chain(futures_util::stream::pending())))
+        }));
+        let mut session = Session::new("runner-killed", "qwen3-8b", PathBuf::new());
+        let run = tokio::spawn(async move {
+            runner
+                .run_session("Explain lifetimes.".to_string(), &mut session)
+                .await
+        });
+
+        let file = get_default_session_path()
+            .unwrap()
+            .join("runner-killed.json");
+        let saved = tokio::time::timeout(Duration::from_secs(5), async {
+            loop {
+                if let Ok(saved) = load_session("runner-killed").await
+                    && saved.messages.len() == 2
+                {
+                    return saved;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the partial turn was never saved");
+        // The crash
+        run.abort();
+        assert!(run.await.unwrap_err().is_cancelled());
+
+        let contents: Vec<&str> = saved
+            .messages
+            .iter()
+            .map(|m| m.content.as_deref().unwrap())
+            .collect();
+        assert_eq!(contents, ["Explain lifetimes.", "Half an ans"]);
+        // Written whole, never a torn file
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+        assert_eq!(json["name"], "runner-killed");
+    }
+
+    #[tokio::test]
+    async fn second_turn_sends_the_first_one() {
+        test_home();
+        let requests = Requests::default();
+        let mut runner = test_runner(quiet_options()).await;
+        runner.script = Some(record(&["Noted."], requests.clone()));
+        let mut session = Session::new("runner-context", "qwen3-8b", PathBuf::new());
+        // Sessions saved by older versions stored the system prompt, forge adds its own
+        session.messages = vec![Message::system_text("You are a test agent.")];
+
+        for task in ["My name is Ada.", "What is my name?"] {
+            runner
+                .run_session(task.to_string(), &mut session)
+                .await
+                .unwrap();
+        }
+
+        let requests = requests.lock().unwrap();
+        let second: Vec<(&str, &str)> = requests[1]
+            .iter()
+            .map(|m| {
+                let role = match m.role {
+                    USER => "user",
+                    ASSISTANT => "assistant",
+                    _ => "other",
+                };
+                (role, m.content.as_deref().unwrap())
+            })
+            .collect();
+        assert_eq!(
+            second,
+            [
+                ("user", "My name is Ada."),
+                ("assistant", "Noted."),
+                ("user", "What is my name?"),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn read_only_mode_tells_the_model() {
+        let normal = test_runner(quiet_options()).await;
+        assert!(!normal.agent_config.system_prompt.contains(READ_ONLY_NOTE));
+
+        let mut options = quiet_options();
+        options.tool_options.read_only = true;
+        let read_only = test_runner(options).await;
+        assert!(
+            read_only
+                .agent_config
+                .system_prompt
+                .starts_with("You are a test agent.")
+        );
+        assert!(
+            read_only
+                .agent_config
+                .system_prompt
+                .contains(READ_ONLY_NOTE)
+        );
+    }
+
+    #[tokio::test]
+    async fn plan_is_sent_with_every_request() {
+        let requests = Requests::default();
+        let mut runner = test_runner(quiet_options()).await;
+        runner.plan = Some("Rename the crate, then fix the imports.".to_string());
+        runner.script = Some(record(&["On it."], requests.clone()));
+
+        let mut history = Vec::new();
+        runner.turn("Start", &mut history).await.unwrap();
+        runner.turn("Go on", &mut history).await.unwrap();
+
+        for request in requests.lock().unwrap().iter() {
+            let first = request[0].content.as_deref().unwrap();
+            assert!(matches!(request[0].role, SYSTEM));
+            assert!(first.ends_with("Rename the crate, then fix the imports."));
+        }
+        // The plan is not part of the conversation itself
+        assert_eq!(history.len(), 4);
+        assert!(history.iter().all(|m| !matches!(m.role, SYSTEM)));
+
+        let (body, _) = runner.request_preview("Go on", &history).unwrap();
+        let messages = body["messages"].as_array().unwrap();
+        assert_eq!(messages[0]["content"], "You are a test agent.");
+        assert!(
+            messages[1]["content"]
+                .as_str()
+                .unwrap()
+                .contains("Rename the crate")
+        );
+    }
+
+    #[tokio::test]
+    async fn each_image_gets_its_own_block_after_the_text() {
+        let requests = Requests::default();
+        let mut runner = test_runner(quiet_options()).await;
+        runner.image_urls = vec![
+            "https://example.com/before.png".to_string(),
+            "data:image/png;base64,iVBORw0KGgo=".to_string(),
+        ];
+        runner.script = Some(record(&["They differ."], requests.clone()));
+
+        runner.run("Compare these".to_string()).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        let parts = requests[0][0].multi_content.as_ref().unwrap();
+        let kinds: Vec<&str> = parts.iter().map(|p| p.r#type.as_str()).collect();
+        assert_eq!(kinds, ["text", "image_url", "image_url"]);
+        assert_eq!(parts[0].text.as_deref(), Some("Compare these"));
+        let urls: Vec<&str> = parts[1..]
+            .iter()
+            .map(|p| p.image_url.as_ref().unwrap().url.as_str())
+            .collect();
+        assert_eq!(urls, runner.image_urls);
+    }
+
+    /// A script that sends `first`, cancels the turn and then stalls like a server still generating
+    fn cancel_after(first: &str, cancel: CancellationToken) -> Script {
+        let first = first.to_string();
+        Arc::new(move |_| {
+            let first = first.clone();
+            let cancel = cancel.clone();
+            let chunk = futures_util::stream::once(async move {
+                cancel.cancel();
+                Ok(first)
+            });
+            Ok(Box::pin(chunk.chain(futures_util::stream::pending())))
+        })
+    }
+
+    #[tokio::test]
+    async fn cancelling_keeps_the_text_so_far() {
+        let mut runner = test_runner(quiet_options()).await;
+        runner.script = Some(cancel_after("Hello", runner.cancel.clone()));
+
+        let mut history = Vec::new();
+        let turn = runner.turn("Say hello at length", &mut history);
+        let transcript = tokio::time::timeout(Duration::from_secs(5), turn)
+            .await
+            .expect("the turn should stop once cancelled")
+            .unwrap();
+        assert_eq!(transcript.text, "Hello");
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[1].content.as_deref(), Some("Hello"));
+
+        // Cancelled before any text, the turn leaves no trace
+        let mut runner = test_runner(quiet_options()).await;
+        runner.script = Some(cancel_after("", runner.cancel.clone()));
+        let transcript = runner.turn("Again", &mut history).await.unwrap();
+        assert!(transcript.text.is_empty());
+        assert_eq!(history.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn slow_server_times_out_with_its_url() {
+        let server = TestServer::start(vec![
+            Reply::new(200, "late").delayed(Duration::from_secs(10)),
+        ])
+        .await;
+        let timeouts = Timeouts {
+            request: Duration::from_secs(1),
+            idle: Duration::from_secs(1),
+        };
+        let mut runner = test_runner(RunnerOptions {
+            timeouts,
+            ..quiet_options()
+        })
+        .await;
+        runner.script = Some(fetch(server.url.clone()));
+
+        // Streaming, the request starts at once and the first chunk never comes
+        let err = runner.run("hi".to_string()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "No data received from http://127.0.0.1:9/v1 for 1s, giving up"
+        );
+
+        // In one piece, the whole request runs into the request timeout
+        runner.options.stream = false;
+        let err = runner.run("hi".to_string()).await.unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Request to http://127.0.0.1:9/v1 timed out after 1s"
+        );
+        assert_eq!(server.hits(), 2);
+    }
+
+    #[tokio::test]
+    async fn context_guard_refuses_before_sending() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let mut runner = test_runner(RunnerOptions {
+            context_size: 100,
+            enforce_context_size: true,
+            ..quiet_options()
+        })
+        .await;
+        runner.script = Some(answer(&["ok"], calls.clone()));
+        let task = "word ".repeat(1000);
+
+        let err = runner.run(task.clone()).await.unwrap_err();
+        assert!(err.to_string().contains("context window"), "{}", err);
+        assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+        // Without an enforced size the request only gets a warning and goes out
+        runner.options.enforce_context_size = false;
+        let transcript = runner.run(task).await.unwrap();
+        assert_eq!(transcript.text, "ok");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
     }
 }
@@ -1,16 +1,118 @@
+use crate::core::config::get_base_path;
+use crate::core::data::{StoredEntry, list_stored, write_atomic};
+use crate::core::messages::MessageExt;
+use crate::core::tokens::estimate_messages;
 use anyhow::Result;
+use chrono::{DateTime, Utc};
+use colored::Colorize;
+use forge::api::agents::{Agent, prompt_with_tools};
 use forge::api::dtos::Message;
+use forge::api::dtos::Role::{ASSISTANT, SYSTEM, TOOL, USER};
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use tokio::fs;
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct Session {
     pub name: String,
     pub last_model_used: String,
+    /// Directory of the session file, not stored: files are always read and written under the current home
+    #[serde(skip)]
     pub path: PathBuf,
     pub messages: Vec<Message>,
+    /// When each message was recorded, index for index with `messages`. Kept apart from the messages
+    /// because servers reject unknown fields on them. `None` for messages saved before times were kept
+    #[serde(default)]
+    pub message_times: Vec<Option<DateTime<Utc>>>,
+    /// Sessions saved before timestamps existed default to the unix epoch
+    #[serde(default)]
+    pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub updated_at: DateTime<Utc>,
+    /// The agent settings of the last turn, `None` for sessions saved before they were recorded
+    #[serde(default)]
+    pub settings: Option<AgentSettings>,
 }
+
+/// Snapshot of the settings that shape the answers, kept so a resumed session can tell when the config changed
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct AgentSettings {
+    pub temperature: f32,
+    pub top_p: f32,
+    pub max_tokens: Option<u32>,
+    pub system_prompt: String,
+}
+
+impl AgentSettings {
+    pub fn of(agent: &Agent) -> Self {
+        AgentSettings {
+            temperature: agent.temperature,
+            top_p: agent.top_p,
+            max_tokens: agent.max_tokens,
+            system_prompt: agent.system_prompt.clone(),
+        }
+    }
+
+    /// What changed from `self` (the stored snapshot) to `current`, one line per setting
+    pub fn differences(&self, current: &AgentSettings) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.temperature != current.temperature {
+            changes.push(format!(
+                "temperature {} -> {}",
+                self.temperature, current.temperature
+            ));
+        }
+        if self.top_p != current.top_p {
+            changes.push(format!("top_p {} -> {}", self.top_p, current.top_p));
+        }
+        if self.max_tokens != current.max_tokens {
+            let show = |v: Option<u32>| v.map_or("unset".to_string(), |v| v.to_string());
+            changes.push(format!(
+                "max_tokens {} -> {}",
+                show(self.max_tokens),
+                show(current.max_tokens)
+            ));
+        }
+        if self.system_prompt != current.system_prompt {
+            changes.push("system prompt changed".to_string());
+        }
+        changes
+    }
+}
+/// Size and recency of a session, to decide which ones to compact or delete
+#[derive(Clone, Debug)]
+pub struct SessionStats {
+    pub messages: usize,
+    pub tool_calls: usize,
+    pub estimated_tokens: usize,
+    pub last_model_used: String,
+    pub updated_at: DateTime<Utc>,
+    /// Mean time from a user message to the last message of its turn, over the turns with both times
+    pub average_response: Option<chrono::Duration>,
+}
+
+/// When and how much of a session gets summarized
+#[derive(Clone, Copy, Debug)]
+pub struct CompactionPolicy {
+    /// Compact once the session holds more messages than this
+    pub max_messages: usize,
+    /// Messages at the end of the session that are always kept verbatim
+    pub keep_recent: usize,
+}
+
+impl Default for CompactionPolicy {
+    fn default() -> Self {
+        CompactionPolicy {
+            max_messages: 40,
+            keep_recent: 10,
+        }
+    }
+}
+
+const COMPACTION_PROMPT: &str = "Summarize the following conversation between a user and a coding agent. \
+Keep every fact, decision, file path and open question that later turns may rely on. \
+Answer with the summary only, do not call any tools.";
+
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub enum MappedMessage {
     User(String),
@@ -19,36 +121,322 @@ pub enum MappedMessage {
 
 impl Session {
     pub fn new(name: &str, model_used: &str, path: PathBuf) -> Self {
+        let now = Utc::now();
         Session {
             name: name.to_string(),
             last_model_used: model_used.to_string(),
             path: get_default_session_path().unwrap_or(path),
             messages: Vec::new(),
+            message_times: Vec::new(),
+            created_at: now,
+            updated_at: now,
+            settings: None,
         }
     }
 
-    pub async fn save_to_disk(&self) -> Result<()> {
+    /// Stored turns to send ahead of a new message.
+    /// forge inserts the agent's system prompt itself, so stored system messages are left out.
+    pub fn context_messages(&self) -> Vec<Message> {
+        self.messages
+            .iter()
+            .filter(|m| !matches!(m.role, SYSTEM))
+            .cloned()
+            .collect()
+    }
+
+    /// Render the conversation as Markdown, system messages are left out
+    pub fn to_markdown(&self) -> String {
+        format!(
+            "# Session: {}\n\n_Last model used: {} - created {}, updated {}_\n{}",
+            self.name,
+            self.last_model_used,
+            self.created_at.format("%Y-%m-%d %H:%M UTC"),
+            self.updated_at.format("%Y-%m-%d %H:%M UTC"),
+            render_messages(&self.messages, &self.message_times)
+        )
+    }
+
+    /// Replace the messages, stamping the ones added since the last update: user messages with `sent_at`,
+    /// the rest of the turn (tool calls, results and the answer) with the current time
+    pub fn update_messages(&mut self, messages: Vec<Message>, sent_at: DateTime<Utc>) {
+        self.messages = messages;
+        self.stamp_messages(sent_at);
+    }
+
+    fn stamp_messages(&mut self, sent_at: DateTime<Utc>) {
+        let now = Utc::now();
+        self.message_times.truncate(self.messages.len());
+        let stamped = self.message_times.len();
+        for message in &self.messages[stamped..] {
+            let time = if matches!(message.role, USER) {
+                sent_at
+            } else {
+                now
+            };
+            self.message_times.push(Some(time));
+        }
+    }
+
+    fn average_response(&self) -> Option<chrono::Duration> {
+        let mut total = chrono::Duration::zero();
+        let mut turns = 0;
+        let mut sent: Option<DateTime<Utc>> = None;
+        let mut answered: Option<DateTime<Utc>> = None;
+        // A user message closes the turn before it, a `None` entry closes the whole session
+        let ends = self
+            .messages
+            .iter()
+            .map(|m| matches!(m.role, USER))
+            .chain([true]);
+        for (is_user, time) in ends.zip(self.message_times.iter().copied().chain([None])) {
+            if is_user {
+                if let (Some(sent), Some(answered)) = (sent, answered) {
+                    total += answered - sent;
+                    turns += 1;
+                }
+                sent = time;
+                answered = None;
+            } else if time.is_some() {
+                answered = time;
+            }
+        }
+        (turns > 0).then(|| total / turns)
+    }
+
+    pub fn stats(&self) -> SessionStats {
+        SessionStats {
+            messages: self.messages.len(),
+            tool_calls: self
+                .messages
+                .iter()
+                .filter_map(|m| m.tool_calls.as_ref())
+                .map(|calls| calls.len())
+                .sum(),
+            estimated_tokens: estimate_messages(&self.messages),
+            last_model_used: self.last_model_used.clone(),
+            updated_at: self.updated_at,
+            average_response: self.average_response(),
+        }
+    }
+
+    pub fn needs_compaction(&self, policy: &CompactionPolicy) -> bool {
+        self.messages.len() > policy.max_messages
+    }
+
+    /// Ask the agent to summarize the oldest turns and replace them with a single assistant note,
+    /// keeping the most recent messages verbatim. Returns whether anything was compacted.
+    /// Summarizing needs no tools, `agent` should carry none: its calls would skip approval and filtering.
+    pub async fn compact(&mut self, agent: &Agent, policy: &CompactionPolicy) -> Result<bool> {
+        let Some(split) = self.compaction_split(policy.keep_recent) else {
+            return Ok(false);
+        };
+
+        let request = vec![Message::user_text(&format!(
+            "{}\n\n{}",
+            COMPACTION_PROMPT,
+            render_messages(&self.messages[..split], &[])
+        ))];
+        let summary = prompt_with_tools(agent.clone(), request, 5).await?;
+        self.replace_with_summary(split, &summary);
+        Ok(true)
+    }
+
+    /// Swap the messages before `split` for a single assistant note holding `summary`
+    fn replace_with_summary(&mut self, split: usize, summary: &str) {
+        let note = Message::assistant_text(&format!(
+            "[Summary of the earlier conversation]\n{}",
+            summary.trim()
+        ));
+        self.messages.splice(..split, [note]);
+        // The note stands in for the summarized messages, it takes the time of the last one
+        if self.message_times.len() >= split {
+            let summarized = self.message_times[split - 1];
+            self.message_times.splice(..split, [summarized]);
+        }
+    }
+
+    /// Index of the first message to keep. The cut is moved back to the start of a user turn
+    /// so an assistant tool call is never separated from its tool results.
+    fn compaction_split(&self, keep_recent: usize) -> Option<usize> {
+        let mut split = self.messages.len().checked_sub(keep_recent.max(1))?;
+        while split > 0 && !matches!(self.messages[split].role, USER) {
+            split -= 1;
+        }
+        // A single old message is not worth a summary round-trip
+        (split > 1).then_some(split)
+    }
+
+    /// See [`validate_history`]
+    pub fn validate(&self) -> Result<()> {
+        validate_history(&self.messages)
+    }
+
+    /// A malformed history is still saved, losing the conversation would be worse, but it is reported
+    pub async fn save_to_disk(&mut self) -> Result<()> {
+        if let Err(e) = self.validate() {
+            eprintln!(
+                "{}",
+                format!("Warning: session '{}' is malformed: {}", self.name, e).yellow()
+            );
+        }
+        self.updated_at = Utc::now();
+        self.stamp_messages(self.updated_at);
         let session_data = serde_json::to_string_pretty(self)?;
-        let file_name = format!("{}.json", self.name);
-        let path = self.path.join(file_name);
-        fs::write(path, session_data).await?;
+        write_atomic(&session_file_path(&self.name)?, session_data).await?;
         Ok(())
     }
 }
 
+/// Check the tool call pairing servers insist on: every TOOL message carries a `tool_call_id` and a `name`
+/// and answers a call of the assistant message right before it, and every call of an assistant message
+/// gets its result before the conversation moves on. Errors name the offending message by index.
+pub fn validate_history(messages: &[Message]) -> Result<()> {
+    // Calls of the latest assistant message still waiting for their result
+    let mut pending: Vec<&str> = Vec::new();
+
+    for (i, message) in messages.iter().enumerate() {
+        match message.role {
+            TOOL => {
+                let Some(ref id) = message.tool_call_id else {
+                    anyhow::bail!("message {} is a tool result without a tool_call_id", i);
+                };
+                if message.name.is_none() {
+                    anyhow::bail!("message {} (tool result {}) has no tool name", i, id);
+                }
+                let Some(position) = pending.iter().position(|p| p == id) else {
+                    anyhow::bail!(
+                        "message {} answers tool call {}, which no assistant message right before it made",
+                        i,
+                        id
+                    );
+                };
+                pending.remove(position);
+            }
+            _ => {
+                if let Some(id) = pending.first() {
+                    anyhow::bail!("tool call {} has no result before message {}", id, i);
+                }
+                if let (ASSISTANT, Some(calls)) = (&message.role, &message.tool_calls) {
+                    pending = calls.iter().map(|call| call.id.as_str()).collect();
+                }
+            }
+        }
+    }
+
+    if let Some(id) = pending.first() {
+        anyhow::bail!("tool call {} has no result", id);
+    }
+    Ok(())
+}
+
+/// `times` are shown next to the headings, missing ones are left out
+fn render_messages(messages: &[Message], times: &[Option<DateTime<Utc>>]) -> String {
+    let mut md = String::new();
+
+    for (i, message) in messages.iter().enumerate() {
+        let heading = match message.role {
+            USER => "## User".to_string(),
+            ASSISTANT => "## Assistant".to_string(),
+            TOOL => format!("### Tool: {}", message.name.as_deref().unwrap_or("unknown")),
+            _ => continue,
+        };
+        match times.get(i).copied().flatten() {
+            Some(time) => md.push_str(&format!(
+                "\n{} _({})_\n\n",
+                heading,
+                time.format("%Y-%m-%d %H:%M:%S UTC")
+            )),
+            None => md.push_str(&format!("\n{}\n\n", heading)),
+        }
+
+        if let Some(ref content) = message.content {
+            md.push_str(content.trim_end());
+            md.push('\n');
+        }
+
+        for part in message.multi_content.iter().flatten() {
+            if let Some(ref text) = part.text {
+                md.push_str(text.trim_end());
+                md.push('\n');
+            }
+            if let Some(ref image) = part.image_url {
+                // Inline data URLs are far too long to be useful in a transcript
+                if image.url.starts_with("data:") {
+                    md.push_str("\n_[embedded image]_\n");
+                } else {
+                    md.push_str(&format!("\n![image]({})\n", image.url));
+                }
+            }
+        }
+
+        if let Some(ref tool_calls) = message.tool_calls {
+            let calls = serde_json::to_string_pretty(tool_calls).unwrap_or_default();
+            md.push_str(&format!("\n```json\n{}\n```\n", calls));
+        }
+    }
+
+    md
+}
+
 pub async fn load_session(session_name: &str) -> Result<Session> {
     let session_path = get_default_session_path()?;
     let file_name = format!("{}.json", session_name);
     let full_path = session_path.join(file_name);
     let session_data = tokio::fs::read_to_string(&full_path).await?;
-    let session: Session = serde_json::from_str(&session_data)?;
+    let mut session: Session = serde_json::from_str(&session_data)?;
+    session.path = session_path;
+    // Messages from before times were kept have none, they must not be stamped as new on the next save
+    session.message_times.resize(session.messages.len(), None);
     Ok(session)
 }
 
+/// Stats of a listed session file, an error means the file doesn't parse as a session
+pub async fn read_session_stats(path: &Path) -> Result<SessionStats> {
+    let session_data = fs::read_to_string(path).await?;
+    let session: Session = serde_json::from_str(&session_data)?;
+    Ok(session.stats())
+}
+
+pub async fn delete_session(session_name: &str) -> Result<()> {
+    let full_path = session_file_path(session_name)?;
+    if !full_path.exists() {
+        anyhow::bail!("Session '{}' does not exist", session_name);
+    }
+    fs::remove_file(&full_path).await?;
+    Ok(())
+}
+
+/// Rename a session, both the file and the `name` stored inside it (`save_to_disk` derives the filename from it)
+pub async fn rename_session(old_name: &str, new_name: &str) -> Result<()> {
+    if new_name.trim().is_empty() || new_name.contains(['/', '\\']) {
+        anyhow::bail!("Invalid session name: '{}'", new_name);
+    }
+
+    let old_path = session_file_path(old_name)?;
+    if !old_path.exists() {
+        anyhow::bail!("Session '{}' does not exist", old_name);
+    }
+    if session_file_path(new_name)?.exists() {
+        anyhow::bail!("Session '{}' already exists", new_name);
+    }
+
+    let mut session = load_session(old_name).await?;
+    session.name = new_name.to_string();
+    session.save_to_disk().await?;
+    fs::remove_file(&old_path).await?;
+    Ok(())
+}
+
+fn session_file_path(session_name: &str) -> Result<PathBuf> {
+    Ok(get_default_session_path()?.join(format!("{}.json", session_name)))
+}
+
+pub async fn list_sessions() -> Result<Vec<StoredEntry>> {
+    list_stored(&get_default_session_path()?, "json").await
+}
+
 pub fn get_default_session_path() -> Result<PathBuf> {
-    let home_dir =
-        dirs::home_dir().ok_or_else(|| anyhow::anyhow!("Could not find home directory"))?;
-    let session_path = home_dir.join(".config").join("r_agent").join("sessions");
+    let session_path = get_base_path()?.join("sessions");
     Ok(session_path)
 }
 
@@ -58,3 +446,357 @@ pub async fn create_session_dir() -> Result<PathBuf> {
 
     Ok(session_path)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::config::tests::test_home;
+    use crate::core::data::tests::temp_dir;
+    use forge::api::dtos::{FunctionCall, ToolCall};
+
+    /// A session with one exchange, saved under the shared test home
+    async fn saved_session(name: &str) -> Session {
+        test_home();
+        let mut session = Session::new(name, "qwen3-8b", PathBuf::new());
+        session.update_messages(
+            vec![
+                Message::user_text("What is 2 + 2?"),
+                Message::assistant_text("4"),
+            ],
+            Utc::now(),
+        );
+        session.save_to_disk().await.unwrap();
+        session
+    }
+
+    #[test]
+    fn markdown_shows_turns_tool_calls_and_times() {
+        let time = |s: &str| DateTime::parse_from_rfc3339(s).unwrap().to_utc();
+        let call = ToolCall {
+            id: "call_1".to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "read_file".to_string(),
+                arguments: r#"{"path":"a.txt"}"#.to_string(),
+            },
+        };
+        let mut session = Session::new("markdown", "qwen3-8b", PathBuf::new());
+        session.created_at = time("2026-01-02T03:04:05Z");
+        session.updated_at = time("2026-01-02T03:05:00Z");
+        session.messages = vec![
+            Message::system_text("You are a test agent."),
+            Message::user_text("What is in a.txt?"),
+            Message::assistant_tool_calls(vec![call]),
+            Message::tool_result("call_1", "read_file", "hello\n"),
+            Message::assistant_text("It says hello."),
+        ];
+        session.message_times = vec![
+            None,
+            Some(time("2026-01-02T03:04:10Z")),
+            None,
+            None,
+            Some(time("2026-01-02T03:04:20Z")),
+        ];
+
+        let expected = r#"# Session: markdown
+
+_Last model used: qwen3-8b - created 2026-01-02 03:04 UTC, updated 2026-01-02 03:05 UTC_
+
+## User _(2026-01-02 03:04:10 UTC)_
+
+What is in a.txt?
+
+## Assistant
+
+
+```json
+[
+  {
+    "id": "call_1",
+    "type": "function",
+    "function": {
+      "name": "read_file",
+      "arguments": "{\"path\":\"a.txt\"}"
+    }
+  }
+]
+```
+
+### Tool: read_file
+
+hello
+
+## Assistant _(2026-01-02 03:04:20 UTC)_
+
+It says hello.
+"#;
+        assert_eq!(session.to_markdown(), expected);
+    }
+
+    /// `turns` exchanges, each a question, a tool call, its result and the answer
+    fn long_session(turns: usize) -> Session {
+        let mut session = Session::new("long", "qwen3-8b", PathBuf::new());
+        for i in 0..turns {
+            let id = format!("call_{}", i);
+            session.messages.extend([
+                Message::user_text(&format!("question {}", i)),
+                Message::assistant_tool_calls(vec![call(&id)]),
+                Message::tool_result(&id, "time", "noon"),
+                Message::assistant_text(&format!("answer {}", i)),
+            ]);
+        }
+        session.stamp_messages(Utc::now());
+        session
+    }
+
+    #[test]
+    fn compaction_keeps_recent_turns_whole() {
+        let policy = CompactionPolicy::default();
+        assert!(!long_session(10).needs_compaction(&policy));
+        let mut session = long_session(15);
+        assert!(session.needs_compaction(&policy));
+
+        // Keeping 10 messages would cut into a turn, the split moves back to its question
+        let split = session.compaction_split(policy.keep_recent).unwrap();
+        assert_eq!(split, 48);
+        assert_eq!(
+            session.messages[split].content.as_deref(),
+            Some("question 12")
+        );
+
+        session.replace_with_summary(split, "  Twelve questions about the time.\n");
+        assert_eq!(session.messages.len(), 13);
+        assert_eq!(session.message_times.len(), 13);
+        assert_eq!(
+            session.messages[0].content.as_deref(),
+            Some("[Summary of the earlier conversation]\nTwelve questions about the time.")
+        );
+        assert_eq!(session.messages[1].content.as_deref(), Some("question 12"));
+        assert_eq!(session.messages[12].content.as_deref(), Some("answer 14"));
+        session.validate().unwrap();
+        assert!(!session.needs_compaction(&policy));
+    }
+
+    #[test]
+    fn short_sessions_have_nothing_to_compact() {
+        assert_eq!(long_session(1).compaction_split(10), None);
+        assert_eq!(long_session(3).compaction_split(10), None);
+        assert_eq!(long_session(4).compaction_split(10), Some(4));
+    }
+
+    fn call(id: &str) -> ToolCall {
+        ToolCall {
+            id: id.to_string(),
+            r#type: "function".to_string(),
+            function: FunctionCall {
+                name: "time".to_string(),
+                arguments: "{}".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn paired_tool_calls_are_valid() {
+        validate_history(&long_session(3).messages).unwrap();
+        validate_history(&[
+            Message::user_text("two at once"),
+            Message::assistant_tool_calls(vec![call("a"), call("b")]),
+            Message::tool_result("b", "time", "noon"),
+            Message::tool_result("a", "time", "noon"),
+            Message::assistant_text("done"),
+        ])
+        .unwrap();
+    }
+
+    #[test]
+    fn orphaned_tool_messages_are_reported() {
+        let message = |history: &[Message]| validate_history(history).unwrap_err().to_string();
+
+        let orphan = message(&[
+            Message::user_text("hi"),
+            Message::tool_result("a", "time", "noon"),
+        ]);
+        assert!(
+            orphan.starts_with("message 1 answers tool call a"),
+            "{}",
+            orphan
+        );
+
+        let unanswered = message(&[
+            Message::user_text("hi"),
+            Message::assistant_tool_calls(vec![call("a"), call("b")]),
+            Message::tool_result("a", "time", "noon"),
+            Message::user_text("never mind"),
+        ]);
+        assert_eq!(unanswered, "tool call b has no result before message 3");
+
+        let trailing = message(&[
+            Message::user_text("hi"),
+            Message::assistant_tool_calls(vec![call("a")]),
+        ]);
+        assert_eq!(trailing, "tool call a has no result");
+
+        let mut nameless = Message::tool_result("a", "time", "noon");
+        nameless.name = None;
+        let nameless = message(&[Message::assistant_tool_calls(vec![call("a")]), nameless]);
+        assert_eq!(nameless, "message 1 (tool result a) has no tool name");
+    }
+
+    #[tokio::test]
+    async fn saving_advances_updated_at_only() {
+        let mut session = saved_session("session-timestamps").await;
+        let created = session.created_at;
+        let first = session.updated_at;
+
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+        session.save_to_disk().await.unwrap();
+        let reloaded = load_session("session-timestamps").await.unwrap();
+        assert_eq!(reloaded.created_at, created);
+        assert!(reloaded.updated_at > first);
+    }
+
+    #[test]
+    fn sessions_saved_without_timestamps_still_load() {
+        let old = r#"{"name": "old", "last_model_used": "qwen3-8b", "messages": []}"#;
+        let session: Session = serde_json::from_str(old).unwrap();
+        assert_eq!(session.created_at, DateTime::<Utc>::UNIX_EPOCH);
+        assert_eq!(session.updated_at, DateTime::<Utc>::UNIX_EPOCH);
+    }
+
+    #[tokio::test]
+    async fn resumed_session_notices_a_changed_temperature() {
+        let settings = AgentSettings {
+            temperature: 0.5,
+            top_p: 0.9,
+            max_tokens: None,
+            system_prompt: "You are a test agent.".to_string(),
+        };
+        let mut session = saved_session("session-settings").await;
+        session.settings = Some(settings.clone());
+        session.save_to_disk().await.unwrap();
+
+        let stored = load_session("session-settings")
+            .await
+            .unwrap()
+            .settings
+            .unwrap();
+        assert!(stored.differences(&settings).is_empty());
+        let current = AgentSettings {
+            temperature: 0.9,
+            max_tokens: Some(512),
+            ..settings
+        };
+        assert_eq!(
+            stored.differences(&current),
+            ["temperature 0.5 -> 0.9", "max_tokens unset -> 512"]
+        );
+
+        let old = r#"{"name": "old", "last_model_used": "qwen3-8b", "messages": []}"#;
+        let old: Session = serde_json::from_str(old).unwrap();
+        assert!(old.settings.is_none());
+    }
+
+    #[tokio::test]
+    async fn stats_of_good_and_corrupt_session_files() {
+        let dir = temp_dir("session-stats");
+        let mut session = long_session(2);
+        let start = Utc::now();
+        session.message_times = (0..8)
+            .map(|i| Some(start + chrono::Duration::seconds(i)))
+            .collect();
+        std::fs::write(
+            dir.join("good.json"),
+            serde_json::to_string(&session).unwrap(),
+        )
+        .unwrap();
+        std::fs::write(dir.join("corrupt.json"), r#"{"name": "corrupt", "#).unwrap();
+
+        let entries = list_stored(&dir, "json").await.unwrap();
+        assert_eq!(entries.len(), 2);
+        let corrupt = read_session_stats(&entries[0].path).await;
+        assert!(corrupt.is_err());
+
+        let stats = read_session_stats(&entries[1].path).await.unwrap();
+        assert_eq!(stats.messages, 8);
+        assert_eq!(stats.tool_calls, 2);
+        assert!(stats.estimated_tokens > 0);
+        assert_eq!(stats.last_model_used, "qwen3-8b");
+        // Each turn runs from its question to its answer, three messages later
+        assert_eq!(stats.average_response, Some(chrono::Duration::seconds(3)));
+    }
+
+    #[tokio::test]
+    async fn message_times_are_saved_but_never_sent() {
+        let mut session = saved_session("session-message-times").await;
+        let sent_at = session.message_times[0].unwrap();
+        assert!(session.message_times[1].unwrap() >= sent_at);
+
+        let mut messages = session.messages.clone();
+        messages.push(Message::user_text("And 3 + 3?"));
+        let later = sent_at + chrono::Duration::seconds(30);
+        session.update_messages(messages, later);
+        session.save_to_disk().await.unwrap();
+        let reloaded = load_session("session-message-times").await.unwrap();
+        assert_eq!(reloaded.message_times.len(), 3);
+        assert_eq!(reloaded.message_times[0], Some(sent_at));
+        assert_eq!(reloaded.message_times[2], Some(later));
+
+        let request = serde_json::to_string(&reloaded.context_messages()).unwrap();
+        assert!(!request.contains("time"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn messages_saved_without_times_are_not_stamped_later() {
+        test_home();
+        let old = r#"{"name": "session-untimed", "last_model_used": "qwen3-8b",
+            "messages": [{"role": "user", "content": "hi"}, {"role": "assistant", "content": "hello"}]}"#;
+        std::fs::write(
+            get_default_session_path()
+                .unwrap()
+                .join("session-untimed.json"),
+            old,
+        )
+        .unwrap();
+
+        let mut session = load_session("session-untimed").await.unwrap();
+        assert_eq!(session.message_times, [None, None]);
+        session.save_to_disk().await.unwrap();
+        let reloaded = load_session("session-untimed").await.unwrap();
+        assert_eq!(reloaded.message_times, [None, None]);
+    }
+
+    #[tokio::test]
+    async fn deleting_a_missing_session_fails() {
+        test_home();
+        let err = delete_session("session-never-saved").await.unwrap_err();
+        assert!(err.to_string().contains("does not exist"), "{}", err);
+
+        saved_session("session-delete").await;
+        delete_session("session-delete").await.unwrap();
+        assert!(load_session("session-delete").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rename_moves_the_file_and_refuses_collisions() {
+        saved_session("session-rename-old").await;
+        saved_session("session-rename-taken").await;
+
+        let err = rename_session("session-rename-old", "session-rename-taken")
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("already exists"), "{}", err);
+        assert!(
+            rename_session("session-rename-old", "../escape")
+                .await
+                .is_err()
+        );
+
+        rename_session("session-rename-old", "session-rename-new")
+            .await
+            .unwrap();
+        assert!(load_session("session-rename-old").await.is_err());
+        let renamed = load_session("session-rename-new").await.unwrap();
+        assert_eq!(renamed.name, "session-rename-new");
+        assert_eq!(renamed.messages.len(), 2);
+    }
+}
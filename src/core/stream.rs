@@ -0,0 +1,228 @@
+use crate::core::events::AgentEvent;
+use crate::core::retry::{RetryPolicy, is_transient};
+use anyhow::{Result, anyhow};
+use colored::Colorize;
+use futures_util::{Stream, StreamExt};
+use std::future::Future;
+use std::time::Duration;
+use tracing::warn;
+
+/// Wraps a chunk stream so a stalled server surfaces as an error instead of hanging forever.
+/// The limit applies between two chunks, so long generations are fine as long as tokens keep coming.
+pub fn with_idle_timeout<S>(
+    stream: S,
+    idle: Duration,
+    url: String,
+) -> impl Stream<Item = Result<String>>
+where
+    S: Stream<Item = Result<String>> + Unpin,
+{
+    futures_util::stream::unfold(Some(stream), move |state| {
+        let url = url.clone();
+        async move {
+            let mut stream = state?;
+            match tokio::time::timeout(idle, stream.next()).await {
+                Ok(Some(chunk)) => Some((chunk, Some(stream))),
+                Ok(None) => None,
+                Err(_) => Some((
+                    Err(anyhow!(
+                        "No data received from {} for {}s, giving up",
+                        url,
+                        idle.as_secs()
+                    )),
+                    None,
+                )),
+            }
+        }
+    })
+}
+
+/// Reconnection state of [`with_reconnect`]
+struct Reconnect<S, F, R> {
+    stream: Option<S>,
+    connect: F,
+    resumable: R,
+    /// Text the current connection has produced, compared against a replay after reconnecting
+    emitted: String,
+    /// Bytes of `emitted` a replaying connection has reproduced so far, `None` when not replaying
+    replayed: Option<usize>,
+    /// First chunk of a regenerated answer, sent right after [`AgentEvent::Restarted`]
+    pending: Option<String>,
+    attempts: u32,
+}
+
+/// Restart a stream that breaks mid-answer with a transient error, up to `policy.max_retries` times.
+/// Servers can't resume, so the request starts over: text repeating what was already emitted is dropped,
+/// and an answer that comes out differently is emitted whole after [`AgentEvent::Restarted`].
+/// Starting over runs the turn's tools again, so once `resumable` returns false the error is returned instead.
+pub fn with_reconnect<S, F, Fut, R>(
+    stream: S,
+    connect: F,
+    resumable: R,
+    policy: RetryPolicy,
+) -> impl Stream<Item = Result<AgentEvent>>
+where
+    S: Stream<Item = Result<String>> + Unpin,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<S>>,
+    R: Fn() -> bool,
+{
+    let state = Reconnect {
+        stream: Some(stream),
+        connect,
+        resumable,
+        emitted: String::new(),
+        replayed: None,
+        pending: None,
+        attempts: 0,
+    };
+
+    futures_util::stream::unfold(Some(state), move |state| async move {
+        let mut state = state?;
+        if let Some(chunk) = state.pending.take() {
+            return Some((Ok(AgentEvent::Text(chunk)), Some(state)));
+        }
+        loop {
+            let stream = match state.stream {
+                Some(ref mut stream) => stream,
+                None => match (state.connect)().await {
+                    Ok(stream) => state.stream.insert(stream),
+                    Err(e) => return Some((Err(e), None)),
+                },
+            };
+
+            let chunk = match stream.next().await {
+                Some(Ok(chunk)) => chunk,
+                Some(Err(e)) if is_transient(&e) && !(state.resumable)() => {
+                    let e =
+                        e.context("Connection lost after tools ran, not starting the turn over");
+                    return Some((Err(e), None));
+                }
+                Some(Err(e)) if state.attempts < policy.max_retries && is_transient(&e) => {
+                    let delay = policy.delay(&e, state.attempts);
+                    state.attempts += 1;
+                    warn!(error = %e, attempt = state.attempts, "stream dropped, reconnecting");
+                    eprintln!(
+                        "{}",
+                        format!(
+                            "\nConnection lost mid-answer ({}), reconnecting in {}ms [{}/{}]",
+                            e,
+                            delay.as_millis(),
+                            state.attempts,
+                            policy.max_retries
+                        )
+                        .yellow()
+                    );
+                    tokio::time::sleep(delay).await;
+                    state.stream = None;
+                    state.replayed = (!state.emitted.is_empty()).then_some(0);
+                    continue;
+                }
+                Some(Err(e)) => return Some((Err(e), None)),
+                None => return None,
+            };
+
+            let Some(replayed) = state.replayed else {
+                state.emitted.push_str(&chunk);
+                return Some((Ok(AgentEvent::Text(chunk)), Some(state)));
+            };
+
+            let pending = &state.emitted[replayed..];
+            if pending.starts_with(chunk.as_str()) {
+                // Still repeating what was already printed
+                let replayed = replayed + chunk.len();
+                state.replayed = (replayed < state.emitted.len()).then_some(replayed);
+            } else if let Some(new) = chunk.strip_prefix(pending) {
+                // Caught up, only the part past the old text is new
+                state.replayed = None;
+                state.emitted.push_str(new);
+                if !new.is_empty() {
+                    let new = new.to_string();
+                    return Some((Ok(AgentEvent::Text(new)), Some(state)));
+                }
+            } else {
+                state.replayed = None;
+                state.emitted = chunk.clone();
+                state.pending = Some(chunk);
+                return Some((Ok(AgentEvent::Restarted), Some(state)));
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures_util::stream::{self, Iter};
+    use std::cell::Cell;
+    use std::vec::IntoIter;
+
+    type Chunks = Iter<IntoIter<Result<String>>>;
+
+    fn chunks(texts: &[&str]) -> Vec<Result<String>> {
+        texts.iter().map(|t| Ok(t.to_string())).collect()
+    }
+
+    /// A refused connection, which [`is_transient`] counts as worth reconnecting
+    async fn connection_lost() -> anyhow::Error {
+        reqwest::Client::new()
+            .get("http://127.0.0.1:9/")
+            .send()
+            .await
+            .unwrap_err()
+            .into()
+    }
+
+    fn no_delay() -> RetryPolicy {
+        RetryPolicy {
+            max_retries: 2,
+            base_delay: Duration::ZERO,
+            max_delay: Duration::ZERO,
+        }
+    }
+
+    /// The first connection breaks after `first`, the reconnection answers `second`
+    async fn run(first: &[&str], second: &[&str], resumable: bool) -> (Vec<String>, usize) {
+        let mut broken = chunks(first);
+        broken.push(Err(connection_lost().await));
+        let connects = Cell::new(0);
+        let connect = || {
+            connects.set(connects.get() + 1);
+            let replay: Chunks = stream::iter(chunks(second));
+            async move { Ok(replay) }
+        };
+
+        let events: Vec<String> =
+            with_reconnect(stream::iter(broken), connect, || resumable, no_delay())
+                .map(|event| match event {
+                    Ok(AgentEvent::Text(text)) => text,
+                    Ok(AgentEvent::Restarted) => "<restarted>".to_string(),
+                    Ok(other) => panic!("unexpected event {:?}", other),
+                    Err(e) => format!("<error: {:#}>", e),
+                })
+                .collect()
+                .await;
+        (events, connects.get())
+    }
+
+    #[tokio::test]
+    async fn replayed_text_is_not_emitted_twice() {
+        let (events, connects) = run(&["Hello ", "wor"], &["Hello ", "world", "!"], true).await;
+        assert_eq!(events, ["Hello ", "wor", "ld", "!"]);
+        assert_eq!(connects, 1);
+    }
+
+    #[tokio::test]
+    async fn a_different_answer_is_emitted_after_restarted() {
+        let (events, _) = run(&["Hello"], &["Good", "bye"], true).await;
+        assert_eq!(events, ["Hello", "<restarted>", "Good", "bye"]);
+    }
+
+    #[tokio::test]
+    async fn no_reconnect_once_tools_ran() {
+        let (events, connects) = run(&["Hello"], &["Hello"], false).await;
+        assert_eq!(events.len(), 2);
+        assert!(events[1].starts_with("<error: Connection lost after tools ran"));
+        assert_eq!(connects, 0);
+    }
+}
@@ -0,0 +1,108 @@
+//! A tiny HTTP server for tests, answering each connection with the next scripted response.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// A scripted response, optionally sent after a delay
+#[derive(Clone)]
+pub struct Reply {
+    raw: String,
+    delay: Duration,
+}
+
+impl Reply {
+    pub fn new(status: u16, body: &str) -> Self {
+        Reply::with_headers(status, &[], body)
+    }
+
+    pub fn with_headers(status: u16, headers: &[(&str, &str)], body: &str) -> Self {
+        let mut raw = format!(
+            "HTTP/1.1 {} Scripted\r\nContent-Length: {}\r\nConnection: close\r\n",
+            status,
+            body.len()
+        );
+        for (name, value) in headers {
+            raw.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        raw.push_str("\r\n");
+        raw.push_str(body);
+        Reply {
+            raw,
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Answer only after `delay`, to run into client timeouts
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+pub struct TestServer {
+    /// Base URL, without a trailing slash
+    pub url: String,
+    hits: Arc<AtomicUsize>,
+}
+
+impl TestServer {
+    /// Serve `replies` in order, repeating the last one once they run out
+    pub async fn start(replies: Vec<Reply>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let hits = Arc::new(AtomicUsize::new(0));
+
+        let counter = hits.clone();
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let n = counter.fetch_add(1, Ordering::SeqCst);
+                let reply = replies[n.min(replies.len() - 1)].clone();
+                tokio::spawn(async move {
+                    read_request(&mut socket).await;
+                    tokio::time::sleep(reply.delay).await;
+                    let _ = socket.write_all(reply.raw.as_bytes()).await;
+                    let _ = socket.shutdown().await;
+                });
+            }
+        });
+
+        TestServer { url, hits }
+    }
+
+    /// Number of connections accepted so far
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::SeqCst)
+    }
+}
+
+/// Read the head and, if it declares one, the body of a request
+async fn read_request(socket: &mut tokio::net::TcpStream) -> String {
+    let mut data = Vec::new();
+    let mut buf = [0u8; 4096];
+    while let Ok(n) = socket.read(&mut buf).await {
+        if n == 0 {
+            break;
+        }
+        data.extend_from_slice(&buf[..n]);
+
+        let text = String::from_utf8_lossy(&data);
+        let Some(head_end) = text.find("\r\n\r\n") else {
+            continue;
+        };
+        let length = text[..head_end]
+            .lines()
+            .find_map(|line| {
+                let (name, value) = line.split_once(':')?;
+                name.eq_ignore_ascii_case("content-length")
+                    .then(|| value.trim().parse::<usize>().ok())?
+            })
+            .unwrap_or(0);
+        if data.len() >= head_end + 4 + length {
+            break;
+        }
+    }
+    String::from_utf8_lossy(&data).into_owned()
+}
@@ -0,0 +1,108 @@
+use forge::api::dtos::Message;
+
+/// Rough cost of one image part, in line with what OpenAI-style servers charge for a detailed image
+pub const IMAGE_TOKENS: usize = 765;
+
+/// Per-message framing (role markers, separators) most chat templates add
+const MESSAGE_OVERHEAD: usize = 4;
+
+/// Approximate the token count of `text` without a tokenizer.
+/// ASCII averages about four characters per token, other scripts (CJK, emoji) are closer to one per char.
+pub fn estimate_text(text: &str) -> usize {
+    let ascii = text.chars().filter(|c| c.is_ascii()).count();
+    let other = text.chars().count() - ascii;
+    ascii.div_ceil(4) + other
+}
+
+/// Approximate the prompt tokens of a message history
+pub fn estimate_messages(messages: &[Message]) -> usize {
+    messages.iter().map(estimate_message).sum()
+}
+
+pub fn estimate_message(message: &Message) -> usize {
+    let mut tokens = MESSAGE_OVERHEAD;
+
+    if let Some(ref content) = message.content {
+        tokens += estimate_text(content);
+    }
+
+    for part in message.multi_content.iter().flatten() {
+        if let Some(ref text) = part.text {
+            tokens += estimate_text(text);
+        }
+        if part.image_url.is_some() {
+            tokens += IMAGE_TOKENS;
+        }
+    }
+
+    if let Some(ref tool_calls) = message.tool_calls {
+        tokens += estimate_text(&serde_json::to_string(tool_calls).unwrap_or_default());
+    }
+
+    tokens
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::messages::MessageExt;
+    use forge::api::dtos::ImageUrl;
+
+    #[test]
+    fn ascii_is_about_four_chars_per_token() {
+        assert_eq!(estimate_text(""), 0);
+        assert_eq!(estimate_text("abcd"), 1);
+        assert_eq!(estimate_text("abcde"), 2);
+        assert_eq!(estimate_text(&"x".repeat(400)), 100);
+    }
+
+    #[test]
+    fn other_scripts_are_one_token_per_char() {
+        assert_eq!(estimate_text("日本語"), 3);
+        assert_eq!(estimate_text("🦀🦀"), 2);
+        assert_eq!(estimate_text("abcd日本"), 3);
+    }
+
+    #[test]
+    fn estimates_stay_close_to_real_token_counts() {
+        // (text, tokens counted with the cl100k tokenizer)
+        let samples = [
+            ("Hello, world!", 4),
+            ("The quick brown fox jumps over the lazy dog.", 10),
+            (
+                "List the files in the src directory and summarize each one.",
+                12,
+            ),
+        ];
+        for (text, tokens) in samples {
+            let estimate = estimate_text(text);
+            assert!(
+                estimate.abs_diff(tokens) * 4 <= tokens,
+                "{:?}: estimated {}, real {}",
+                text,
+                estimate,
+                tokens
+            );
+        }
+    }
+
+    #[test]
+    fn messages_add_framing_and_images() {
+        let text = Message::user_text("abcd");
+        assert_eq!(estimate_message(&text), MESSAGE_OVERHEAD + 1);
+
+        let image = ImageUrl {
+            url: "https://example.com/a.png".to_string(),
+        };
+        let with_image = Message::user_with_images("abcd", vec![image]);
+        assert_eq!(
+            estimate_message(&with_image),
+            MESSAGE_OVERHEAD + 1 + IMAGE_TOKENS
+        );
+
+        assert_eq!(
+            estimate_messages(&[text, with_image]),
+            2 * MESSAGE_OVERHEAD + 2 + IMAGE_TOKENS
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use anyhow::{Result, anyhow};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+/// A Rust type a tool argument can have, with the JSON schema the model is shown for it
+pub trait ArgType {
+    fn schema() -> Value;
+    /// Whether the model has to pass the argument, only `Option`s are optional
+    const REQUIRED: bool = true;
+}
+
+impl ArgType for String {
+    fn schema() -> Value {
+        serde_json::json!({ "type": "string" })
+    }
+}
+
+impl ArgType for bool {
+    fn schema() -> Value {
+        serde_json::json!({ "type": "boolean" })
+    }
+}
+
+macro_rules! integer_arg {
+    ($($ty:ty),*) => {
+        $(impl ArgType for $ty {
+            fn schema() -> Value {
+                serde_json::json!({ "type": "integer" })
+            }
+        })*
+    };
+}
+
+integer_arg!(u32, u64, usize, i32, i64);
+
+impl ArgType for f64 {
+    fn schema() -> Value {
+        serde_json::json!({ "type": "number" })
+    }
+}
+
+impl<T: ArgType> ArgType for Vec<T> {
+    fn schema() -> Value {
+        serde_json::json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+impl<T: ArgType> ArgType for Option<T> {
+    fn schema() -> Value {
+        T::schema()
+    }
+
+    const REQUIRED: bool = false;
+}
+
+/// Arguments of a tool, declared once with [`tool_args!`]: the schema the model sees and
+/// what `execute_tool` reads come from the same struct and can't drift apart
+pub trait ToolArgs: DeserializeOwned {
+    /// The `parameters` object of the tool's function schema
+    fn parameters() -> Value;
+
+    fn parse(args: Value) -> Result<Self> {
+        serde_json::from_value(args).map_err(|e| anyhow!("Invalid arguments: {}", e))
+    }
+}
+
+/// The function schema a tool's `description()` returns
+pub fn function_schema(name: &str, description: &str, parameters: Value) -> Value {
+    serde_json::json!({
+        "type": "function",
+        "function": {
+            "name": name,
+            "description": description,
+            "parameters": parameters
+        }
+    })
+}
+
+/// Declare the arguments of a tool as a struct. Each field's doc comment becomes its description
+/// in the schema, `Option` fields are optional and every other field is required.
+macro_rules! tool_args {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $(
+                $(#[doc = $doc:literal])*
+                $field:ident: $ty:ty
+            ),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, serde::Deserialize)]
+        $vis struct $name {
+            $(
+                $(#[doc = $doc])*
+                pub $field: $ty,
+            )*
+        }
+
+        impl $crate::core::tool_args::ToolArgs for $name {
+            fn parameters() -> serde_json::Value {
+                // Never written to by argument-less tools
+                #[allow(unused_mut)]
+                let mut properties = serde_json::Map::new();
+                #[allow(unused_mut)]
+                let mut required: Vec<&str> = Vec::new();
+                $(
+                    let mut schema = <$ty as $crate::core::tool_args::ArgType>::schema();
+                    let docs: &[&str] = &[$($doc),*];
+                    let description = docs.iter().map(|line| line.trim()).collect::<Vec<_>>().join(" ");
+                    if !description.is_empty() {
+                        schema["description"] = serde_json::Value::String(description);
+                    }
+                    properties.insert(stringify!($field).to_string(), schema);
+                    if <$ty as $crate::core::tool_args::ArgType>::REQUIRED {
+                        required.push(stringify!($field));
+                    }
+                )*
+                serde_json::json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": required
+                })
+            }
+        }
+    };
+}
+
+pub(crate) use tool_args;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    tool_args! {
+        pub struct SearchArgs {
+            /// What to look for
+            pattern: String,
+            /// Where to look,
+            /// defaults to everywhere
+            paths: Option<Vec<String>>,
+            limit: u32,
+        }
+    }
+
+    #[test]
+    fn schema_follows_the_fields() {
+        assert_eq!(
+            SearchArgs::parameters(),
+            serde_json::json!({
+                "type": "object",
+                "properties": {
+                    "pattern": { "type": "string", "description": "What to look for" },
+                    "paths": {
+                        "type": "array",
+                        "items": { "type": "string" },
+                        "description": "Where to look, defaults to everywhere"
+                    },
+                    "limit": { "type": "integer" }
+                },
+                "required": ["pattern", "limit"]
+            })
+        );
+    }
+
+    #[test]
+    fn parse_reads_optional_fields_as_none() {
+        let args = SearchArgs::parse(serde_json::json!({ "pattern": "todo", "limit": 3 })).unwrap();
+        assert_eq!(args.pattern, "todo");
+        assert!(args.paths.is_none());
+        assert_eq!(args.limit, 3);
+        assert!(SearchArgs::parse(serde_json::json!({ "limit": 3 })).is_err());
+    }
+}
@@ -1,30 +1,490 @@
+use crate::core::events::{AgentEvent, EventSender};
+use crate::core::tool_args::{ToolArgs, function_schema, tool_args};
 use anyhow::{Result, anyhow};
-use colored::Colorize;
 use forge::api::tools_registry::{Tool, ToolRegistry};
 use serde_json::Value;
+use std::collections::HashMap;
 use std::env;
+use std::future::Future;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
 use std::process::Stdio;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use std::time::Instant;
 #[allow(unused)]
 use tokio::fs;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::process::Command;
+use tokio::sync::Semaphore;
+use tracing::{Instrument, debug, info_span};
 
 pub fn get_default_toolset() -> ToolRegistry {
     let mut registry = ToolRegistry::new();
-    registry.register(LsTool);
-    registry.register(ReadFileTool);
-    registry.register(TimeTool);
-    registry.register(RgTool);
-    registry.register(PwdTool);
-    registry.register(GitDiffTool);
-    registry.register(GitStatusTool);
-    registry.register(GitLogTool);
-    registry.register(PsTool);
-    registry.register(CargoCheckTool);
-    // registry.register(TreeTool);
-    registry.register(SafeCurlTool);
+    for tool in default_tools() {
+        registry.register(DynTool::new(tool));
+    }
+    registry
+}
+
+/// The default toolset without the tools in [`MUTATING_TOOLS`]
+pub fn get_readonly_toolset() -> ToolRegistry {
+    let mut registry = ToolRegistry::new();
+    for tool in readonly_tools() {
+        registry.register(DynTool::new(tool));
+    }
+    registry
+}
+
+/// Decides whether a tool call may run, given the tool name and the call's arguments
+pub type Approver = Arc<dyn Fn(&str, &Value) -> bool + Send + Sync>;
+
+/// Tools that end up running arbitrary code (build scripts, proc macros), a human has to approve each call
+pub const APPROVAL_REQUIRED: &[&str] = &["cargo_check_tool", "background_run_tool"];
+
+pub fn requires_approval(name: &str) -> bool {
+    APPROVAL_REQUIRED.contains(&name)
+}
+
+/// Tools that change the machine or run code, none of them is registered in read-only mode
+pub const MUTATING_TOOLS: &[&str] = &["cargo_check_tool", "background_run_tool"];
+
+/// Appended to the system prompt in read-only mode
+pub const READ_ONLY_NOTE: &str = "READ-ONLY MODE: you can only inspect the project. Editing files, running builds \
+and executing commands are unavailable, do not offer to do them.";
+
+/// How the runner wraps the default tools
+#[derive(Clone, Copy, Debug)]
+pub struct ToolOptions {
+    /// Upper bound of tools running at the same time, across the whole toolset
+    pub max_concurrent: usize,
+    /// Leave out the tools in [`MUTATING_TOOLS`]
+    pub read_only: bool,
+    /// Abort the turn once the model makes the same call (same tool, same arguments) this many times, 0 never aborts
+    pub max_repeats: usize,
+    /// Cut every tool result to this many chars before the model sees it, `None` leaves them whole
+    pub max_output: Option<usize>,
+}
+
+impl Default for ToolOptions {
+    fn default() -> Self {
+        ToolOptions {
+            max_concurrent: 4,
+            read_only: false,
+            max_repeats: 3,
+            max_output: None,
+        }
+    }
+}
+
+impl ToolOptions {
+    /// The tools these options register
+    pub fn tools(&self) -> Vec<Box<dyn Tool>> {
+        if self.read_only {
+            readonly_tools()
+        } else {
+            default_tools()
+        }
+    }
+}
+
+/// The default toolset, with every call and result reported to `events`, and `called` set on the first call.
+/// Calls to tools in [`APPROVAL_REQUIRED`] go through `approver` first when one is given.
+pub fn get_observed_toolset(
+    events: EventSender,
+    called: Arc<AtomicBool>,
+    options: &ToolOptions,
+    approver: Option<Approver>,
+) -> ToolRegistry {
+    let limit = Arc::new(Semaphore::new(options.max_concurrent.max(1)));
+    let repeats = RepeatGuard::new(options.max_repeats);
+    let mut registry = ToolRegistry::new();
+    for tool in options.tools() {
+        let mut tool = DynTool::new(tool)
+            .with_events(events.clone())
+            .with_called(called.clone())
+            .with_limit(limit.clone());
+        if options.max_repeats > 0 {
+            tool = tool.with_repeat_guard(repeats.clone());
+        }
+        if let Some(max_output) = options.max_output {
+            tool = tool.with_max_output(max_output);
+        }
+        if let Some(ref approver) = approver
+            && requires_approval(tool.name())
+        {
+            tool = tool.with_approver(approver.clone());
+        }
+        registry.register(tool);
+    }
     registry
 }
 
+/// The first `limit` chars of `output`, with a note saying how much was cut
+pub fn truncate_output(output: &str, limit: usize) -> String {
+    let total = output.chars().count();
+    if total <= limit {
+        return output.to_string();
+    }
+    let kept: String = output.chars().take(limit).collect();
+    format!("{}\n[truncated, {} of {} chars kept]", kept, limit, total)
+}
+
+/// Counts identical calls across a toolset, a model stuck calling the same thing over and over
+/// is stopped long before forge runs out of iterations
+#[derive(Clone)]
+pub struct RepeatGuard {
+    max_repeats: usize,
+    seen: Arc<Mutex<HashMap<(String, String), usize>>>,
+}
+
+impl RepeatGuard {
+    pub fn new(max_repeats: usize) -> Self {
+        RepeatGuard {
+            max_repeats,
+            seen: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Record a call, failing once the same tool got the same arguments more than `max_repeats` times
+    pub fn check(&self, name: &str, args: &Value) -> std::result::Result<(), String> {
+        let mut seen = self.seen.lock().unwrap_or_else(|e| e.into_inner());
+        let count = seen
+            .entry((name.to_string(), args.to_string()))
+            .or_insert(0);
+        *count += 1;
+        if *count > self.max_repeats {
+            return Err(format!(
+                "The model called {} with the same arguments {} times ({}), stopping the turn",
+                name, count, args
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// The tools registered by `get_default_toolset`, kept as trait objects so they can be listed
+pub fn default_tools() -> Vec<Box<dyn Tool>> {
+    vec![
+        Box::new(LsTool),
+        Box::new(ReadFileTool),
+        Box::new(ReadSymbolTool),
+        Box::new(TimeTool),
+        Box::new(RgTool),
+        Box::new(FindFilesTool::default()),
+        Box::new(PwdTool),
+        Box::new(EnvTool::default()),
+        Box::new(GitDiffTool),
+        Box::new(GitStatusTool),
+        Box::new(GitLogTool),
+        Box::new(PsTool),
+        #[cfg(feature = "cargo-check")]
+        Box::new(CargoCheckTool),
+        // Box::new(TreeTool),
+        Box::new(SafeCurlTool::default()),
+        Box::new(WebFetchTool::default()),
+        Box::new(BackgroundRunTool),
+        Box::new(ProcessStatusTool),
+    ]
+}
+
+/// Register `tool` with its `tool_callback` overridden. `false` makes forge hand the tool's result back
+/// as the answer instead of looping to the model again, without writing a new tool type.
+pub fn register_with_callback<T: Tool + 'static>(
+    registry: &mut ToolRegistry,
+    tool: T,
+    callback: bool,
+) {
+    registry.register(DynTool::new(Box::new(tool)).with_callback(callback));
+}
+
+/// Register an async closure as a tool, without writing a type for it. `description` is the whole function
+/// schema, as a tool's `description()` returns it.
+pub fn register_fn<F, Fut>(
+    registry: &mut ToolRegistry,
+    name: &str,
+    description: Value,
+    callback: bool,
+    run: F,
+) where
+    F: Fn(Value) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<String>> + Send + 'static,
+{
+    registry.register(FnTool::new(name, description, callback, run));
+}
+
+type ToolFuture = Pin<Box<dyn Future<Output = Result<String>> + Send>>;
+
+/// A tool backed by a closure, see [`register_fn`]
+pub struct FnTool {
+    name: String,
+    description: Value,
+    callback: bool,
+    run: Box<dyn Fn(Value) -> ToolFuture + Send + Sync>,
+}
+
+impl FnTool {
+    pub fn new<F, Fut>(name: &str, description: Value, callback: bool, run: F) -> Self
+    where
+        F: Fn(Value) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<String>> + Send + 'static,
+    {
+        FnTool {
+            name: name.to_string(),
+            description,
+            callback,
+            run: Box::new(move |args| Box::pin(run(args))),
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for FnTool {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn description(&self) -> Value {
+        self.description.clone()
+    }
+
+    fn tool_callback(&self) -> bool {
+        self.callback
+    }
+
+    async fn execute_tool(&self, args: Value) -> Result<String> {
+        (self.run)(args).await
+    }
+}
+
+/// The default tools that only inspect
+pub fn readonly_tools() -> Vec<Box<dyn Tool>> {
+    default_tools()
+        .into_iter()
+        .filter(|tool| !MUTATING_TOOLS.contains(&tool.name()))
+        .collect()
+}
+
+/// Short description of a tool, taken from its function schema
+pub fn tool_summary(tool: &dyn Tool) -> String {
+    tool.description()["function"]["description"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Registers a boxed tool, `ToolRegistry::register` wants a concrete `Tool`.
+/// Also the place where the runner hooks into tool execution.
+pub struct DynTool {
+    tool: Box<dyn Tool>,
+    events: Option<EventSender>,
+    called: Option<Arc<AtomicBool>>,
+    limit: Option<Arc<Semaphore>>,
+    callback: Option<bool>,
+    approver: Option<Approver>,
+    repeats: Option<RepeatGuard>,
+    max_output: Option<usize>,
+    /// Compiled from the tool's declared `parameters`, `None` when the tool declares none (or an invalid one)
+    validator: Option<jsonschema::Validator>,
+}
+
+impl DynTool {
+    pub fn new(tool: Box<dyn Tool>) -> Self {
+        let validator = tool
+            .description()
+            .get("function")
+            .and_then(|f| f.get("parameters"))
+            .and_then(|schema| jsonschema::validator_for(schema).ok());
+        DynTool {
+            tool,
+            events: None,
+            called: None,
+            limit: None,
+            callback: None,
+            approver: None,
+            repeats: None,
+            max_output: None,
+            validator,
+        }
+    }
+
+    /// Ask `approver` before every call, a denied call is answered with a "denied" result
+    pub fn with_approver(mut self, approver: Approver) -> Self {
+        self.approver = Some(approver);
+        self
+    }
+
+    /// Fail the call, and with it forge's tool loop, once `guard` sees it repeated too often
+    pub fn with_repeat_guard(mut self, guard: RepeatGuard) -> Self {
+        self.repeats = Some(guard);
+        self
+    }
+
+    /// Cut the result to `max_output` chars, whichever tool produced it
+    pub fn with_max_output(mut self, max_output: usize) -> Self {
+        self.max_output = Some(max_output);
+        self
+    }
+
+    /// Check the model's arguments against the declared schema. The error lists every problem and repeats
+    /// the schema, so the model can correct the call on its next try.
+    pub fn validate(&self, args: &Value) -> std::result::Result<(), String> {
+        let Some(ref validator) = self.validator else {
+            return Ok(());
+        };
+        let problems: Vec<String> = validator
+            .iter_errors(args)
+            .map(|e| match e.instance_path().to_string() {
+                path if path.is_empty() => format!("- {}", e),
+                path => format!("- {}: {}", path, e),
+            })
+            .collect();
+        if problems.is_empty() {
+            return Ok(());
+        }
+        Err(format!(
+            "Invalid arguments for {}:\n{}\nExpected parameters: {}",
+            self.name(),
+            problems.join("\n"),
+            self.description()["function"]["parameters"]
+        ))
+    }
+
+    /// Takes precedence over the wrapped tool's own `tool_callback`
+    pub fn with_callback(mut self, callback: bool) -> Self {
+        self.callback = Some(callback);
+        self
+    }
+
+    /// Share a semaphore between tools so a model asking for many calls at once can't flood the machine
+    pub fn with_limit(mut self, limit: Arc<Semaphore>) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    /// Report each call and its result as [`AgentEvent`]s
+    pub fn with_events(mut self, events: EventSender) -> Self {
+        self.events = Some(events);
+        self
+    }
+
+    /// Set `called` on every call, whether or not the call is then denied or fails
+    pub fn with_called(mut self, called: Arc<AtomicBool>) -> Self {
+        self.called = Some(called);
+        self
+    }
+
+    fn emit(&self, event: AgentEvent) {
+        if let Some(ref events) = self.events {
+            // Nobody listening anymore is fine, the tool result still goes to the model
+            let _ = events.send(event);
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for DynTool {
+    fn name(&self) -> &str {
+        self.tool.name()
+    }
+
+    fn description(&self) -> Value {
+        self.tool.description()
+    }
+
+    fn tool_callback(&self) -> bool {
+        self.callback.unwrap_or_else(|| self.tool.tool_callback())
+    }
+
+    async fn execute_tool(&self, args: Value) -> Result<String> {
+        let _permit = match self.limit {
+            Some(ref limit) => Some(limit.acquire().await?),
+            None => None,
+        };
+
+        if let Some(ref called) = self.called {
+            called.store(true, Ordering::SeqCst);
+        }
+        self.emit(AgentEvent::ToolCall {
+            name: self.name().to_string(),
+            args: args.clone(),
+        });
+
+        // The only error handed back to forge, it ends the turn instead of feeding the loop another result
+        if let Some(ref repeats) = self.repeats
+            && let Err(repeated) = repeats.check(self.name(), &args)
+        {
+            self.emit(AgentEvent::ToolResult {
+                name: self.name().to_string(),
+                output: repeated.clone(),
+            });
+            return Err(anyhow!(repeated));
+        }
+
+        if let Err(invalid) = self.validate(&args) {
+            debug!(tool = self.name(), %args, "rejected invalid arguments");
+            self.emit(AgentEvent::ToolResult {
+                name: self.name().to_string(),
+                output: invalid.clone(),
+            });
+            return Ok(invalid);
+        }
+
+        if let Some(ref approver) = self.approver {
+            let approver = approver.clone();
+            let (name, call_args) = (self.name().to_string(), args.clone());
+            // The CLI approver blocks on stdin
+            let approved = tokio::task::spawn_blocking(move || approver(&name, &call_args)).await?;
+            if !approved {
+                let denied = format!(
+                    "The user denied the call to {}, do not retry it. Continue without it or ask the user.",
+                    self.name()
+                );
+                self.emit(AgentEvent::ToolResult {
+                    name: self.name().to_string(),
+                    output: denied.clone(),
+                });
+                return Ok(denied);
+            }
+        }
+
+        let span = info_span!("tool", name = self.name());
+        let started = Instant::now();
+        debug!(parent: &span, %args, "tool started");
+        let result = self.tool.execute_tool(args).instrument(span.clone()).await;
+        debug!(
+            parent: &span,
+            elapsed_ms = started.elapsed().as_millis() as u64,
+            ok = result.is_ok(),
+            "tool finished"
+        );
+
+        // A failing tool is reported to the model like any other result, so one bad call
+        // (missing file, bad pattern) doesn't abort the whole run and the model can recover
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => format!("Error: {} failed: {:#}", self.name(), e),
+        };
+        let output = match self.max_output {
+            Some(limit) => truncate_output(&output, limit),
+            None => output,
+        };
+        self.emit(AgentEvent::ToolResult {
+            name: self.name().to_string(),
+            output: output.clone(),
+        });
+        Ok(output)
+    }
+}
+
+tool_args! {
+    pub struct LsArgs {
+        /// The directory path to list (optional, defaults to current directory)
+        path: Option<String>,
+    }
+}
+
 /// A tool to list files and directories in the current directory (cross-platform)
 pub struct LsTool;
 
@@ -35,23 +495,11 @@ impl Tool for LsTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Lists files and directories in the specified path (defaults to current directory). Returns a formatted list showing names and whether each entry is a file or directory.",
-                "parameters": {
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "The directory path to list (optional, defaults to current directory)"
-                        }
-                    },
-                    "required": []
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Lists files and directories in the specified path (defaults to current directory). Returns a formatted list showing names and whether each entry is a file or directory.",
+            LsArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -59,14 +507,11 @@ impl Tool for LsTool {
     }
 
     async fn execute_tool(&self, args: Value) -> Result<String> {
-        let path = args["path"]
-            .as_str()
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| {
-                env::current_dir()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .unwrap_or_else(|_| ".".to_string())
-            });
+        let path = LsArgs::parse(args)?.path.unwrap_or_else(|| {
+            env::current_dir()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|_| ".".to_string())
+        });
         #[cfg(target_os = "windows")]
         let mut cmd = {
             let mut c = Command::new("cmd");
@@ -74,23 +519,16 @@ impl Tool for LsTool {
             c
         };
         #[cfg(not(target_os = "windows"))]
-        let cmd = {
+        let mut cmd = {
             let mut c = Command::new("ls");
             c.arg("-l").arg(&path);
             c
         };
-        let output = cmd.stdout(Stdio::piped()).output().await;
+        let output = cmd.stdout(Stdio::piped()).kill_on_drop(true).output().await;
         match output {
             Ok(out) if out.status.success() => {
                 let result = String::from_utf8_lossy(&out.stdout).to_string();
-                println!(
-                    "{}",
-                    format!(
-                        "[DEBUG] LsTool executed\nListing path: {}\n[Returning] \n{}\n",
-                        path, result
-                    )
-                    .dimmed()
-                );
+                debug!(%path, output = %result, "LsTool executed");
                 Ok(result)
             }
             Err(e) => {
@@ -107,6 +545,13 @@ impl Tool for LsTool {
     }
 }
 
+tool_args! {
+    pub struct TreeArgs {
+        /// The directory path to display the tree from (optional, defaults to current directory)
+        path: Option<String>,
+    }
+}
+
 pub struct TreeTool;
 
 #[async_trait::async_trait]
@@ -116,23 +561,11 @@ impl Tool for TreeTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Displays a tree-like structure of files and directories starting from the specified path (defaults to current directory). Useful for visualizing the hierarchy of files and folders.",
-                "parameters": {
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "The directory path to display the tree from (optional, defaults to current directory)"
-                        }
-                    },
-                    "required": []
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Displays a tree-like structure of files and directories starting from the specified path (defaults to current directory). Useful for visualizing the hierarchy of files and folders.",
+            TreeArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -140,14 +573,11 @@ impl Tool for TreeTool {
     }
 
     async fn execute_tool(&self, args: Value) -> Result<String> {
-        let path = args["path"]
-            .as_str()
-            .map(|s| s.to_string())
-            .unwrap_or_else(|| {
-                env::current_dir()
-                    .map(|p| p.to_string_lossy().to_string())
-                    .unwrap_or_else(|_| ".".to_string())
-            });
+        let path = TreeArgs::parse(args)?.path.unwrap_or_else(|| {
+            env::current_dir()
+                .map(|p| p.to_string_lossy().to_string())
+                .unwrap_or_else(|_| ".".to_string())
+        });
         #[cfg(target_os = "windows")]
         let mut cmd = {
             let mut c = Command::new("powershell");
@@ -163,20 +593,14 @@ impl Tool for TreeTool {
         let output = cmd
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
+            .kill_on_drop(true)
             .output()
             .await;
 
         match output {
             Ok(out) if out.status.success() => {
                 let result = String::from_utf8_lossy(&out.stdout).to_string();
-                println!(
-                    "{}",
-                    format!(
-                        "[DEBUG] TreeTool executed\nDisplaying tree for path: {}\n[Returning] \n{}\n",
-                        path, result
-                    )
-                    .dimmed()
-                );
+                debug!(%path, output = %result, "TreeTool executed");
                 Ok(result)
             }
             Ok(out) => {
@@ -194,6 +618,13 @@ impl Tool for TreeTool {
     }
 }
 
+tool_args! {
+    pub struct ReadFileArgs {
+        /// Path to the file to read (relative or absolute)
+        path: String,
+    }
+}
+
 pub struct ReadFileTool;
 
 #[async_trait::async_trait]
@@ -203,23 +634,11 @@ impl Tool for ReadFileTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Reads and returns the complete contents of a text file. Use this to examine source code, configuration files, documentation, or any text-based file.",
-                "parameters": {
-                    "type": "object",
-                    "properties": {
-                        "path": {
-                            "type": "string",
-                            "description": "Path to the file to read (relative or absolute)"
-                        }
-                    },
-                    "required": ["path"]
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Reads and returns the complete contents of a text file. Use this to examine source code, configuration files, documentation, or any text-based file.",
+            ReadFileArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -227,9 +646,7 @@ impl Tool for ReadFileTool {
     }
 
     async fn execute_tool(&self, args: Value) -> Result<String> {
-        let path = args["path"]
-            .as_str()
-            .ok_or_else(|| anyhow!("missing 'path' parameter"))?;
+        let path = &ReadFileArgs::parse(args)?.path;
         #[cfg(target_os = "windows")]
         let mut cmd = {
             let mut c = Command::new("cmd");
@@ -237,23 +654,16 @@ impl Tool for ReadFileTool {
             c
         };
         #[cfg(not(target_os = "windows"))]
-        let cmd = {
+        let mut cmd = {
             let mut c = Command::new("cat");
-            c.arg(&path);
+            c.arg(path);
             c
         };
-        let output = cmd.stdout(Stdio::piped()).output().await;
+        let output = cmd.stdout(Stdio::piped()).kill_on_drop(true).output().await;
         match output {
             Ok(out) if out.status.success() => {
                 let result = String::from_utf8_lossy(&out.stdout).to_string();
-                println!(
-                    "{}",
-                    format!(
-                        "[DEBUG] ReadFileTool executed\nReading file at path: {}\n[Returning] \n{}\n",
-                        path, result
-                    )
-                        .dimmed()
-                );
+                debug!(%path, output = %result, "ReadFileTool executed");
                 Ok(result)
             }
 
@@ -270,6 +680,223 @@ impl Tool for ReadFileTool {
     }
 }
 
+/// Definitions longer than this are cut, the model can read the rest with read_file_tool
+const MAX_SYMBOL_LINES: usize = 400;
+
+/// Definitions of the same name returned at most (a struct and its impls, overloads)
+const MAX_SYMBOL_MATCHES: usize = 5;
+
+/// Words that introduce a definition, across the languages a project is likely to hold
+const DEFINITION_KEYWORDS: &[&str] = &[
+    "fn",
+    "struct",
+    "enum",
+    "trait",
+    "impl",
+    "mod",
+    "type",
+    "const",
+    "static",
+    "union",
+    "macro_rules",
+    "class",
+    "def",
+    "function",
+    "interface",
+    "func",
+];
+
+tool_args! {
+    pub struct ReadSymbolArgs {
+        /// Path to the source file (relative or absolute)
+        path: String,
+        /// Name of the definition, e.g. `run_session` or `Session`
+        symbol: String,
+    }
+}
+
+/// Reads the definition of a function, type or impl from a file, so the model doesn't have to read
+/// a whole large file for it. A lightweight scan, not a parser: braces end the definition, or the
+/// indentation for Python-style blocks.
+pub struct ReadSymbolTool;
+
+#[async_trait::async_trait]
+impl Tool for ReadSymbolTool {
+    fn name(&self) -> &str {
+        "read_symbol_tool"
+    }
+
+    fn description(&self) -> Value {
+        function_schema(
+            self.name(),
+            "Reads the definition of a function, struct, enum, trait, impl or class from a source file, with its doc comments and line numbers. Prefer it over reading a whole large file when only one definition matters.",
+            ReadSymbolArgs::parameters(),
+        )
+    }
+
+    fn tool_callback(&self) -> bool {
+        true
+    }
+
+    async fn execute_tool(&self, args: Value) -> Result<String> {
+        let ReadSymbolArgs {
+            ref path,
+            ref symbol,
+        } = ReadSymbolArgs::parse(args)?;
+
+        let source = fs::read_to_string(path).await?;
+        let lines: Vec<&str> = source.lines().collect();
+        let definitions = find_definitions(&lines, symbol);
+        debug!(%path, %symbol, found = definitions.len(), "ReadSymbolTool executed");
+        if definitions.is_empty() {
+            return Ok(format!(
+                "No definition of '{}' found in {}, try rg_tool to find where it is defined",
+                symbol, path
+            ));
+        }
+
+        let blocks: Vec<String> = definitions
+            .into_iter()
+            .take(MAX_SYMBOL_MATCHES)
+            .map(|(start, end)| {
+                let shown_end = end.min(start + MAX_SYMBOL_LINES - 1);
+                let mut block = format!("{}:{}-{}\n", path, start + 1, end + 1);
+                for (n, line) in lines[start..=shown_end].iter().enumerate() {
+                    block.push_str(&format!("{}: {}\n", start + n + 1, line));
+                }
+                if shown_end < end {
+                    block.push_str(&format!("[truncated, {} more lines]\n", end - shown_end));
+                }
+                block
+            })
+            .collect();
+        Ok(blocks.join("\n"))
+    }
+}
+
+/// Line ranges (0-based, inclusive) of the definitions of `symbol`, doc comments and attributes included
+fn find_definitions(lines: &[&str], symbol: &str) -> Vec<(usize, usize)> {
+    lines
+        .iter()
+        .enumerate()
+        .filter(|(_, line)| defines(line, symbol))
+        .map(|(i, _)| (definition_start(lines, i), definition_end(lines, i)))
+        .collect()
+}
+
+/// A definition keyword directly followed by `symbol`, or an `impl` naming it
+fn defines(line: &str, symbol: &str) -> bool {
+    let trimmed = line.trim_start();
+    if trimmed.starts_with("//") || trimmed.starts_with('*') || trimmed.starts_with("/*") {
+        return false;
+    }
+    let words: Vec<&str> = trimmed
+        .split(|c: char| !(c.is_alphanumeric() || c == '_'))
+        .filter(|w| !w.is_empty())
+        .collect();
+    words.iter().enumerate().any(|(i, word)| match *word {
+        // `impl<T> Trait for Symbol<T> {`, the words up to the body
+        "impl" if i == 0 || words[..i].iter().all(|w| ["pub", "unsafe"].contains(w)) => {
+            let head = trimmed.split('{').next().unwrap_or_default();
+            head.split(|c: char| !(c.is_alphanumeric() || c == '_'))
+                .any(|w| w == symbol)
+        }
+        keyword => DEFINITION_KEYWORDS.contains(&keyword) && words.get(i + 1) == Some(&symbol),
+    })
+}
+
+/// Walk up over the doc comments, attributes and decorators right above the definition
+fn definition_start(lines: &[&str], line: usize) -> usize {
+    let mut start = line;
+    while start > 0 {
+        let above = lines[start - 1].trim_start();
+        let attached = ["///", "//!", "#[", "@", "/**", "*", "*/"]
+            .iter()
+            .any(|prefix| above.starts_with(prefix));
+        if !attached {
+            break;
+        }
+        start -= 1;
+    }
+    start
+}
+
+/// The line closing the definition that starts at `line`: its matching `}`, the `;` of a
+/// declaration without a body, or the last line indented deeper for a block opened by `:`
+fn definition_end(lines: &[&str], line: usize) -> usize {
+    let indent = |l: &str| l.len() - l.trim_start().len();
+    let mut depth = 0usize;
+    let mut opened = false;
+
+    for (i, text) in lines.iter().enumerate().skip(line) {
+        for c in code_chars(text) {
+            match c {
+                '{' => {
+                    depth += 1;
+                    opened = true;
+                }
+                '}' => depth = depth.saturating_sub(1),
+                ';' if !opened => return i,
+                _ => {}
+            }
+        }
+        if opened && depth == 0 {
+            return i;
+        }
+        if !opened && text.trim_end().ends_with(':') {
+            let base = indent(lines[line]);
+            let body_end = lines[i + 1..]
+                .iter()
+                .position(|l| !l.trim().is_empty() && indent(l) <= base)
+                .map_or(lines.len(), |n| i + 1 + n);
+            // Blank lines before the next definition belong to neither
+            return (i..body_end)
+                .rev()
+                .find(|&n| !lines[n].trim().is_empty())
+                .unwrap_or(i);
+        }
+    }
+    lines.len() - 1
+}
+
+/// The chars of `line` outside string literals, char literals and `//` comments
+fn code_chars(line: &str) -> Vec<char> {
+    let mut code = Vec::new();
+    let mut chars = line.chars().peekable();
+    let mut in_string = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if in_string => {
+                chars.next();
+            }
+            '"' => in_string = !in_string,
+            _ if in_string => {}
+            '/' if chars.peek() == Some(&'/') => break,
+            // '{' and '}' as char literals, lifetimes like 'a pass through
+            '\'' if matches!(chars.peek(), Some('{') | Some('}')) => {
+                let mut ahead = chars.clone();
+                ahead.next();
+                if ahead.next() == Some('\'') {
+                    chars = ahead;
+                } else {
+                    code.push(c);
+                }
+            }
+            _ => code.push(c),
+        }
+    }
+    code
+}
+
+tool_args! {
+    pub struct RgArgs {
+        /// Text or regex to search for
+        pattern: String,
+        /// Optional path to search in
+        path: Option<String>,
+    }
+}
+
 pub struct RgTool;
 
 #[async_trait::async_trait]
@@ -279,27 +906,11 @@ impl Tool for RgTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Search text using ripgrep",
-                "parameters": {
-                    "type": "object",
-                    "properties": {
-                        "pattern": {
-                            "type": "string",
-                            "description": "Text or regex to search for"
-                        },
-                        "path": {
-                            "type": "string",
-                            "description": "Optional path to search in"
-                        }
-                    },
-                    "required": ["pattern"]
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Search text using ripgrep",
+            RgArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -307,28 +918,20 @@ impl Tool for RgTool {
     }
 
     async fn execute_tool(&self, args: Value) -> Result<String> {
-        let pattern = args["pattern"].as_str().unwrap();
-        let path = args["path"].as_str();
+        let RgArgs { pattern, path } = RgArgs::parse(args)?;
 
         let mut cmd = Command::new("rg");
-        cmd.arg(pattern);
+        cmd.arg(&pattern);
 
         if let Some(p) = path {
             cmd.arg(p);
         }
 
-        let output = cmd.output().await?;
+        let output = cmd.kill_on_drop(true).output().await?;
 
         if output.status.success() {
             let result = String::from_utf8_lossy(&output.stdout).to_string();
-            println!(
-                "{}",
-                format!(
-                    "[DEBUG] RgTool executed\nSearching for pattern: {}\n[Returning] \n{}\n",
-                    pattern, result
-                )
-                .dimmed()
-            );
+            debug!(%pattern, output = %result, "RgTool executed");
             Ok(result)
         } else {
             let err_msg = String::from_utf8_lossy(&output.stderr).to_string();
@@ -337,6 +940,132 @@ impl Tool for RgTool {
     }
 }
 
+/// Directories no search should descend into
+const IGNORED_DIRS: &[&str] = &[".git", "target", "node_modules"];
+
+tool_args! {
+    pub struct FindFilesArgs {
+        /// Glob pattern, relative to the search root
+        glob: String,
+        /// Directory to search from (optional, defaults to current directory)
+        path: Option<String>,
+    }
+}
+
+/// Finds files by glob, walking the tree natively instead of shelling out to `find`
+pub struct FindFilesTool {
+    pub max_results: usize,
+}
+
+impl Default for FindFilesTool {
+    fn default() -> Self {
+        FindFilesTool { max_results: 200 }
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for FindFilesTool {
+    fn name(&self) -> &str {
+        "find_files_tool"
+    }
+
+    fn description(&self) -> Value {
+        function_schema(
+            self.name(),
+            "Finds files whose path matches a glob pattern, e.g. '*.rs' or 'src/**/mod.rs'. A pattern without '/' is matched against file names at any depth. Skips .git, target and node_modules.",
+            FindFilesArgs::parameters(),
+        )
+    }
+
+    fn tool_callback(&self) -> bool {
+        true
+    }
+
+    async fn execute_tool(&self, args: Value) -> Result<String> {
+        let FindFilesArgs {
+            glob: pattern,
+            path,
+        } = FindFilesArgs::parse(args)?;
+        let root = PathBuf::from(path.as_deref().unwrap_or("."));
+        let max_results = self.max_results;
+
+        let glob = glob::Pattern::new(&pattern)
+            .map_err(|e| anyhow!("invalid glob '{}': {}", pattern, e))?;
+        let by_name = !pattern.contains('/');
+
+        let found = tokio::task::spawn_blocking(move || {
+            let mut found = Vec::new();
+            find_files(&root, &root, &glob, by_name, max_results + 1, &mut found);
+            found
+        })
+        .await?;
+
+        if found.is_empty() {
+            return Ok(format!("No files match '{}'", pattern));
+        }
+        let truncated = found.len() > max_results;
+        let mut result = found
+            .iter()
+            .take(max_results)
+            .map(|p| p.display().to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        if truncated {
+            result.push_str(&format!("\n[showing the first {} matches]", max_results));
+        }
+
+        debug!(%pattern, output = %result, "FindFilesTool executed");
+        Ok(result)
+    }
+}
+
+/// Depth-first walk collecting at most `limit` matches, unreadable directories are skipped
+fn find_files(
+    root: &Path,
+    dir: &Path,
+    glob: &glob::Pattern,
+    by_name: bool,
+    limit: usize,
+    found: &mut Vec<PathBuf>,
+) {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return;
+    };
+    let mut entries: Vec<_> = entries.flatten().map(|e| e.path()).collect();
+    entries.sort();
+
+    for path in entries {
+        if found.len() >= limit {
+            return;
+        }
+        let name = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or_default();
+        if path.is_dir() {
+            if !IGNORED_DIRS.contains(&name) {
+                find_files(root, &path, glob, by_name, limit, found);
+            }
+            continue;
+        }
+
+        let relative = path.strip_prefix(root).unwrap_or(&path);
+        let matched = if by_name {
+            glob.matches(name)
+        } else {
+            glob.matches_path(relative)
+        };
+        if matched {
+            found.push(relative.to_path_buf());
+        }
+    }
+}
+
+tool_args! {
+    /// The arguments of tools that take none
+    pub struct NoArgs {}
+}
+
 pub struct PwdTool;
 
 #[async_trait::async_trait]
@@ -346,18 +1075,11 @@ impl Tool for PwdTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Prints the current working directory",
-                "parameters": {
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Prints the current working directory",
+            NoArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -369,19 +1091,16 @@ impl Tool for PwdTool {
         let output = {
             let mut c = Command::new("powershell");
             c.arg("pwd");
-            c.output().await?
+            c.kill_on_drop(true).output().await?
         };
         #[cfg(not(target_os = "windows"))]
-        let cmd = {
+        let output = {
             let mut c = Command::new("pwd");
-            c.output().await?
+            c.kill_on_drop(true).output().await?
         };
         if output.status.success() {
             let result = String::from_utf8_lossy(&output.stdout).to_string();
-            println!(
-                "{}",
-                format!("[DEBUG] PwdTool executed\n[Returning] \n{}\n", result).dimmed()
-            );
+            debug!(output = %result, "PwdTool executed");
             Ok(result)
         } else {
             let err_msg = String::from_utf8_lossy(&output.stderr).to_string();
@@ -390,7 +1109,85 @@ impl Tool for PwdTool {
     }
 }
 
-pub struct GitDiffTool;
+tool_args! {
+    pub struct EnvArgs {
+        /// Name of the environment variable
+        name: String,
+    }
+}
+
+/// Names containing one of these are never read, even when the allowlist matches (`CARGO_REGISTRY_TOKEN`)
+const SECRET_MARKERS: [&str; 4] = ["TOKEN", "SECRET", "KEY", "PASSWORD"];
+
+/// Reads environment variables, but only those on the allowlist and never ones named like a secret,
+/// so secrets never reach the model
+pub struct EnvTool {
+    /// Exact names, or prefixes ending in `*`
+    pub allowed: Vec<String>,
+}
+
+impl Default for EnvTool {
+    fn default() -> Self {
+        EnvTool {
+            allowed: vec!["CARGO_*".into(), "RUST_*".into(), "PATH".into()],
+        }
+    }
+}
+
+impl EnvTool {
+    pub fn is_allowed(&self, name: &str) -> bool {
+        let upper = name.to_uppercase();
+        if SECRET_MARKERS.iter().any(|marker| upper.contains(marker)) {
+            return false;
+        }
+        self.allowed
+            .iter()
+            .any(|allowed| match allowed.strip_suffix('*') {
+                Some(prefix) => name.starts_with(prefix),
+                None => name == allowed,
+            })
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for EnvTool {
+    fn name(&self) -> &str {
+        "env_tool"
+    }
+
+    fn description(&self) -> Value {
+        function_schema(
+            self.name(),
+            &format!(
+                "Returns the value of an environment variable. Only these are permitted: {}, \
+                except names containing TOKEN, SECRET, KEY or PASSWORD",
+                self.allowed.join(", ")
+            ),
+            EnvArgs::parameters(),
+        )
+    }
+
+    fn tool_callback(&self) -> bool {
+        true
+    }
+
+    async fn execute_tool(&self, args: Value) -> Result<String> {
+        let name = &EnvArgs::parse(args)?.name;
+
+        if !self.is_allowed(name) {
+            return Ok(format!("Reading {} is not permitted", name));
+        }
+        let result = match env::var(name) {
+            Ok(value) => format!("{}={}", name, value),
+            Err(env::VarError::NotPresent) => format!("{} is not set", name),
+            Err(env::VarError::NotUnicode(_)) => format!("{} is set but not valid UTF-8", name),
+        };
+        debug!(%name, output = %result, "EnvTool executed");
+        Ok(result)
+    }
+}
+
+pub struct GitDiffTool;
 
 #[async_trait::async_trait]
 impl Tool for GitDiffTool {
@@ -399,18 +1196,11 @@ impl Tool for GitDiffTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Shows git diff for the current repository",
-                "parameters": {
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Shows git diff for the current repository",
+            NoArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -418,14 +1208,15 @@ impl Tool for GitDiffTool {
     }
 
     async fn execute_tool(&self, _args: Value) -> Result<String> {
-        let output = Command::new("git").args(["diff"]).output().await?;
+        let output = Command::new("git")
+            .args(["diff"])
+            .kill_on_drop(true)
+            .output()
+            .await?;
 
         if output.status.success() {
             let result = String::from_utf8_lossy(&output.stdout).to_string();
-            println!(
-                "{}",
-                format!("[DEBUG] GitDiffTool executed\n[Returning] \n{}\n", result).dimmed()
-            );
+            debug!(output = %result, "GitDiffTool executed");
             Ok(result)
         } else {
             let err_msg = String::from_utf8_lossy(&output.stderr).to_string();
@@ -443,31 +1234,25 @@ impl Tool for GitStatusTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Shows git status for the current repository",
-                "parameters": {
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Shows git status for the current repository",
+            NoArgs::parameters(),
+        )
     }
     fn tool_callback(&self) -> bool {
         true
     }
     async fn execute_tool(&self, _args: Value) -> Result<String> {
-        let output = Command::new("git").args(["status"]).output().await?;
+        let output = Command::new("git")
+            .args(["status"])
+            .kill_on_drop(true)
+            .output()
+            .await?;
 
         if output.status.success() {
             let result = String::from_utf8_lossy(&output.stdout).to_string();
-            println!(
-                "{}",
-                format!("[DEBUG] GitStatusTool executed\n[Returning] \n{}\n", result).dimmed()
-            );
+            debug!(output = %result, "GitStatusTool executed");
             Ok(result)
         } else {
             let err_msg = String::from_utf8_lossy(&output.stderr).to_string();
@@ -484,18 +1269,11 @@ impl Tool for PsTool {
         "process_list_tool"
     }
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Lists running processes on the system",
-                "parameters": {
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Lists running processes on the system",
+            NoArgs::parameters(),
+        )
     }
     fn tool_callback(&self) -> bool {
         true
@@ -508,18 +1286,15 @@ impl Tool for PsTool {
             c
         };
         #[cfg(not(target_os = "windows"))]
-        let cmd = {
+        let mut cmd = {
             let mut c = Command::new("ps");
             c.arg("-aux");
             c
         };
-        let output = cmd.output().await?;
+        let output = cmd.kill_on_drop(true).output().await?;
         if output.status.success() {
             let result = String::from_utf8_lossy(&output.stdout).to_string();
-            println!(
-                "{}",
-                format!("[DEBUG] PsTool executed\n[Returning] \n{}\n", result).dimmed()
-            );
+            debug!(output = %result, "PsTool executed");
             Ok(result)
         } else {
             let err_msg = String::from_utf8_lossy(&output.stderr).to_string();
@@ -537,18 +1312,11 @@ impl Tool for GitLogTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Shows git log for the current repository",
-                "parameters": {
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Shows git log for the current repository",
+            NoArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -558,15 +1326,13 @@ impl Tool for GitLogTool {
     async fn execute_tool(&self, _args: Value) -> Result<String> {
         let output = Command::new("git")
             .args(["log", "--oneline"])
+            .kill_on_drop(true)
             .output()
             .await?;
 
         if output.status.success() {
             let result = String::from_utf8_lossy(&output.stdout).to_string();
-            println!(
-                "{}",
-                format!("[DEBUG] GitLogTool executed\n[Returning] \n{}\n", result).dimmed()
-            );
+            debug!(output = %result, "GitLogTool executed");
             Ok(result)
         } else {
             let err_msg = String::from_utf8_lossy(&output.stderr).to_string();
@@ -575,7 +1341,28 @@ impl Tool for GitLogTool {
     }
 }
 
-pub struct SafeCurlTool;
+tool_args! {
+    pub struct SafeCurlArgs {
+        /// The URL to fetch data from
+        url: String,
+    }
+}
+
+/// Performs a GET request and returns the raw body. Goes through the same checks as [`WebFetchTool`]:
+/// http(s) only, no local or private addresses unless `allow_private` is set, a timeout and a size cap.
+pub struct SafeCurlTool {
+    pub allow_private: bool,
+    pub max_chars: usize,
+}
+
+impl Default for SafeCurlTool {
+    fn default() -> Self {
+        SafeCurlTool {
+            allow_private: env::var("R_AGENT_FETCH_ALLOW_PRIVATE").is_ok_and(|v| v == "1"),
+            max_chars: 20_000,
+        }
+    }
+}
 
 #[async_trait::async_trait]
 impl Tool for SafeCurlTool {
@@ -584,23 +1371,11 @@ impl Tool for SafeCurlTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Performs a safe HTTP GET request to the specified URL and returns the response body. Use this tool to fetch data from web APIs or websites.",
-                "parameters": {
-                    "type": "object",
-                    "properties": {
-                        "url": {
-                            "type": "string",
-                            "description": "The URL to fetch data from"
-                        }
-                    },
-                    "required": ["url"]
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Performs a safe HTTP GET request to the specified URL and returns the response body. Use this tool to fetch data from web APIs or websites.",
+            SafeCurlArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -608,33 +1383,313 @@ impl Tool for SafeCurlTool {
     }
 
     async fn execute_tool(&self, args: Value) -> Result<String> {
-        let url = args["url"]
-            .as_str()
-            .ok_or_else(|| anyhow!("missing 'url' parameter"))?;
-
-        let response = reqwest::get(url).await?;
-
-        if response.status().is_success() {
-            let body = response.text().await?;
-            println!(
-                "{}",
-                format!(
-                    "[DEBUG] SafeCurlTool executed\nFetching URL: {}\n[Returning] \n{}\n",
-                    url, body
-                )
-                .dimmed()
-            );
-            Ok(body)
+        let url = &SafeCurlArgs::parse(args)?.url;
+
+        let response = match fetch_checked(url, self.allow_private).await? {
+            Ok(response) => response,
+            Err(refused) => return Ok(refused),
+        };
+        if !response.status().is_success() {
+            return Ok(format!("Failed to fetch URL: HTTP {}", response.status()));
+        }
+
+        let body = truncate_output(&read_body(response).await?, self.max_chars);
+        debug!(%url, output = %body, "SafeCurlTool executed");
+        Ok(body)
+    }
+}
+
+tool_args! {
+    pub struct WebFetchArgs {
+        /// The http or https URL of the page
+        url: String,
+    }
+}
+
+/// Fetches a web page and returns its title and readable text, for documentation referenced in code.
+/// Local and private addresses are refused unless `allow_private` is set
+/// (`R_AGENT_FETCH_ALLOW_PRIVATE=1` for the default toolset), so the model can't probe internal services.
+pub struct WebFetchTool {
+    pub allow_private: bool,
+    pub max_chars: usize,
+}
+
+impl Default for WebFetchTool {
+    fn default() -> Self {
+        WebFetchTool {
+            allow_private: env::var("R_AGENT_FETCH_ALLOW_PRIVATE").is_ok_and(|v| v == "1"),
+            max_chars: 20_000,
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl Tool for WebFetchTool {
+    fn name(&self) -> &str {
+        "web_fetch_tool"
+    }
+
+    fn description(&self) -> Value {
+        function_schema(
+            self.name(),
+            "Fetches a web page over http(s) and returns its title and readable text with the HTML stripped. Use this to read documentation referenced in the code.",
+            WebFetchArgs::parameters(),
+        )
+    }
+
+    fn tool_callback(&self) -> bool {
+        true
+    }
+
+    async fn execute_tool(&self, args: Value) -> Result<String> {
+        let url = &WebFetchArgs::parse(args)?.url;
+
+        let response = match fetch_checked(url, self.allow_private).await? {
+            Ok(response) => response,
+            Err(refused) => return Ok(refused),
+        };
+        if !response.status().is_success() {
+            return Ok(format!("Failed to fetch URL: HTTP {}", response.status()));
+        }
+
+        let url = response.url().clone();
+        let body = read_body(response).await?;
+        let title = html_title(&body).unwrap_or_default();
+        let mut text = html_to_text(&body);
+        if text.chars().count() > self.max_chars {
+            text = text.chars().take(self.max_chars).collect();
+            text.push_str(&format!("\n\n[truncated to {} characters]", self.max_chars));
+        }
+
+        debug!(%url, %title, output = %text, "WebFetchTool executed");
+        Ok(format!("Title: {}\nURL: {}\n\n{}", title, url, text))
+    }
+}
+
+/// Bodies of fetched URLs are read up to this many bytes, the rest is dropped
+const MAX_FETCH_BYTES: usize = 2 * 1024 * 1024;
+
+/// GET `url` with [`fetch_client`], after refusing other schemes than http(s) and, unless `allow_private`,
+/// local and private hosts. The inner `Err` is the refusal or failure to hand back to the model.
+async fn fetch_checked(
+    url: &str,
+    allow_private: bool,
+) -> Result<std::result::Result<reqwest::Response, String>> {
+    let url = reqwest::Url::parse(url).map_err(|e| anyhow!("invalid url '{}': {}", url, e))?;
+
+    if !matches!(url.scheme(), "http" | "https") {
+        return Ok(Err(format!(
+            "Refused: only http and https URLs can be fetched, got {}",
+            url.scheme()
+        )));
+    }
+    if !allow_private && let Err(reason) = check_public_host(&url).await {
+        return Ok(Err(format!("Refused to fetch {}: {}", url, reason)));
+    }
+
+    match fetch_client(allow_private)?.get(url.clone()).send().await {
+        Ok(response) => Ok(Ok(response)),
+        Err(e) => Ok(Err(format!("Failed to fetch {}: {}", url, e))),
+    }
+}
+
+/// The body as text, cut at [`MAX_FETCH_BYTES`] so a huge download can't fill the memory
+async fn read_body(mut response: reqwest::Response) -> Result<String> {
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if body.len() >= MAX_FETCH_BYTES {
+            body.truncate(MAX_FETCH_BYTES);
+            break;
+        }
+    }
+    Ok(String::from_utf8_lossy(&body).into_owned())
+}
+
+/// Client for the fetch tools, redirects are followed only while they stay on public hosts
+fn fetch_client(allow_private: bool) -> Result<reqwest::Client> {
+    let policy = reqwest::redirect::Policy::custom(move |attempt| {
+        if attempt.previous().len() >= 5 {
+            attempt.error("too many redirects")
+        } else if !allow_private && is_private_literal(attempt.url()) {
+            attempt.error("redirect to a local or private address")
         } else {
-            let err_msg = format!("Failed to fetch URL: HTTP {}", response.status());
-            Ok(err_msg)
+            attempt.follow()
+        }
+    });
+    Ok(reqwest::Client::builder()
+        .user_agent(concat!("r-agent/", env!("CARGO_PKG_VERSION")))
+        .timeout(std::time::Duration::from_secs(20))
+        .redirect(policy)
+        .build()?)
+}
+
+/// Resolves the host and refuses loopback, private, link-local and unspecified addresses
+async fn check_public_host(url: &reqwest::Url) -> std::result::Result<(), String> {
+    if is_private_literal(url) {
+        return Err("local or private address".to_string());
+    }
+    let host = url.host_str().ok_or_else(|| "missing host".to_string())?;
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs = tokio::net::lookup_host((host, port))
+        .await
+        .map_err(|e| format!("could not resolve {}: {}", host, e))?;
+    for addr in addrs {
+        if is_private_ip(addr.ip()) {
+            return Err(format!(
+                "{} resolves to the private address {}",
+                host,
+                addr.ip()
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn is_private_literal(url: &reqwest::Url) -> bool {
+    let Some(host) = url.host_str() else {
+        return true;
+    };
+    // IPv6 hosts come bracketed
+    match host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) => is_private_ip(ip),
+        Err(_) => {
+            let host = host.to_ascii_lowercase();
+            host == "localhost" || host.ends_with(".localhost")
+        }
+    }
+}
+
+fn is_private_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                // Carrier-grade NAT, 100.64.0.0/10
+                || (a == 100 && (b & 0xc0) == 64)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_private_ip(IpAddr::V4(v4));
+            }
+            let first = ip.segments()[0];
+            ip.is_loopback()
+                || ip.is_unspecified()
+                // Unique local fc00::/7 and link-local fe80::/10
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+        }
+    }
+}
+
+fn html_title(html: &str) -> Option<String> {
+    let lower = html.to_ascii_lowercase();
+    let start = lower.find("<title")?;
+    let start = start + lower[start..].find('>')? + 1;
+    let end = start + lower[start..].find("</title")?;
+    Some(decode_entities(html[start..end].trim()))
+}
+
+/// Good-enough HTML to text: drops scripts, styles and tags, keeps block breaks, collapses whitespace
+fn html_to_text(html: &str) -> String {
+    let lower = html.to_ascii_lowercase();
+    let mut text = String::new();
+    let mut i = 0;
+
+    while i < html.len() {
+        if html.as_bytes()[i] != b'<' {
+            let next = html[i..].find('<').map_or(html.len(), |n| i + n);
+            text.push_str(&html[i..next]);
+            i = next;
+            continue;
+        }
+
+        let tag_end = lower[i..].find('>').map_or(html.len(), |n| i + n + 1);
+        let tag = &lower[i..tag_end];
+        let skip_until = ["script", "style", "noscript", "head"]
+            .iter()
+            .find(|name| {
+                tag.strip_prefix('<')
+                    .and_then(|t| t.strip_prefix(**name))
+                    .is_some_and(|rest| rest.starts_with(['>', ' ', '\t', '\n', '/']))
+            })
+            .map(|name| format!("</{}", name));
+
+        i = match skip_until {
+            Some(close) => lower[tag_end..]
+                .find(&close)
+                .and_then(|n| lower[tag_end + n..].find('>').map(|m| tag_end + n + m + 1))
+                .unwrap_or(html.len()),
+            None => tag_end,
+        };
+
+        let name = tag
+            .trim_start_matches(['<', '/'])
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .next()
+            .unwrap_or_default();
+        if matches!(
+            name,
+            "p" | "br"
+                | "div"
+                | "li"
+                | "tr"
+                | "h1"
+                | "h2"
+                | "h3"
+                | "h4"
+                | "h5"
+                | "h6"
+                | "pre"
+                | "section"
+        ) {
+            text.push('\n');
         }
     }
+
+    let text = decode_entities(&text);
+    let mut out = String::new();
+    let mut blank = false;
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            blank = !out.is_empty();
+            continue;
+        }
+        if blank {
+            out.push('\n');
+            blank = false;
+        }
+        out.push_str(&line);
+        out.push('\n');
+    }
+    out
 }
 
-/// A tool to run 'cargo check' in the current Rust project directory
+fn decode_entities(text: &str) -> String {
+    text.replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// A tool to run 'cargo check' in the enclosing Rust workspace, returning a compact list of diagnostics
 pub struct CargoCheckTool;
 
+/// Upper bound for one `cargo check`, a cold build of a big workspace can take a while
+const CARGO_CHECK_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(300);
+
 #[async_trait::async_trait]
 impl Tool for CargoCheckTool {
     fn name(&self) -> &str {
@@ -642,18 +1697,11 @@ impl Tool for CargoCheckTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Runs 'cargo check' in the current Rust project directory and returns the output. Use this tool to verify that your Rust code compiles without errors.",
-                "parameters": {
-                    "type": "object",
-                    "properties": {},
-                    "required": []
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Runs 'cargo check' in the enclosing Rust workspace and returns the errors and warnings as 'level file:line: message' lines. Use this tool to verify that your Rust code compiles without errors.",
+            NoArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -661,32 +1709,327 @@ impl Tool for CargoCheckTool {
     }
 
     async fn execute_tool(&self, _args: Value) -> Result<String> {
-        let output = Command::new("cargo").arg("check").output().await?;
+        let Some(root) = find_cargo_root(&env::current_dir()?) else {
+            return Ok("No Cargo.toml found in the current directory or its parents".to_string());
+        };
 
-        if output.status.success() {
-            let result = String::from_utf8_lossy(&output.stderr).to_string();
-            println!(
-                "{}",
-                format!(
-                    "[DEBUG] CargoCheckTool executed\n[Returning] \n{}\n",
-                    result
-                )
-                .dimmed()
-            );
-            Ok(result)
+        let mut cmd = Command::new("cargo");
+        cmd.arg("check")
+            .arg("--message-format=json")
+            .current_dir(&root)
+            .kill_on_drop(true);
+        let output = match tokio::time::timeout(CARGO_CHECK_TIMEOUT, cmd.output()).await {
+            Err(_) => {
+                return Ok(format!(
+                    "cargo check did not finish within {}s",
+                    CARGO_CHECK_TIMEOUT.as_secs()
+                ));
+            }
+            Ok(Err(e)) if e.kind() == std::io::ErrorKind::NotFound => {
+                return Ok("cargo is not installed or not in PATH".to_string());
+            }
+            Ok(output) => output?,
+        };
+
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let diagnostics = parse_cargo_diagnostics(&stdout);
+        let result = if diagnostics.is_empty() && !output.status.success() {
+            // Failures before compiling (bad manifest, resolution errors) only show up on stderr
+            String::from_utf8_lossy(&output.stderr).to_string()
+        } else if diagnostics.is_empty() {
+            format!("cargo check passed in {}", root.display())
         } else {
-            let err_msg = String::from_utf8_lossy(&output.stderr).to_string();
-            println!(
-                "{}",
-                format!(
-                    "[DEBUG] CargoCheckTool executed (with errors)\n[Returning] \n{}\n",
-                    err_msg
-                )
-                    .dimmed()
-            );
-            Ok(err_msg)
+            let errors = diagnostics
+                .iter()
+                .filter(|d| d.starts_with("error"))
+                .count();
+            format!(
+                "cargo check in {}: {} error(s), {} warning(s)\n{}",
+                root.display(),
+                errors,
+                diagnostics.len() - errors,
+                diagnostics.join("\n")
+            )
+        };
+
+        debug!(root = %root.display(), output = %result, "CargoCheckTool executed");
+        Ok(result)
+    }
+}
+
+/// Closest directory at or above `dir` holding a Cargo.toml
+fn find_cargo_root(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .find(|d| d.join("Cargo.toml").is_file())
+        .map(Path::to_path_buf)
+}
+
+/// One `level file:line:column: message` line per error or warning of a `--message-format=json` stream
+fn parse_cargo_diagnostics(stream: &str) -> Vec<String> {
+    stream
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|msg| msg["reason"] == "compiler-message")
+        .filter_map(|msg| {
+            let message = &msg["message"];
+            let level = message["level"].as_str()?;
+            if !matches!(level, "error" | "warning") {
+                return None;
+            }
+            let text = message["message"].as_str()?;
+            // Summary lines like "aborting due to 2 previous errors" have no spans
+            let span = message["spans"]
+                .as_array()?
+                .iter()
+                .find(|s| s["is_primary"] == true)?;
+            Some(format!(
+                "{} {}:{}:{}: {}",
+                level,
+                span["file_name"].as_str().unwrap_or("?"),
+                span["line_start"],
+                span["column_start"],
+                text
+            ))
+        })
+        // The same warning is reported once per target (lib, bin, tests)
+        .fold(Vec::new(), |mut diagnostics, d| {
+            if !diagnostics.contains(&d) {
+                diagnostics.push(d);
+            }
+            diagnostics
+        })
+}
+
+/// Processes started by [`BackgroundRunTool`], shared with [`ProcessStatusTool`] and kept across turns
+static BACKGROUND: LazyLock<Mutex<BackgroundProcesses>> = LazyLock::new(Default::default);
+
+/// At most this many background processes are tracked at once, finished ones included
+const MAX_BACKGROUND_PROCESSES: usize = 8;
+
+/// Output kept per background process, older output is dropped first
+const BACKGROUND_OUTPUT_LIMIT: usize = 64 * 1024;
+
+/// What [`ProcessStatusTool`] shows of the captured output
+const BACKGROUND_OUTPUT_SHOWN: usize = 4000;
+
+#[derive(Default)]
+struct BackgroundProcesses {
+    next_id: u32,
+    processes: Vec<BackgroundProcess>,
+}
+
+struct BackgroundProcess {
+    id: u32,
+    command: String,
+    started: Instant,
+    child: tokio::process::Child,
+    /// stdout and stderr interleaved as the lines arrive
+    output: Arc<Mutex<String>>,
+}
+
+impl BackgroundProcess {
+    fn status(&mut self) -> String {
+        match self.child.try_wait() {
+            Ok(None) => format!("running for {}s", self.started.elapsed().as_secs()),
+            Ok(Some(status)) => match status.code() {
+                Some(code) => format!("exited with code {}", code),
+                None => "killed by a signal".to_string(),
+            },
+            Err(e) => format!("unknown ({})", e),
         }
+    }
+
+    fn summary(&mut self) -> String {
+        let status = self.status();
+        format!("[{}] {} - {}", self.id, self.command, status)
+    }
+}
 
+/// Kill every process still running, called once the command is done so nothing outlives ragent
+pub fn stop_background_processes() {
+    let mut background = BACKGROUND.lock().unwrap_or_else(|e| e.into_inner());
+    for process in background.processes.iter_mut() {
+        let _ = process.child.start_kill();
+    }
+    background.processes.clear();
+}
+
+/// Calls [`stop_background_processes`] when dropped, hold one for the lifetime of the command
+pub struct BackgroundProcessGuard;
+
+impl Drop for BackgroundProcessGuard {
+    fn drop(&mut self) {
+        stop_background_processes();
+    }
+}
+
+/// Append what `reader` produces to `output`, keeping only the last [`BACKGROUND_OUTPUT_LIMIT`] bytes
+fn capture_output<R>(reader: R, output: Arc<Mutex<String>>)
+where
+    R: tokio::io::AsyncRead + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        let mut lines = BufReader::new(reader).lines();
+        while let Ok(Some(line)) = lines.next_line().await {
+            let mut output = output.lock().unwrap_or_else(|e| e.into_inner());
+            output.push_str(&line);
+            output.push('\n');
+            if output.len() > BACKGROUND_OUTPUT_LIMIT {
+                let mut cut = output.len() - BACKGROUND_OUTPUT_LIMIT;
+                while !output.is_char_boundary(cut) {
+                    cut += 1;
+                }
+                output.drain(..cut);
+            }
+        }
+    });
+}
+
+tool_args! {
+    pub struct BackgroundRunArgs {
+        /// The shell command to run
+        command: String,
+    }
+}
+
+/// Starts a shell command without waiting for it (a dev server, a watcher, a long build),
+/// its output is captured for [`ProcessStatusTool`]
+pub struct BackgroundRunTool;
+
+#[async_trait::async_trait]
+impl Tool for BackgroundRunTool {
+    fn name(&self) -> &str {
+        "background_run_tool"
+    }
+
+    fn description(&self) -> Value {
+        function_schema(
+            self.name(),
+            "Starts a shell command in the background and returns its id right away. Use it for long-running commands like servers or watchers, then check on them with process_status_tool.",
+            BackgroundRunArgs::parameters(),
+        )
+    }
+
+    fn tool_callback(&self) -> bool {
+        true
+    }
+
+    async fn execute_tool(&self, args: Value) -> Result<String> {
+        let command = &BackgroundRunArgs::parse(args)?.command;
+
+        let mut background = BACKGROUND.lock().unwrap_or_else(|e| e.into_inner());
+        if background.processes.len() >= MAX_BACKGROUND_PROCESSES {
+            // Make room by forgetting finished processes first
+            background
+                .processes
+                .retain_mut(|p| matches!(p.child.try_wait(), Ok(None)));
+            if background.processes.len() >= MAX_BACKGROUND_PROCESSES {
+                return Ok(format!(
+                    "{} background processes are already running, wait for one to finish",
+                    MAX_BACKGROUND_PROCESSES
+                ));
+            }
+        }
+
+        #[cfg(target_os = "windows")]
+        let mut cmd = {
+            let mut c = Command::new("cmd");
+            c.arg("/C").arg(command);
+            c
+        };
+        #[cfg(not(target_os = "windows"))]
+        let mut cmd = {
+            let mut c = Command::new("sh");
+            c.arg("-c").arg(command);
+            c
+        };
+        let mut child = cmd
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let output = Arc::new(Mutex::new(String::new()));
+        if let Some(stdout) = child.stdout.take() {
+            capture_output(stdout, output.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            capture_output(stderr, output.clone());
+        }
+
+        background.next_id += 1;
+        let id = background.next_id;
+        let pid = child.id().unwrap_or_default();
+        background.processes.push(BackgroundProcess {
+            id,
+            command: command.to_string(),
+            started: Instant::now(),
+            child,
+            output,
+        });
+
+        debug!(id, pid, command, "BackgroundRunTool started a process");
+        Ok(format!(
+            "Started background process {} (pid {}): {}\nCheck it with process_status_tool and id {}",
+            id, pid, command, id
+        ))
+    }
+}
+
+tool_args! {
+    pub struct ProcessStatusArgs {
+        /// The id returned by background_run_tool
+        id: Option<u32>,
+    }
+}
+
+/// Reports on the processes started by [`BackgroundRunTool`]
+pub struct ProcessStatusTool;
+
+#[async_trait::async_trait]
+impl Tool for ProcessStatusTool {
+    fn name(&self) -> &str {
+        "process_status_tool"
+    }
+
+    fn description(&self) -> Value {
+        function_schema(
+            self.name(),
+            "Checks a process started with background_run_tool: whether it is still running or its exit code, and its latest output. Without an id, lists every background process.",
+            ProcessStatusArgs::parameters(),
+        )
+    }
+
+    fn tool_callback(&self) -> bool {
+        true
+    }
+
+    async fn execute_tool(&self, args: Value) -> Result<String> {
+        let mut background = BACKGROUND.lock().unwrap_or_else(|e| e.into_inner());
+
+        let Some(id) = ProcessStatusArgs::parse(args)?.id else {
+            if background.processes.is_empty() {
+                return Ok("No background processes".to_string());
+            }
+            let summaries: Vec<String> = background
+                .processes
+                .iter_mut()
+                .map(|p| p.summary())
+                .collect();
+            return Ok(summaries.join("\n"));
+        };
+
+        let Some(process) = background.processes.iter_mut().find(|p| p.id == id) else {
+            return Ok(format!("No background process with id {}", id));
+        };
+        let summary = process.summary();
+        let output = process.output.lock().unwrap_or_else(|e| e.into_inner());
+        let shown = match output.char_indices().rev().nth(BACKGROUND_OUTPUT_SHOWN) {
+            Some((start, _)) => format!("[...]\n{}", &output[start..]),
+            None => output.clone(),
+        };
+        Ok(format!("{}\nOutput:\n{}", summary, shown))
     }
 }
 
@@ -700,17 +2043,11 @@ impl Tool for TimeTool {
     }
 
     fn description(&self) -> Value {
-        serde_json::json!({
-            "type": "function",
-            "function": {
-                "name": self.name(),
-                "description": "Returns the current system time in a human-readable format.",
-                "parameters": {
-                    "type": "object",
-                    "properties": {}
-                }
-            }
-        })
+        function_schema(
+            self.name(),
+            "Returns the current system time in a human-readable format.",
+            NoArgs::parameters(),
+        )
     }
 
     fn tool_callback(&self) -> bool {
@@ -719,14 +2056,854 @@ impl Tool for TimeTool {
 
     async fn execute_tool(&self, _args: Value) -> Result<String> {
         let now = chrono::Local::now();
-        println!(
+        debug!(output = %now.to_rfc2822(), "TimeTool executed");
+        Ok(format!("Current system time is: {}", now.to_rfc2822()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicUsize;
+    use std::time::Duration;
+
+    /// Collects what a fmt subscriber writes
+    #[derive(Clone, Default)]
+    struct Captured(Arc<Mutex<Vec<u8>>>);
+
+    impl std::io::Write for Captured {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn tool_runs_inside_a_tool_span() {
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = tracing_subscriber::fmt()
+            .with_max_level(tracing::Level::DEBUG)
+            .with_ansi(false)
+            .with_writer(move || writer.clone())
+            .finish();
+        // The test runtime runs on this thread, the default reaches every await below
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        DynTool::new(Box::new(TimeTool))
+            .execute_tool(serde_json::json!({}))
+            .await
+            .unwrap();
+
+        let log = String::from_utf8(captured.0.lock().unwrap().clone()).unwrap();
+        let in_span = |message: &str| {
+            log.lines().any(|line| {
+                line.contains(r#"tool{name="get_time_tool"}"#) && line.contains(message)
+            })
+        };
+        assert!(in_span("tool started"), "{}", log);
+        assert!(in_span("TimeTool executed"), "{}", log);
+        assert!(in_span("tool finished"), "{}", log);
+    }
+
+    #[tokio::test]
+    async fn concurrent_calls_stay_under_the_limit() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (count, max) = (running.clone(), peak.clone());
+        let slow = FnTool::new("slow_tool", Value::Null, true, move |_| {
+            let (count, max) = (count.clone(), max.clone());
+            async move {
+                let now = count.fetch_add(1, Ordering::SeqCst) + 1;
+                max.fetch_max(now, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                count.fetch_sub(1, Ordering::SeqCst);
+                Ok("done".to_string())
+            }
+        });
+        let tool = DynTool::new(Box::new(slow)).with_limit(Arc::new(Semaphore::new(2)));
+
+        let calls = (0..8).map(|i| tool.execute_tool(serde_json::json!({ "call": i })));
+        let outputs = futures_util::future::join_all(calls).await;
+        assert!(outputs.iter().all(|o| o.as_deref().unwrap() == "done"));
+        assert_eq!(peak.load(Ordering::SeqCst), 2);
+        assert_eq!(running.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn callback_override_wins_over_the_tool() {
+        assert!(TimeTool.tool_callback());
+        let plain = DynTool::new(Box::new(TimeTool));
+        let terminal = DynTool::new(Box::new(TimeTool)).with_callback(false);
+        assert!(plain.tool_callback());
+        assert!(!terminal.tool_callback());
+        assert!(
+            DynTool::new(Box::new(TimeTool))
+                .with_callback(true)
+                .tool_callback()
+        );
+        assert_eq!(terminal.name(), plain.name());
+    }
+
+    /// A tool taking a required `path` and an optional integer `limit`, counting its executions
+    fn schema_tool(runs: Arc<AtomicUsize>) -> DynTool {
+        let parameters = serde_json::json!({
+            "type": "object",
+            "properties": {
+                "path": { "type": "string" },
+                "limit": { "type": "integer" }
+            },
+            "required": ["path"]
+        });
+        let description = function_schema("head_tool", "First lines of a file", parameters);
+        DynTool::new(Box::new(FnTool::new(
+            "head_tool",
+            description,
+            true,
+            move |_| {
+                runs.fetch_add(1, Ordering::SeqCst);
+                async { Ok("first lines".to_string()) }
+            },
+        )))
+    }
+
+    #[tokio::test]
+    async fn arguments_are_checked_against_the_schema() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let tool = schema_tool(runs.clone());
+
+        let valid = serde_json::json!({ "path": "README.md", "limit": 5 });
+        assert_eq!(tool.execute_tool(valid).await.unwrap(), "first lines");
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+
+        let missing = tool
+            .execute_tool(serde_json::json!({ "limit": 5 }))
+            .await
+            .unwrap();
+        assert!(
+            missing.starts_with("Invalid arguments for head_tool:\n- "),
             "{}",
+            missing
+        );
+        assert!(
+            missing.contains("\"path\" is a required property"),
+            "{}",
+            missing
+        );
+        assert!(missing.contains("Expected parameters: "), "{}", missing);
+
+        let wrong_type = tool
+            .execute_tool(serde_json::json!({ "path": "README.md", "limit": "five" }))
+            .await
+            .unwrap();
+        assert!(wrong_type.contains("- /limit: "), "{}", wrong_type);
+        assert!(wrong_type.contains("\"integer\""), "{}", wrong_type);
+
+        // Neither bad call reached the tool
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn failing_tool_is_reported_as_a_result() {
+        let flaky = FnTool::new("flaky_tool", Value::Null, true, |args: Value| async move {
+            match args["path"].as_str() {
+                Some("missing.txt") => Err(anyhow!("No such file: missing.txt")),
+                _ => Ok("contents".to_string()),
+            }
+        });
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let tool = DynTool::new(Box::new(flaky)).with_events(sender);
+
+        let failed = tool
+            .execute_tool(serde_json::json!({ "path": "missing.txt" }))
+            .await
+            .unwrap();
+        assert_eq!(
+            failed,
+            "Error: flaky_tool failed: No such file: missing.txt"
+        );
+
+        // The loop goes on, the model's next call runs normally
+        let recovered = tool
+            .execute_tool(serde_json::json!({ "path": "present.txt" }))
+            .await
+            .unwrap();
+        assert_eq!(recovered, "contents");
+
+        let mut outputs = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            if let AgentEvent::ToolResult { output, .. } = event {
+                outputs.push(output);
+            }
+        }
+        assert_eq!(outputs, vec![failed, recovered]);
+    }
+
+    #[tokio::test]
+    async fn denied_call_never_reaches_the_tool() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let write = FnTool::new("write_tool", Value::Null, true, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok("written".to_string()) }
+        });
+        // Scripted approver: deny anything touching the .env file
+        let approver: Approver = Arc::new(|_, args| args["path"] != ".env");
+        let tool = DynTool::new(Box::new(write)).with_approver(approver);
+
+        let denied = tool
+            .execute_tool(serde_json::json!({ "path": ".env" }))
+            .await
+            .unwrap();
+        assert!(
+            denied.starts_with("The user denied the call to write_tool"),
+            "{}",
+            denied
+        );
+        assert_eq!(runs.load(Ordering::SeqCst), 0);
+
+        let approved = tool
+            .execute_tool(serde_json::json!({ "path": "notes.md" }))
+            .await
+            .unwrap();
+        assert_eq!(approved, "written");
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn read_only_mode_registers_no_mutating_tool() {
+        let names = |options: ToolOptions| -> Vec<String> {
+            options
+                .tools()
+                .iter()
+                .map(|tool| tool.name().to_string())
+                .collect()
+        };
+        let all = names(ToolOptions::default());
+        let read_only = names(ToolOptions {
+            read_only: true,
+            ..ToolOptions::default()
+        });
+
+        assert!(all.iter().any(|name| name == "background_run_tool"));
+        for name in &read_only {
+            assert!(!MUTATING_TOOLS.contains(&name.as_str()), "{}", name);
+        }
+        // Everything else is still there
+        assert_eq!(
+            read_only,
+            all.into_iter()
+                .filter(|name| !MUTATING_TOOLS.contains(&name.as_str()))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[tokio::test]
+    async fn each_tool_reports_its_call_while_it_runs() {
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let sleeper = |name: &str| {
+            let tool = FnTool::new(name, Value::Null, true, |_| async {
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                Ok("awake".to_string())
+            });
+            DynTool::new(Box::new(tool)).with_events(sender.clone())
+        };
+        let (first, second) = (sleeper("first_tool"), sleeper("second_tool"));
+
+        let running = tokio::spawn(async move {
+            let args = serde_json::json!({});
+            futures_util::future::join(first.execute_tool(args.clone()), second.execute_tool(args))
+                .await
+        });
+
+        // Both calls are announced long before either tool is done
+        let mut started = Vec::new();
+        for _ in 0..2 {
+            match receiver.recv().await.unwrap() {
+                AgentEvent::ToolCall { name, .. } => started.push(name),
+                other => panic!("expected a tool call, got {:?}", other),
+            }
+        }
+        started.sort();
+        assert_eq!(started, ["first_tool", "second_tool"]);
+        assert!(!running.is_finished());
+
+        let (a, b) = running.await.unwrap();
+        assert_eq!((a.unwrap(), b.unwrap()), ("awake".into(), "awake".into()));
+        let mut finished = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            match event {
+                AgentEvent::ToolResult { name, .. } => finished.push(name),
+                other => panic!("expected a tool result, got {:?}", other),
+            }
+        }
+        finished.sort();
+        assert_eq!(finished, ["first_tool", "second_tool"]);
+    }
+
+    #[tokio::test]
+    async fn identical_calls_stop_the_turn_early() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let counter = runs.clone();
+        let stuck = FnTool::new("read_file_tool", Value::Null, true, move |_| {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async { Ok("same as before".to_string()) }
+        });
+        let tool = DynTool::new(Box::new(stuck)).with_repeat_guard(RepeatGuard::new(3));
+        let args = serde_json::json!({ "path": "Cargo.toml" });
+
+        // A model stuck on one call, within forge's budget of 25 iterations
+        let mut stopped_at = None;
+        for iteration in 1..=25 {
+            if let Err(e) = tool.execute_tool(args.clone()).await {
+                assert_eq!(
+                    e.to_string(),
+                    r#"The model called read_file_tool with the same arguments 4 times ({"path":"Cargo.toml"}), stopping the turn"#
+                );
+                stopped_at = Some(iteration);
+                break;
+            }
+        }
+        assert_eq!(stopped_at, Some(4));
+        assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+        // Other arguments are a different call
+        let other = serde_json::json!({ "path": "README.md" });
+        assert_eq!(tool.execute_tool(other).await.unwrap(), "same as before");
+    }
+
+    #[tokio::test]
+    async fn huge_tool_output_is_clamped() {
+        let huge = FnTool::new("dump_tool", Value::Null, true, |_| async {
+            Ok("é".repeat(1_000_000))
+        });
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let tool = DynTool::new(Box::new(huge))
+            .with_max_output(1000)
+            .with_events(sender);
+
+        let output = tool.execute_tool(serde_json::json!({})).await.unwrap();
+        assert!(output.starts_with(&"é".repeat(1000)));
+        assert!(output.ends_with("\n[truncated, 1000 of 1000000 chars kept]"));
+        assert!(output.chars().count() < 1100);
+
+        // What the terminal shows is what the model gets
+        let mut results = Vec::new();
+        while let Ok(event) = receiver.try_recv() {
+            if let AgentEvent::ToolResult { output, .. } = event {
+                results.push(output);
+            }
+        }
+        assert_eq!(results, [output]);
+
+        let small = FnTool::new("echo_tool", Value::Null, true, |_| async {
+            Ok("short".to_string())
+        });
+        let tool = DynTool::new(Box::new(small)).with_max_output(1000);
+        assert_eq!(
+            tool.execute_tool(serde_json::json!({})).await.unwrap(),
+            "short"
+        );
+    }
+
+    #[cfg(target_os = "linux")]
+    #[tokio::test]
+    async fn cancelled_tool_call_reaps_its_child() {
+        let pid_file = crate::core::data::tests::temp_dir("reaped-child").join("pid");
+        let path = pid_file.clone();
+        // Spawned like the real tools spawn theirs, `exec` keeps the pid the shell wrote
+        let sleeper = FnTool::new("sleep_tool", Value::Null, true, move |_| {
+            let path = path.clone();
+            async move {
+                let script = format!("echo $$ > {}; exec sleep 30", path.display());
+                let output = Command::new("sh")
+                    .args(["-c", &script])
+                    .kill_on_drop(true)
+                    .output()
+                    .await?;
+                Ok(String::from_utf8_lossy(&output.stdout).to_string())
+            }
+        });
+        let tool = DynTool::new(Box::new(sleeper));
+        let cancel = tokio_util::sync::CancellationToken::new();
+
+        let watcher = {
+            let (cancel, pid_file) = (cancel.clone(), pid_file.clone());
+            tokio::spawn(async move {
+                while !std::fs::read_to_string(&pid_file).is_ok_and(|pid| pid.ends_with('\n')) {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+                cancel.cancel();
+            })
+        };
+        // What the runner does on Ctrl-C: stop waiting and drop the call
+        let finished = tokio::select! {
+            _ = cancel.cancelled() => false,
+            _ = tool.execute_tool(serde_json::json!({})) => true,
+        };
+        assert!(!finished);
+        watcher.await.unwrap();
+
+        let pid = std::fs::read_to_string(&pid_file).unwrap();
+        let proc_entry = PathBuf::from(format!("/proc/{}", pid.trim()));
+        let reaped = tokio::time::timeout(Duration::from_secs(5), async {
+            while proc_entry.exists() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await;
+        assert!(reaped.is_ok(), "sleep {} is still around", pid.trim());
+    }
+
+    #[tokio::test]
+    async fn closure_tool_registers_and_executes() {
+        let greeting = Arc::new("Hello".to_string());
+        let greet = move |args: Value| {
+            let greeting = greeting.clone();
+            async move {
+                let name = args["name"].as_str().ok_or(anyhow!("no name given"))?;
+                Ok(format!("{}, {}!", greeting, name))
+            }
+        };
+        let parameters = serde_json::json!({
+            "type": "object",
+            "properties": { "name": { "type": "string" } },
+            "required": ["name"]
+        });
+        let description = function_schema("greet_tool", "Greets someone", parameters);
+
+        let mut registry = ToolRegistry::new();
+        register_fn(
+            &mut registry,
+            "greet_tool",
+            description.clone(),
+            false,
+            greet.clone(),
+        );
+
+        // The same closure, wrapped as register_fn wraps it
+        let tool = FnTool::new("greet_tool", description.clone(), false, greet);
+        assert_eq!(tool.name(), "greet_tool");
+        assert_eq!(tool.description(), description);
+        assert!(!tool.tool_callback());
+        let args = serde_json::json!({ "name": "Ada" });
+        assert_eq!(tool.execute_tool(args).await.unwrap(), "Hello, Ada!");
+        assert!(tool.execute_tool(serde_json::json!({})).await.is_err());
+
+        // Behind a DynTool the schema is enforced before the closure runs
+        let checked = DynTool::new(Box::new(tool));
+        let refused = checked.execute_tool(serde_json::json!({})).await.unwrap();
+        assert!(
+            refused.starts_with("Invalid arguments for greet_tool"),
+            "{}",
+            refused
+        );
+    }
+
+    #[test]
+    fn env_tool_refuses_secrets_on_the_allowlist() {
+        let tool = EnvTool::default();
+        assert!(tool.is_allowed("CARGO_HOME"));
+        assert!(tool.is_allowed("PATH"));
+        assert!(!tool.is_allowed("CARGO_REGISTRY_TOKEN"));
+        assert!(!tool.is_allowed("CARGO_REGISTRIES_MY_REGISTRY_TOKEN"));
+        assert!(!tool.is_allowed("HOME"));
+    }
+
+    #[tokio::test]
+    async fn env_tool_does_not_return_a_token() {
+        let output = EnvTool::default()
+            .execute_tool(serde_json::json!({ "name": "CARGO_REGISTRY_TOKEN" }))
+            .await
+            .unwrap();
+        assert_eq!(output, "Reading CARGO_REGISTRY_TOKEN is not permitted");
+    }
+
+    #[tokio::test]
+    async fn bad_arguments_are_an_error_not_a_panic() {
+        let error = RgTool
+            .execute_tool(serde_json::json!({ "pattern": 42 }))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Invalid arguments"));
+    }
+
+    #[test]
+    fn every_tool_declares_an_object_schema() {
+        for tool in default_tools() {
+            let schema = tool.description();
+            assert_eq!(schema["function"]["name"], tool.name());
+            assert_eq!(schema["function"]["parameters"]["type"], "object");
+        }
+    }
+
+    #[tokio::test]
+    async fn safe_curl_refuses_private_addresses() {
+        let tool = SafeCurlTool {
+            allow_private: false,
+            max_chars: 100,
+        };
+        for url in [
+            "http://169.254.169.254/latest/meta-data/",
+            "http://localhost:8080/",
+            "http://[::1]/",
+            "file:///etc/passwd",
+        ] {
+            let output = tool
+                .execute_tool(serde_json::json!({ "url": url }))
+                .await
+                .unwrap();
+            assert!(output.starts_with("Refused"), "{}: {}", url, output);
+        }
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    #[tokio::test]
+    async fn background_process_can_be_polled_until_it_exits() {
+        let started = BackgroundRunTool
+            .execute_tool(serde_json::json!({"command": "sleep 0.2; echo done"}))
+            .await
+            .unwrap();
+        let id: u32 = started
+            .strip_prefix("Started background process ")
+            .and_then(|rest| rest.split_whitespace().next())
+            .and_then(|id| id.parse().ok())
+            .unwrap_or_else(|| panic!("no id in {:?}", started));
+
+        let status = || ProcessStatusTool.execute_tool(serde_json::json!({ "id": id }));
+        assert!(status().await.unwrap().contains("running for"));
+
+        let deadline = Instant::now() + Duration::from_secs(10);
+        let report = loop {
+            let report = status().await.unwrap();
+            if report.contains("exited") {
+                break report;
+            }
+            assert!(Instant::now() < deadline, "still running: {}", report);
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        };
+        assert!(report.contains("exited with code 0"), "{}", report);
+        // The output is captured by a separate task, give it a moment to catch up
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(status().await.unwrap().ends_with("done\n"));
+
+        let missing = ProcessStatusTool
+            .execute_tool(serde_json::json!({ "id": 9999 }))
+            .await
+            .unwrap();
+        assert_eq!(missing, "No background process with id 9999");
+    }
+
+    const RUST_FIXTURE: &str = r#"use std::fmt;
+
+/// Parses a brace.
+/// Handles `{` in docs.
+#[inline]
+#[allow(dead_code)]
+pub fn parse_brace(c: char) -> bool {
+    let open = '{';
+    let text = "}}}";
+    // a } in a comment
+    c == open && !text.is_empty()
+}
+
+pub struct Point {
+    x: i32,
+}
+
+impl fmt::Display for Point {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.x)
+    }
+}
+
+fn declared_only(x: u8);
+"#;
+
+    const PYTHON_FIXTURE: &str = r#"import os
+
+@cache
+def load(path):
+    if path:
+        return os.path.basename(path)
+
+    return None
+
+
+def other():
+    pass
+"#;
+
+    fn definition<'a>(source: &'a str, symbol: &str) -> Vec<&'a str> {
+        let lines: Vec<&str> = source.lines().collect();
+        let found = find_definitions(&lines, symbol);
+        assert_eq!(found.len(), 1, "{} found {:?}", symbol, found);
+        let (start, end) = found[0];
+        lines[start..=end].to_vec()
+    }
+
+    #[test]
+    fn rust_fn_comes_with_its_docs_and_attributes() {
+        let lines = definition(RUST_FIXTURE, "parse_brace");
+        assert_eq!(lines.first(), Some(&"/// Parses a brace."));
+        assert_eq!(lines.last(), Some(&"}"));
+        assert_eq!(lines.len(), 10);
+        assert!(lines.contains(&"#[allow(dead_code)]"));
+    }
+
+    #[test]
+    fn brace_literals_and_comments_do_not_end_a_body() {
+        assert!(code_chars("let open = '{';").iter().all(|c| *c != '{'));
+        assert!(code_chars(r#"let s = "}\"}";"#).iter().all(|c| *c != '}'));
+        assert_eq!(code_chars("x // }").iter().collect::<String>(), "x ");
+        // A lifetime is not a char literal
+        assert!(code_chars("fn f<'a>() {").contains(&'{'));
+
+        let lines: Vec<&str> = RUST_FIXTURE.lines().collect();
+        let start = lines.iter().position(|l| l.starts_with("pub fn")).unwrap();
+        assert_eq!(lines[definition_end(&lines, start)], "}");
+        assert_eq!(definition_end(&lines, start), start + 5);
+    }
+
+    #[test]
+    fn impl_blocks_match_the_type_and_the_trait() {
+        assert!(defines("impl fmt::Display for Point {", "Point"));
+        assert!(defines("impl<T: Clone> Trait for Wrapper<T> {", "Wrapper"));
+        assert!(defines("unsafe impl Send for Point {}", "Send"));
+        assert!(!defines("impl Other for Point2 {", "Point"));
+        assert!(!defines("// impl Display for Point {", "Point"));
+        assert!(!defines("let p = Point { x: 1 };", "Point"));
+
+        let lines = definition(RUST_FIXTURE, "Display");
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0], "impl fmt::Display for Point {");
+
+        let lines: Vec<&str> = RUST_FIXTURE.lines().collect();
+        assert_eq!(find_definitions(&lines, "Point").len(), 2);
+    }
+
+    #[test]
+    fn declarations_end_at_their_semicolon() {
+        assert_eq!(
+            definition(RUST_FIXTURE, "declared_only"),
+            ["fn declared_only(x: u8);"]
+        );
+    }
+
+    #[test]
+    fn python_def_ends_with_its_indentation() {
+        let lines = definition(PYTHON_FIXTURE, "load");
+        assert_eq!(lines.first(), Some(&"@cache"));
+        assert_eq!(lines.last(), Some(&"    return None"));
+        assert_eq!(
+            definition(PYTHON_FIXTURE, "other"),
+            ["def other():", "    pass"]
+        );
+    }
+
+    #[tokio::test]
+    async fn read_symbol_tool_prints_the_definition_with_line_numbers() {
+        let path = crate::core::data::tests::temp_dir("read-symbol").join("fixture.rs");
+        std::fs::write(&path, RUST_FIXTURE).unwrap();
+        let path = path.display().to_string();
+
+        let output = ReadSymbolTool
+            .execute_tool(serde_json::json!({"path": path, "symbol": "Point"}))
+            .await
+            .unwrap();
+        assert!(output.starts_with(&format!("{}:14-16\n14: pub struct Point {{", path)));
+        assert!(output.contains(&format!("{}:18-22\n18: impl fmt::Display", path)));
+
+        let missing = ReadSymbolTool
+            .execute_tool(serde_json::json!({"path": path, "symbol": "nothing"}))
+            .await
+            .unwrap();
+        assert!(missing.starts_with("No definition of 'nothing'"));
+    }
+
+    const HTML_PAGE: &str = r#"<!DOCTYPE html>
+<html><head><title> Tom &amp; Jerry </title>
+<style>body { color: red; }</style></head>
+<body>
+<script type="text/javascript">var hidden = "<p>not text</p>";</script>
+<h1>Cartoons</h1><p>Cat &lt;3 mouse, &quot;always&quot;&nbsp;&#39;forever&#39;.</p>
+<NOSCRIPT>enable js</NOSCRIPT>
+<ul><li>One</li><li>Two</li></ul>
+</body></html>"#;
+
+    #[test]
+    fn html_title_is_trimmed_and_decoded() {
+        assert_eq!(html_title(HTML_PAGE).as_deref(), Some("Tom & Jerry"));
+        assert_eq!(
+            html_title("<TITLE lang=\"en\">Docs</TITLE>").as_deref(),
+            Some("Docs")
+        );
+        assert_eq!(html_title("<p>no title</p>"), None);
+    }
+
+    #[test]
+    fn html_to_text_drops_scripts_styles_and_tags() {
+        assert_eq!(
+            html_to_text(HTML_PAGE),
+            "Cartoons\n\nCat <3 mouse, \"always\" 'forever'.\n\nOne\n\nTwo\n"
+        );
+        assert_eq!(decode_entities("&amp;lt;"), "&lt;");
+    }
+
+    #[tokio::test]
+    async fn web_fetch_refuses_private_addresses() {
+        let tool = WebFetchTool {
+            allow_private: false,
+            max_chars: 100,
+        };
+        for url in [
+            "http://127.0.0.1:8080/",
+            "http://10.0.0.8/admin",
+            "http://192.168.1.1/",
+            "http://[::ffff:127.0.0.1]/",
+            "ftp://example.com/file",
+        ] {
+            let output = tool
+                .execute_tool(serde_json::json!({ "url": url }))
+                .await
+                .unwrap();
+            assert!(output.starts_with("Refused"), "{}: {}", url, output);
+        }
+    }
+
+    #[tokio::test]
+    async fn web_fetch_returns_the_readable_page() {
+        use crate::core::test_server::{Reply, TestServer};
+
+        let html = [("Content-Type", "text/html; charset=utf-8")];
+        let server = TestServer::start(vec![
+            Reply::with_headers(200, &html, HTML_PAGE),
+            Reply::new(404, "gone"),
+        ])
+        .await;
+        let tool = WebFetchTool {
+            allow_private: true,
+            max_chars: 8,
+        };
+        let fetch = || tool.execute_tool(serde_json::json!({ "url": server.url }));
+
+        let output = fetch().await.unwrap();
+        assert_eq!(
+            output,
             format!(
-                "[DEBUG] TimeTool executed\n[Returning] \n{}\n",
-                now.to_rfc2822()
+                "Title: Tom & Jerry\nURL: {}/\n\nCartoons\n\n[truncated to 8 characters]",
+                server.url
             )
-            .dimmed()
         );
-        Ok(format!("Current system time is: {}", now.to_rfc2822()))
+        assert_eq!(
+            fetch().await.unwrap(),
+            "Failed to fetch URL: HTTP 404 Not Found"
+        );
+    }
+
+    #[test]
+    fn cargo_diagnostics_are_parsed_once_each() {
+        let primary = |file: &str, line: u32, column: u32| {
+            serde_json::json!({
+                "file_name": file, "line_start": line, "column_start": column, "is_primary": true
+            })
+        };
+        let message = |level: &str, text: &str, spans: Value| {
+            serde_json::json!({
+                "reason": "compiler-message",
+                "package_id": "demo 0.1.0",
+                "message": { "level": level, "message": text, "spans": spans, "rendered": text },
+            })
+            .to_string()
+        };
+        let unused = message(
+            "warning",
+            "unused variable: `x`",
+            serde_json::json!([primary("src/lib.rs", 3, 9)]),
+        );
+        let stream = [
+            r#"{"reason":"compiler-artifact","package_id":"dep 1.0.0","target":{"name":"dep"}}"#.to_string(),
+            unused.clone(),
+            message(
+                "error",
+                "mismatched types",
+                serde_json::json!([
+                    { "file_name": "src/main.rs", "line_start": 1, "column_start": 1, "is_primary": false },
+                    primary("src/main.rs", 7, 18),
+                ]),
+            ),
+            // The bin target reports the lib's warning again
+            unused,
+            message("note", "for more information see E0308", serde_json::json!([])),
+            message("error", "aborting due to 1 previous error", serde_json::json!([])),
+            "Compiling demo v0.1.0 (not json)".to_string(),
+            r#"{"reason":"build-finished","success":false}"#.to_string(),
+        ]
+        .join("\n");
+
+        assert_eq!(
+            parse_cargo_diagnostics(&stream),
+            [
+                "warning src/lib.rs:3:9: unused variable: `x`",
+                "error src/main.rs:7:18: mismatched types",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn find_files_matches_names_and_paths_but_skips_build_dirs() {
+        let root = crate::core::data::tests::temp_dir("find-files");
+        for file in [
+            "Cargo.toml",
+            "build.rs",
+            "src/main.rs",
+            "src/core/mod.rs",
+            "src/core/tools.rs",
+            "src/cmd/mod.rs",
+            "tests/mod.rs",
+            "target/debug/build.rs",
+            ".git/hooks/pre-commit.rs",
+        ] {
+            let path = root.join(file);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, "").unwrap();
+        }
+        let find = |tool: FindFilesTool, glob: &str| {
+            let args = serde_json::json!({ "glob": glob, "path": root.display().to_string() });
+            async move { tool.execute_tool(args).await.unwrap() }
+        };
+
+        assert_eq!(
+            find(FindFilesTool::default(), "*.rs").await,
+            [
+                "build.rs",
+                "src/cmd/mod.rs",
+                "src/core/mod.rs",
+                "src/core/tools.rs",
+                "src/main.rs",
+                "tests/mod.rs",
+            ]
+            .join("\n")
+        );
+        assert_eq!(
+            find(FindFilesTool::default(), "src/**/mod.rs").await,
+            "src/cmd/mod.rs\nsrc/core/mod.rs"
+        );
+        assert_eq!(
+            find(FindFilesTool::default(), "*.py").await,
+            "No files match '*.py'"
+        );
+        assert_eq!(
+            find(FindFilesTool { max_results: 2 }, "*.rs").await,
+            "build.rs\nsrc/cmd/mod.rs\n[showing the first 2 matches]"
+        );
+        assert_eq!(
+            find(FindFilesTool { max_results: 2 }, "src/**/mod.rs").await,
+            "src/cmd/mod.rs\nsrc/core/mod.rs"
+        );
     }
 }